@@ -0,0 +1,72 @@
+// Benchmarks for the hot paths, to compare against before optimizing.
+// Run them with `cargo bench -p chip8-emulator`: criterion reports the
+// throughput in cycles (or opcodes) per second.
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+
+use chip8_emulator::*;
+
+const SEED: u64 = 0xC8;
+
+/// A processor with `rom` loaded and a seeded RNG, so every run is the same.
+fn processor_with(rom: &[u8]) -> Chip8Processor {
+    let mut processor = Chip8Builder::new().seed(SEED).build();
+    processor.load_rom(rom).unwrap();
+    processor
+}
+
+/// A whole program: BRIX plays by itself for a while.
+fn bench_cycle_n(c: &mut Criterion) {
+    const CYCLES: usize = 100_000;
+    let rom = include_bytes!("../tests/roms/BRIX");
+
+    let mut group = c.benchmark_group("cycle_n");
+    group.throughput(Throughput::Elements(CYCLES as u64));
+    group.sample_size(10);
+    group.bench_function("brix", |b| {
+        b.iter_batched(
+            || processor_with(rom),
+            |mut processor| {
+                // BRIX does not stop on its own, so any error is a bug
+                for _ in 0..CYCLES / 1000 {
+                    processor.cycle_n(1000).unwrap();
+                    processor.tick_timers();
+                }
+                processor
+            },
+            BatchSize::LargeInput,
+        )
+    });
+    group.finish();
+}
+
+/// Drawing: a 15-row sprite over and over, every other cycle.
+fn bench_draw(c: &mut Criterion) {
+    const CYCLES: usize = 1000;
+    // LD I, 0x000; DRW V0, V0, 15; JP 0x202
+    let rom = [0xA0, 0x00, 0xD0, 0x0F, 0x12, 0x02];
+
+    let mut group = c.benchmark_group("dxyn");
+    group.throughput(Throughput::Elements(CYCLES as u64));
+    group.bench_function("15_rows", |b| {
+        let mut processor = processor_with(&rom);
+        b.iter(|| processor.cycle_n(black_box(CYCLES)).unwrap())
+    });
+    group.finish();
+}
+
+/// Decoding every possible opcode, without running any.
+fn bench_decode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decode");
+    group.throughput(Throughput::Elements(0x10000));
+    group.bench_function("all_opcodes", |b| {
+        b.iter(|| {
+            for opcode in 0..=u16::MAX {
+                black_box(decode(black_box(opcode)));
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_cycle_n, bench_draw, bench_decode);
+criterion_main!(benches);
@@ -0,0 +1,51 @@
+// Feed random programs to the processor, looking for opcodes that panic
+// (bad indexing, overflowing arithmetic...) instead of returning an error.
+//
+// The first two bytes of the input pick the quirks, one bit each, and the
+// rest is the ROM.
+// Run with:
+//
+//     cargo +nightly fuzz run opcodes corpus/opcodes seeds/opcodes
+//
+// The seeds are the ROMs in `roms/`, behind two zero bytes (plain CHIP-8).
+#![no_main]
+
+use chip8_emulator::{Chip8Processor, Quirks};
+use libfuzzer_sys::fuzz_target;
+
+// Enough to get well into a program, while still running fast.
+const FRAMES: usize = 100;
+const CYCLES_PER_FRAME: usize = 10;
+
+fuzz_target!(|data: &[u8]| {
+    let [low, high, rom @ ..] = data else {
+        return;
+    };
+    let flags = u16::from_le_bytes([*low, *high]);
+
+    let mut processor = Chip8Processor::new();
+    processor.set_quirks(Quirks {
+        xo_chip: flags & 0b0000000001 != 0,
+        schip: flags & 0b0000000010 != 0,
+        shift_uses_vy: flags & 0b0000000100 != 0,
+        sprite_wrapping: flags & 0b0000001000 == 0,
+        display_wait: flags & 0b0000010000 != 0,
+        logic_resets_vf: flags & 0b0000100000 != 0,
+        schip_lores_scroll_half: flags & 0b0001000000 != 0,
+        hires_chip8: flags & 0b0010000000 != 0,
+        schip_drw_vf_rows: flags & 0b0100000000 != 0,
+        sprite_address_wrap: flags & 0b1000000000 == 0,
+    });
+
+    if processor.load_rom(rom).is_err() {
+        return;
+    }
+
+    // Errors are a fine way for a bad program to end, panics are not.
+    for _ in 0..FRAMES {
+        if processor.cycle_n(CYCLES_PER_FRAME).is_err() {
+            return;
+        }
+        processor.tick_timers();
+    }
+});
@@ -0,0 +1,333 @@
+// A small two-pass assembler for the CHIP-8 mnemonics in Cowgod's
+// specification (`LD V0, 0x0A`, `JP label`, `DRW V1, V2, 5`, ...).
+//
+// The first pass works out where every label lands in memory, the second
+// turns each line into big-endian opcode bytes, now that every label
+// can be resolved.
+//
+// `disassemble` goes the other way, one opcode at a time.
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::{decode, Instruction, START_ADDRESS};
+
+/// Everything that can go wrong while assembling. Lines count from 1.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum AsmError {
+    /// The mnemonic is not one we know about.
+    UnknownMnemonic { line: usize, mnemonic: String },
+    /// The operands don't fit any form of the instruction.
+    InvalidOperands { line: usize, mnemonic: String },
+    /// A label was used, but never defined.
+    UndefinedLabel { line: usize, label: String },
+    /// The same label was defined twice.
+    DuplicateLabel { line: usize, label: String },
+    /// A number is too large for where it is used (e.g. a byte above 0xFF).
+    ValueOutOfRange { line: usize, value: u16 },
+    /// The program goes past the end of the 64 KiB address space here.
+    ProgramTooLarge { line: usize },
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AsmError::UnknownMnemonic { line, mnemonic } =>
+                write!(f, "line {}: unknown mnemonic '{}'", line, mnemonic),
+            AsmError::InvalidOperands { line, mnemonic } =>
+                write!(f, "line {}: invalid operands for '{}'", line, mnemonic),
+            AsmError::UndefinedLabel { line, label } =>
+                write!(f, "line {}: undefined label '{}'", line, label),
+            AsmError::DuplicateLabel { line, label } =>
+                write!(f, "line {}: label '{}' is already defined", line, label),
+            AsmError::ValueOutOfRange { line, value } =>
+                write!(f, "line {}: value {:#x} is out of range", line, value),
+            AsmError::ProgramTooLarge { line } =>
+                write!(f, "line {}: the program doesn't fit in 64 KiB", line),
+        }
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+/// A single statement, as found by the first pass.
+struct Statement<'a> {
+    line: usize,
+    mnemonic: String,
+    operands: Vec<&'a str>,
+}
+
+/// An operand, as understood by the second pass.
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Operand {
+    V(u8),        // A register, V0 to VF
+    I,            // The I register
+    IndirectI,    // [I], the memory pointed at by I
+    Dt,           // The delay timer
+    St,           // The sound timer
+    K,            // A keypress
+    F,            // A font sprite
+    B,            // BCD storage
+    Value(u16),   // A number, or a resolved label
+}
+
+/// Assemble `source` into a ROM that runs from 0x200.
+pub fn assemble(source: &str) -> Result<Vec<u8>, AsmError> {
+    // First pass: find the labels and the statements
+    let mut labels: HashMap<&str, u16> = HashMap::new();
+    let mut statements = Vec::new();
+    // In usize, so a program that is too long can't wrap around
+    let mut address = START_ADDRESS as usize;
+
+    for (index, raw_line) in source.lines().enumerate() {
+        let line = index + 1;
+        // Everything after a ';' is a comment
+        let mut text = raw_line.split(';').next().unwrap_or("").trim();
+
+        // There might be any number of labels before the statement
+        while let Some((label, rest)) = text.split_once(':') {
+            let label = label.trim();
+            let address = u16::try_from(address).map_err(|_| AsmError::ProgramTooLarge { line })?;
+            if labels.insert(label, address).is_some() {
+                return Err(AsmError::DuplicateLabel { line, label: label.to_string() });
+            }
+            text = rest.trim();
+        }
+
+        if text.is_empty() {
+            continue;
+        }
+
+        let (mnemonic, operands) = match text.split_once(char::is_whitespace) {
+            Some((mnemonic, operands)) => (
+                mnemonic,
+                operands.split(',').map(str::trim).collect::<Vec<_>>()
+            ),
+            None => (text, Vec::new()),
+        };
+        let mnemonic = mnemonic.to_ascii_uppercase();
+
+        address += match mnemonic.as_str() {
+            "DB" => operands.len(),
+            "DW" => 2 * operands.len(),
+            _ => 2,
+        };
+        if address > u16::MAX as usize + 1 {
+            return Err(AsmError::ProgramTooLarge { line });
+        }
+
+        statements.push(Statement { line, mnemonic, operands });
+    }
+
+    // Second pass: emit the bytes
+    let mut rom = Vec::new();
+
+    for statement in statements {
+        let line = statement.line;
+        let operands = statement.operands
+            .iter()
+            .map(|text| parse_operand(text, &labels, line))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        match statement.mnemonic.as_str() {
+            "DB" => {
+                for operand in operands {
+                    rom.push(value(operand, 0xFF, &statement)? as u8);
+                }
+            },
+            "DW" => {
+                for operand in operands {
+                    rom.extend_from_slice(&value(operand, 0xFFFF, &statement)?.to_be_bytes());
+                }
+            },
+            _ => rom.extend_from_slice(&encode(&statement, &operands)?.to_be_bytes()),
+        }
+    }
+
+    Ok(rom)
+}
+
+/// Understand a single operand, resolving labels to their address.
+fn parse_operand(text: &str, labels: &HashMap<&str, u16>, line: usize) -> Result<Operand, AsmError> {
+    let upper = text.to_ascii_uppercase();
+
+    let operand = match upper.as_str() {
+        "I" => Operand::I,
+        "[I]" => Operand::IndirectI,
+        "DT" => Operand::Dt,
+        "ST" => Operand::St,
+        "K" => Operand::K,
+        "F" => Operand::F,
+        "B" => Operand::B,
+        _ => {
+            // Registers are a V followed by a single hex digit
+            if upper.len() == 2 && upper.starts_with('V') {
+                if let Ok(register) = u8::from_str_radix(&upper[1..], 16) {
+                    return Ok(Operand::V(register));
+                }
+            }
+
+            let number = if let Some(hex) = upper.strip_prefix("0X") {
+                u16::from_str_radix(hex, 16).ok()
+            } else if let Some(hex) = upper.strip_prefix('#') {
+                u16::from_str_radix(hex, 16).ok()
+            } else if let Some(bin) = upper.strip_prefix("0B") {
+                u16::from_str_radix(bin, 2).ok()
+            } else {
+                upper.parse::<u16>().ok()
+            };
+
+            match number.or_else(|| labels.get(text).copied()) {
+                Some(value) => Operand::Value(value),
+                None => return Err(AsmError::UndefinedLabel { line, label: text.to_string() }),
+            }
+        }
+    };
+
+    Ok(operand)
+}
+
+/// Get a plain number out of an operand, checking it is at most `max`.
+fn value(operand: Operand, max: u16, statement: &Statement) -> Result<u16, AsmError> {
+    match operand {
+        Operand::Value(value) if value <= max => Ok(value),
+        Operand::Value(value) => Err(AsmError::ValueOutOfRange { line: statement.line, value }),
+        _ => Err(AsmError::InvalidOperands {
+            line: statement.line,
+            mnemonic: statement.mnemonic.clone()
+        }),
+    }
+}
+
+/// Turn a single instruction into its opcode.
+fn encode(statement: &Statement, operands: &[Operand]) -> Result<u16, AsmError> {
+    use Operand::*;
+
+    // Shorthands to range-check the numbers we put in the opcode
+    let addr = |operand| value(operand, 0xFFF, statement);
+    let byte = |operand| value(operand, 0xFF, statement);
+    let nibble = |operand| value(operand, 0xF, statement);
+
+    let opcode = match (statement.mnemonic.as_str(), operands) {
+        ("CLS", []) => 0x00E0,
+        ("RET", []) => 0x00EE,
+        ("SYS", [a]) => addr(*a)?,
+        ("JP", [V(0), a]) => 0xB000 | addr(*a)?,
+        ("JP", [a]) => 0x1000 | addr(*a)?,
+        ("CALL", [a]) => 0x2000 | addr(*a)?,
+        ("SE", [V(x), V(y)]) => 0x5000 | xy(*x, *y),
+        ("SE", [V(x), b]) => 0x3000 | x_(*x) | byte(*b)?,
+        ("SNE", [V(x), V(y)]) => 0x9000 | xy(*x, *y),
+        ("SNE", [V(x), b]) => 0x4000 | x_(*x) | byte(*b)?,
+        ("LD", [V(x), V(y)]) => 0x8000 | xy(*x, *y),
+        ("LD", [V(x), Dt]) => 0xF007 | x_(*x),
+        ("LD", [V(x), K]) => 0xF00A | x_(*x),
+        ("LD", [V(x), IndirectI]) => 0xF065 | x_(*x),
+        ("LD", [V(x), b]) => 0x6000 | x_(*x) | byte(*b)?,
+        ("LD", [I, a]) => 0xA000 | addr(*a)?,
+        ("LD", [Dt, V(x)]) => 0xF015 | x_(*x),
+        ("LD", [St, V(x)]) => 0xF018 | x_(*x),
+        ("LD", [F, V(x)]) => 0xF029 | x_(*x),
+        ("LD", [B, V(x)]) => 0xF033 | x_(*x),
+        ("LD", [IndirectI, V(x)]) => 0xF055 | x_(*x),
+        ("ADD", [I, V(x)]) => 0xF01E | x_(*x),
+        ("ADD", [V(x), V(y)]) => 0x8004 | xy(*x, *y),
+        ("ADD", [V(x), b]) => 0x7000 | x_(*x) | byte(*b)?,
+        ("OR", [V(x), V(y)]) => 0x8001 | xy(*x, *y),
+        ("AND", [V(x), V(y)]) => 0x8002 | xy(*x, *y),
+        ("XOR", [V(x), V(y)]) => 0x8003 | xy(*x, *y),
+        ("SUB", [V(x), V(y)]) => 0x8005 | xy(*x, *y),
+        ("SHR", [V(x)]) => 0x8006 | x_(*x),
+        ("SHR", [V(x), V(y)]) => 0x8006 | xy(*x, *y),
+        ("SUBN", [V(x), V(y)]) => 0x8007 | xy(*x, *y),
+        ("SHL", [V(x)]) => 0x800E | x_(*x),
+        ("SHL", [V(x), V(y)]) => 0x800E | xy(*x, *y),
+        ("RND", [V(x), b]) => 0xC000 | x_(*x) | byte(*b)?,
+        ("DRW", [V(x), V(y), n]) => 0xD000 | xy(*x, *y) | nibble(*n)?,
+        ("SKP", [V(x)]) => 0xE09E | x_(*x),
+        ("SKNP", [V(x)]) => 0xE0A1 | x_(*x),
+        (mnemonic, _) if is_mnemonic(mnemonic) => {
+            return Err(AsmError::InvalidOperands {
+                line: statement.line,
+                mnemonic: statement.mnemonic.clone()
+            });
+        },
+        _ => {
+            return Err(AsmError::UnknownMnemonic {
+                line: statement.line,
+                mnemonic: statement.mnemonic.clone()
+            });
+        },
+    };
+
+    Ok(opcode)
+}
+
+/// Turn an opcode back into assembly, in the syntax `assemble` reads.
+///
+/// Opcodes with no mnemonic (including the SUPER-CHIP and XO-CHIP ones)
+/// come out as a `DW`, so the output always assembles back to `opcode`.
+pub fn disassemble(opcode: u16) -> String {
+    use Instruction::*;
+
+    match decode(opcode) {
+        Cls => "CLS".to_string(),
+        Ret => "RET".to_string(),
+        // Without their extension, 00FE and 00FF are plain SYS calls
+        Sys(nnn) => format!("SYS {:#05x}", nnn),
+        Low | High | Exit | ScrollDown(_) | ScrollUp(_) | ScrollRight | ScrollLeft => {
+            format!("SYS {:#05x}", opcode & 0xFFF)
+        },
+        Jp(nnn) => format!("JP {:#05x}", nnn),
+        Call(nnn) => format!("CALL {:#05x}", nnn),
+        SeVxByte(x, nn) => format!("SE V{:X}, {:#04x}", x, nn),
+        SneVxByte(x, nn) => format!("SNE V{:X}, {:#04x}", x, nn),
+        SeVxVy(x, y) => format!("SE V{:X}, V{:X}", x, y),
+        LdVxByte(x, nn) => format!("LD V{:X}, {:#04x}", x, nn),
+        AddVxByte(x, nn) => format!("ADD V{:X}, {:#04x}", x, nn),
+        LdVxVy(x, y) => format!("LD V{:X}, V{:X}", x, y),
+        Or(x, y) => format!("OR V{:X}, V{:X}", x, y),
+        And(x, y) => format!("AND V{:X}, V{:X}", x, y),
+        Xor(x, y) => format!("XOR V{:X}, V{:X}", x, y),
+        AddVxVy(x, y) => format!("ADD V{:X}, V{:X}", x, y),
+        Sub(x, y) => format!("SUB V{:X}, V{:X}", x, y),
+        Shr(x, y) => format!("SHR V{:X}, V{:X}", x, y),
+        Subn(x, y) => format!("SUBN V{:X}, V{:X}", x, y),
+        Shl(x, y) => format!("SHL V{:X}, V{:X}", x, y),
+        SneVxVy(x, y) => format!("SNE V{:X}, V{:X}", x, y),
+        LdI(nnn) => format!("LD I, {:#05x}", nnn),
+        JpV0(nnn) => format!("JP V0, {:#05x}", nnn),
+        Rnd(x, nn) => format!("RND V{:X}, {:#04x}", x, nn),
+        Drw(x, y, n) => format!("DRW V{:X}, V{:X}, {}", x, y, n),
+        Skp(x) => format!("SKP V{:X}", x),
+        Sknp(x) => format!("SKNP V{:X}", x),
+        LdVxDt(x) => format!("LD V{:X}, DT", x),
+        LdVxK(x) => format!("LD V{:X}, K", x),
+        LdDtVx(x) => format!("LD DT, V{:X}", x),
+        LdStVx(x) => format!("LD ST, V{:X}", x),
+        AddIVx(x) => format!("ADD I, V{:X}", x),
+        LdFVx(x) => format!("LD F, V{:X}", x),
+        LdBVx(x) => format!("LD B, V{:X}", x),
+        LdIVx(x) => format!("LD [I], V{:X}", x),
+        LdVxI(x) => format!("LD V{:X}, [I]", x),
+        _ => format!("DW {:#06x}", opcode),
+    }
+}
+
+/// Put register X in its place in the opcode.
+fn x_(x: u8) -> u16 {
+    (x as u16) << 8
+}
+
+/// Put registers X and Y in their place in the opcode.
+fn xy(x: u8, y: u8) -> u16 {
+    x_(x) | (y as u16) << 4
+}
+
+fn is_mnemonic(mnemonic: &str) -> bool {
+    matches!(
+        mnemonic,
+        "CLS" | "RET" | "SYS" | "JP" | "CALL" | "SE" | "SNE" | "LD" | "ADD"
+            | "OR" | "AND" | "XOR" | "SUB" | "SHR" | "SUBN" | "SHL" | "RND"
+            | "DRW" | "SKP" | "SKNP"
+    )
+}
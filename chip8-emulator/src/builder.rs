@@ -0,0 +1,66 @@
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+
+use crate::{Chip8Processor, Quirks, TimerMode};
+
+/// Configure a `Chip8Processor` before building it.
+///
+/// `Chip8Processor::new()` is still the way to get a plain CHIP-8.
+#[derive(Clone, Debug, Default)]
+pub struct Chip8Builder {
+    quirks: Quirks,
+    seed: Option<u64>,
+    hires: bool,
+    display_mirror: bool,
+    timer_mode: TimerMode,
+}
+
+impl Chip8Builder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Run with these quirks.
+    pub fn quirks(mut self, quirks: Quirks) -> Self {
+        self.quirks = quirks;
+        self
+    }
+
+    /// Seed the random number generator used by `CXNN`, so runs repeat.
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Start in the 128x64 high resolution mode.
+    pub fn hires(mut self, hires: bool) -> Self {
+        self.hires = hires;
+        self
+    }
+
+    /// Mirror the screen in RAM, see `Chip8Processor::set_display_mirror`.
+    pub fn display_mirror(mut self, display_mirror: bool) -> Self {
+        self.display_mirror = display_mirror;
+        self
+    }
+
+    /// Choose what makes the timers count down.
+    pub fn timer_mode(mut self, timer_mode: TimerMode) -> Self {
+        self.timer_mode = timer_mode;
+        self
+    }
+
+    pub fn build(self) -> Chip8Processor {
+        let mut processor = Chip8Processor::new();
+
+        processor.set_quirks(self.quirks);
+        processor.rng = self.seed.map(StdRng::seed_from_u64);
+        if self.hires {
+            processor.set_hires(true);
+        }
+        processor.set_display_mirror(self.display_mirror);
+        processor.set_timer_mode(self.timer_mode);
+
+        processor
+    }
+}
@@ -0,0 +1,159 @@
+// The display planes, packed one row per word.
+//
+// Each row is a `u128`, with the leftmost pixel in the most significant
+// bit, so a sprite row is drawn with a shift and a XOR, and clearing the
+// screen is a handful of word writes. The 64x32 screen only uses the top
+// 64 bits of each row.
+//
+// Front-ends still want one bool per pixel, so we keep that view too, and
+// patch it with the bits every operation actually changes.
+
+/// One display plane, `width` (at most 128) by `height` pixels.
+#[derive(PartialEq, Debug)]
+pub(crate) struct Bitplane {
+    width: usize,
+    rows: Vec<u128>, // One word per row, leftmost pixel first
+    pixels: Vec<bool>, // The same pixels, one bool each, row after row
+}
+
+impl Bitplane {
+    pub(crate) fn new(width: usize, height: usize) -> Self {
+        debug_assert!(width <= 128, "rows are 128 bits wide");
+        Self {
+            width,
+            rows: vec![0; height],
+            pixels: vec![false; width * height],
+        }
+    }
+
+    /// A plane holding `pixels`, `width` to a row.
+    #[cfg(test)]
+    pub(crate) fn from_pixels(width: usize, pixels: &[bool]) -> Self {
+        let mut plane = Self::new(width, pixels.len() / width);
+        for (index, on) in pixels.iter().enumerate() {
+            plane.set(index % width, index / width, *on);
+        }
+        plane
+    }
+
+    /// A plane `width` wide with these packed `rows`, as `row` returns them.
+    pub(crate) fn from_rows(width: usize, rows: Vec<u128>) -> Self {
+        let mut plane = Self::new(width, rows.len());
+        for (y, row) in rows.into_iter().enumerate() {
+            plane.xor_row(y, row);
+        }
+        plane
+    }
+
+    pub(crate) fn width(&self) -> usize {
+        self.width
+    }
+
+    pub(crate) fn height(&self) -> usize {
+        self.rows.len()
+    }
+
+    /// Every pixel, row after row.
+    pub(crate) fn pixels(&self) -> &[bool] {
+        &self.pixels
+    }
+
+    /// The pixels of row `y`, packed from the most significant bit.
+    pub(crate) fn row(&self, y: usize) -> u128 {
+        self.rows[y]
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.rows.fill(0);
+        self.pixels.fill(false);
+    }
+
+    /// The bits of the columns that are on the screen.
+    pub(crate) fn row_mask(&self) -> u128 {
+        !0 << (128 - self.width)
+    }
+
+    pub(crate) fn get(&self, x: usize, y: usize) -> bool {
+        self.pixels[x + y * self.width]
+    }
+
+    pub(crate) fn set(&mut self, x: usize, y: usize, on: bool) {
+        let bit = 1 << (127 - x);
+        if on {
+            self.rows[y] |= bit;
+        } else {
+            self.rows[y] &= !bit;
+        }
+        self.pixels[x + y * self.width] = on;
+    }
+
+    /// Flip the pixels of row `y` set in `bits`, and return the ones that
+    /// were on before (the collisions).
+    pub(crate) fn xor_row(&mut self, y: usize, bits: u128) -> u128 {
+        let bits = bits & self.row_mask();
+        let collided = self.rows[y] & bits;
+        self.rows[y] ^= bits;
+
+        let start = y * self.width;
+        for x in columns(bits) {
+            self.pixels[start + x] ^= true;
+        }
+
+        collided
+    }
+
+    /// Move every pixel `dx` columns right and `dy` rows down (left and up
+    /// when negative). What goes past an edge is lost, and what comes in
+    /// from the other side is off.
+    pub(crate) fn scroll(&mut self, dx: isize, dy: isize) {
+        let height = self.rows.len() as isize;
+        let mask = self.row_mask();
+        let shift = |row: u128| {
+            let distance = dx.unsigned_abs() as u32;
+            let row = if dx >= 0 { row.checked_shr(distance) } else { row.checked_shl(distance) };
+            row.unwrap_or(0) & mask
+        };
+
+        let rows: Vec<u128> = (0..height)
+            .map(|y| match y - dy {
+                from @ 0.. if from < height => shift(self.rows[from as usize]),
+                _ => 0,
+            })
+            .collect();
+
+        // Most pixels move, so the bools are rebuilt from scratch
+        for (y, row) in rows.iter().enumerate() {
+            let start = y * self.width;
+            for x in 0..self.width {
+                self.pixels[start + x] = row & (1 << (127 - x)) != 0;
+            }
+        }
+        self.rows = rows;
+    }
+}
+
+// Deriving `Clone` would allocate in `clone_from` too, and restoring
+// snapshots over and over has to reuse the buffers.
+impl Clone for Bitplane {
+    fn clone(&self) -> Self {
+        Self { width: self.width, rows: self.rows.clone(), pixels: self.pixels.clone() }
+    }
+
+    fn clone_from(&mut self, source: &Self) {
+        self.width = source.width;
+        self.rows.clone_from(&source.rows);
+        self.pixels.clone_from(&source.pixels);
+    }
+}
+
+/// The columns of the bits set in a packed row, from the left.
+pub(crate) fn columns(mut bits: u128) -> impl Iterator<Item = usize> {
+    std::iter::from_fn(move || {
+        if bits == 0 {
+            return None;
+        }
+        let x = bits.leading_zeros() as usize;
+        bits &= !(1 << (127 - x));
+        Some(x)
+    })
+}
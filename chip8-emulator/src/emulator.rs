@@ -0,0 +1,120 @@
+use std::time::Duration;
+
+use crate::{Chip8Error, Chip8Processor};
+
+/// What a front-end has to do after a frame.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct FrameOutput {
+    /// Whether the beep should be playing.
+    pub beeping: bool,
+    /// Whether the screen has to be drawn again.
+    pub display_changed: bool,
+}
+
+// A billion billionths of a cycle make a cycle, see `CycleScheduler`.
+const NANOCYCLES: i128 = 1_000_000_000;
+
+/// Works out how many cycles to run in a frame to keep a target clock
+/// speed, whatever the frame rate.
+///
+/// Frames rarely last a whole number of cycles, so the fraction of a
+/// cycle left over is carried to the next frame. Cycles are run when they
+/// are at least half due, and the carry can go negative to pay them back:
+/// that way a 60th of a second (which a `Duration` rounds down to the
+/// nanosecond) is still 10 cycles at 600Hz, and the long-run speed is
+/// exactly the target.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct CycleScheduler {
+    clock_hz: u32,
+    // Cycles due but not run yet, in billionths of a cycle, between minus
+    // and plus half a cycle
+    carry: i128,
+}
+
+impl CycleScheduler {
+    /// Schedule `clock_hz` cycles per second.
+    pub fn new(clock_hz: u32) -> Self {
+        Self { clock_hz, carry: 0 }
+    }
+
+    pub fn clock_hz(&self) -> u32 {
+        self.clock_hz
+    }
+
+    /// Change the speed. The carry is kept, so nothing is lost or run
+    /// twice. 0 pauses.
+    pub fn set_clock_hz(&mut self, clock_hz: u32) {
+        self.clock_hz = clock_hz;
+    }
+
+    /// How many cycles to run for a frame that lasted `dt`.
+    pub fn cycles_for(&mut self, dt: Duration) -> usize {
+        // A nanosecond at N Hz is N billionths of a cycle
+        let due = self.carry + dt.as_nanos() as i128 * self.clock_hz as i128;
+        let cycles = (due + NANOCYCLES / 2).div_euclid(NANOCYCLES);
+        self.carry = due - cycles * NANOCYCLES;
+
+        cycles as usize
+    }
+}
+
+/// A `Chip8Processor` running at a fixed clock speed, so front-ends only
+/// have to say how much time passed, and not how many cycles to run.
+#[derive(Clone, Debug)]
+pub struct Emulator {
+    processor: Chip8Processor,
+    scheduler: CycleScheduler, // How many cycles each frame gets
+    // The display as of the end of the last frame
+    last_display: Vec<bool>,
+}
+
+impl Emulator {
+    /// The speed most CHIP-8 programs expect, about 10 instructions a frame.
+    pub const DEFAULT_CLOCK_HZ: u32 = 600;
+
+    /// Run `processor` at `clock_hz` instructions per second.
+    pub fn new(processor: Chip8Processor, clock_hz: u32) -> Self {
+        let last_display = processor.get_display().to_vec();
+
+        Self { processor, scheduler: CycleScheduler::new(clock_hz), last_display }
+    }
+
+    pub fn processor(&self) -> &Chip8Processor {
+        &self.processor
+    }
+
+    pub fn processor_mut(&mut self) -> &mut Chip8Processor {
+        &mut self.processor
+    }
+
+    pub fn clock_hz(&self) -> u32 {
+        self.scheduler.clock_hz()
+    }
+
+    /// Change the speed, e.g. for a fast-forward key. 0 pauses.
+    pub fn set_clock_hz(&mut self, clock_hz: u32) {
+        self.scheduler.set_clock_hz(clock_hz);
+    }
+
+    /// Let `dt` pass with the keys in `input` held: run as many cycles as
+    /// fit in it at the clock speed, and tick the timers at 60Hz. Time left
+    /// over is carried to the next frame, so the speed is right whatever
+    /// the frame rate, see `CycleScheduler`.
+    pub fn run_frame(&mut self, dt: Duration, input: [bool; 16]) -> Result<FrameOutput, Chip8Error> {
+        self.processor.set_keypad(input);
+
+        let cycles = self.scheduler.cycles_for(dt);
+        self.processor.cycle_n(cycles)?;
+
+        self.processor.advance_timers(dt);
+        self.processor.end_frame();
+
+        let display_changed = self.processor.get_display() != self.last_display.as_slice();
+        if display_changed {
+            self.last_display.clear();
+            self.last_display.extend_from_slice(self.processor.get_display());
+        }
+
+        Ok(FrameOutput { beeping: self.processor.is_beeping(), display_changed })
+    }
+}
@@ -0,0 +1,64 @@
+use std::fmt;
+use std::io;
+
+use crate::asm::AsmError;
+
+/// Everything that can go wrong while loading or running a program.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum Chip8Error {
+    /// The ROM does not fit in the RAM at the requested address.
+    RomTooLarge { size: usize, max: usize },
+    /// A subroutine was called with all 16 stack slots already in use.
+    StackOverflow,
+    /// A return was executed outside of any subroutine.
+    StackUnderflow,
+    /// An address past the end of the RAM was accessed.
+    OutOfBoundsMemory(u16),
+    /// The opcode is not an instruction, usually because the program ran
+    /// into its own data.
+    UnknownOpcode(u16),
+    /// The ROM file could not be read.
+    Io { kind: io::ErrorKind, message: String },
+    /// A save state could not be loaded, because it is cut short or is not
+    /// a save state at all.
+    InvalidState(String),
+    /// A save state was written by a newer version of the emulator, which
+    /// this one can't read.
+    UnsupportedStateVersion(u8),
+    /// The source given to `load_asm` doesn't assemble.
+    Asm(AsmError),
+    /// The program counter is odd, so it is no longer on an instruction.
+    /// Only reported with `set_strict_alignment`.
+    MisalignedPc,
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Chip8Error::RomTooLarge { size, max } => write!(
+                f,
+                "ROM is too large: {} bytes, but only {} bytes are available",
+                size, max
+            ),
+            Chip8Error::StackOverflow => write!(f, "stack overflow"),
+            Chip8Error::StackUnderflow => write!(f, "stack underflow"),
+            Chip8Error::OutOfBoundsMemory(address) =>
+                write!(f, "address {:#06x} is outside of the RAM", address),
+            Chip8Error::UnknownOpcode(opcode) => write!(f, "unknown opcode {:#06x}", opcode),
+            Chip8Error::Io { message, .. } => write!(f, "unable to read ROM: {}", message),
+            Chip8Error::InvalidState(message) => write!(f, "invalid save state: {}", message),
+            Chip8Error::UnsupportedStateVersion(version) =>
+                write!(f, "save state version {} is newer than this emulator", version),
+            Chip8Error::Asm(error) => write!(f, "unable to assemble: {}", error),
+            Chip8Error::MisalignedPc => write!(f, "the program counter is on an odd address"),
+        }
+    }
+}
+
+impl std::error::Error for Chip8Error {}
+
+impl From<AsmError> for Chip8Error {
+    fn from(error: AsmError) -> Self {
+        Chip8Error::Asm(error)
+    }
+}
@@ -0,0 +1,583 @@
+// Running decoded instructions.
+//
+// `execute` picks a handler by the high nibble of the opcode, from
+// `DISPATCH`, and every handler only knows about its own family of
+// opcodes. An opcode that ends up in the wrong handler is an
+// `UnknownOpcode`, so a mislabeled or duplicated arm shows up in the
+// dispatch tests instead of running the wrong instruction.
+use rand::{random, Rng};
+
+use crate::{
+    decode, Chip8Error, Chip8Processor, Instruction, BIG_FONT_ADDRESS, HIRES_CHIP8_PATCH_ADDRESS,
+    HIRES_CHIP8_START_ADDRESS, START_ADDRESS,
+};
+
+/// Runs one family of opcodes.
+pub(crate) type Handler = fn(&mut Chip8Processor, u16) -> Result<(), Chip8Error>;
+
+/// The handler for each high nibble of the opcode.
+pub(crate) const DISPATCH: [Handler; 16] = [
+    Chip8Processor::exec_system, // 0
+    Chip8Processor::exec_flow, // 1
+    Chip8Processor::exec_flow, // 2
+    Chip8Processor::exec_skip, // 3
+    Chip8Processor::exec_skip, // 4
+    Chip8Processor::exec_skip, // 5
+    Chip8Processor::exec_load, // 6
+    Chip8Processor::exec_load, // 7
+    Chip8Processor::exec_alu, // 8
+    Chip8Processor::exec_skip, // 9
+    Chip8Processor::exec_load, // A
+    Chip8Processor::exec_flow, // B
+    Chip8Processor::exec_load, // C
+    Chip8Processor::exec_draw, // D
+    Chip8Processor::exec_keys, // E
+    Chip8Processor::exec_misc, // F
+];
+
+impl Chip8Processor {
+    /// Execute the input opcode.
+    pub(crate) fn execute(&mut self, opcode: u16) -> Result<(), Chip8Error> {
+        DISPATCH[(opcode >> 12) as usize](self, opcode)
+    }
+
+    /// 0NNN: the screen, returns, and the SUPER-CHIP resolution switches and exit.
+    pub(crate) fn exec_system(&mut self, opcode: u16) -> Result<(), Chip8Error> {
+        match decode(opcode) {
+            // 0. 0000 - NOP - Do nothing
+            Instruction::Sys(0) => (),
+
+            // 0230 - Clear the 64x64 screen (HIRES CHIP-8)
+            // A routine of the HIRES interpreter patch, which we run
+            // ourselves.
+            Instruction::Sys(0x230) if self.quirks.hires_chip8 => {
+                self.display.clear();
+                self.sync_display_mirror();
+            },
+
+            // 1. 00E0 - CLS - Clear Display
+            // Only the selected planes are cleared.
+            Instruction::Cls => {
+                if self.plane_mask & 0b01 != 0 {
+                    self.display.clear();
+                }
+                if self.plane_mask & 0b10 != 0 {
+                    self.second_plane.clear();
+                }
+                self.sync_display_mirror();
+            },
+
+            // 00FE - Switch to the 64x32 low resolution mode (SCHIP)
+            Instruction::Low if self.quirks.schip => {
+                self.set_hires(false);
+            },
+
+            // 00FF - Switch to the 128x64 high resolution mode (SCHIP)
+            Instruction::High if self.quirks.schip => {
+                self.set_hires(true);
+            },
+
+            // 00FD - Exit the interpreter (SCHIP)
+            // Nothing runs anymore after this, see `is_halted`.
+            Instruction::Exit if self.quirks.schip => {
+                self.halted = true;
+            },
+
+            // 00CN - Scroll the selected planes down N rows (SCHIP)
+            Instruction::ScrollDown(n) if self.quirks.schip => {
+                let rows = self.scroll_distance(n);
+                self.scroll_planes(0, rows);
+            },
+
+            // 00DN - Scroll the selected planes up N rows (XO-CHIP)
+            Instruction::ScrollUp(n) if self.quirks.xo_chip => {
+                let rows = self.scroll_distance(n);
+                self.scroll_planes(0, -rows);
+            },
+
+            // 00FB - Scroll the selected planes right 4 columns (SCHIP)
+            Instruction::ScrollRight if self.quirks.schip => {
+                let columns = self.scroll_distance(4);
+                self.scroll_planes(columns, 0);
+            },
+
+            // 00FC - Scroll the selected planes left 4 columns (SCHIP)
+            Instruction::ScrollLeft if self.quirks.schip => {
+                let columns = self.scroll_distance(4);
+                self.scroll_planes(-columns, 0);
+            },
+
+            // 2. 00EE - Return from subroutine
+            Instruction::Ret => {
+                let return_value = self.pop()?;
+                self.program_counter = return_value;
+            },
+
+            // 0NNN - SYS NNN - Call a machine code routine at NNN
+            // Only the original COSMAC VIP could run those, and interpreters
+            // have always ignored them, so do we. Without SCHIP, 00FE,
+            // 00FF, 00FD and the scrolls are just more of them.
+            Instruction::Sys(_) | Instruction::Low | Instruction::High | Instruction::Exit
+            | Instruction::ScrollDown(_) | Instruction::ScrollUp(_) | Instruction::ScrollRight | Instruction::ScrollLeft => (),
+
+            // Not one of ours, or an extension we are not running
+            _ => return Err(Chip8Error::UnknownOpcode(opcode)),
+        }
+
+        Ok(())
+    }
+
+    /// 1NNN, 2NNN and BNNN: jumps and calls.
+    pub(crate) fn exec_flow(&mut self, opcode: u16) -> Result<(), Chip8Error> {
+        match decode(opcode) {
+            // 1260 at 0x200 - Start a HIRES CHIP-8 program
+            // The ROM jumps into the interpreter patch it carries, which
+            // switches to the 64x64 screen and runs the program from 0x2C0.
+            // The patch is COSMAC VIP machine code, so we do what it does
+            // instead of running it.
+            Instruction::Jp(HIRES_CHIP8_PATCH_ADDRESS)
+                if self.quirks.hires_chip8 && self.program_counter == START_ADDRESS + 2 => {
+                self.set_hires_chip8();
+                self.program_counter = HIRES_CHIP8_START_ADDRESS;
+            },
+
+            // 3. 1NNN - JMP NNN - Jump to location NNN
+            Instruction::Jp(nnn) => {
+                self.program_counter = nnn;
+            },
+
+            // 4. 2NNN - CALL NNN - Call Subroutine @NNN
+            Instruction::Call(nnn) => {
+                self.push(self.program_counter)?; // This works because u16 is Copy
+                self.program_counter = nnn;
+            },
+
+            // 19. BNNN - Jump to address V0 + NNN
+            Instruction::JpV0(nnn) => {
+                // V0 + NNN can go past the end of the RAM, so we wrap around
+                // the address space, like the 12-bit address bus would.
+                let target = (self.registers[0] as u16).wrapping_add(nnn);
+                self.program_counter = (target as usize % self.ram.len()) as u16;
+            },
+
+            // Not one of ours, or an extension we are not running
+            _ => return Err(Chip8Error::UnknownOpcode(opcode)),
+        }
+
+        Ok(())
+    }
+
+    /// 3XNN, 4XNN, 5XY0 and 9XY0: the conditional skips.
+    pub(crate) fn exec_skip(&mut self, opcode: u16) -> Result<(), Chip8Error> {
+        match decode(opcode) {
+            // 5. 3XNN - SKIP VX == NN - Skip ahead if
+            Instruction::SeVxByte(x, nn) => {
+                if self.registers[x as usize] == nn {
+                    self.skip();
+                }
+            },
+
+            // 6. 4XNN - SKIP VX != NN - Skip ahead if not
+            Instruction::SneVxByte(x, nn) => {
+                if self.registers[x as usize] != nn {
+                    self.skip();
+                }
+            },
+
+            // 7. 5XY0 - SKIP VX == VY - Skip ahead if X == Y
+            Instruction::SeVxVy(x, y) => {
+                if self.registers[x as usize] == self.registers[y as usize] {
+                    self.skip();
+                }
+            },
+
+            // 17. 9XY0 - Skip if VX != VY
+            Instruction::SneVxVy(x, y) => {
+                if self.registers[x as usize] != self.registers[y as usize] {
+                    self.skip();
+                }
+            },
+
+            // Not one of ours, or an extension we are not running
+            _ => return Err(Chip8Error::UnknownOpcode(opcode)),
+        }
+
+        Ok(())
+    }
+
+    /// 6XNN, 7XNN, ANNN and CXNN: loading immediate values.
+    pub(crate) fn exec_load(&mut self, opcode: u16) -> Result<(), Chip8Error> {
+        match decode(opcode) {
+            
+            // 8. 6XNN - VX = NN - Set register X to NN
+            Instruction::LdVxByte(x, nn) => {
+                self.registers[x as usize] = nn; 
+            },
+
+            // 9. 7XNN - VX + NN
+            Instruction::AddVxByte(x, nn) => {
+                // Rust could overflow here, but Chip8 expects the numbers to wrap.
+                // There is no carry: VF is left alone, on every platform.
+                self.registers[x as usize] = self.registers[x as usize].wrapping_add(nn); 
+            },
+
+            // 18. ANNN - Set I to 0xNNN
+            Instruction::LdI(nnn) => {
+                self.i_register = nnn;
+            },
+
+            // 20. CXNN - Make a random number and AND it in VX
+            Instruction::Rnd(x, nn) => {
+                let random_num: u8 = match (self.rng_constant, &mut self.rng) {
+                    (Some(constant), _) => constant,
+                    (None, Some(rng)) => rng.gen(),
+                    (None, None) => random(),
+                };
+
+                self.registers[x as usize] = random_num & nn; 
+            },
+
+            // Not one of ours, or an extension we are not running
+            _ => return Err(Chip8Error::UnknownOpcode(opcode)),
+        }
+
+        Ok(())
+    }
+
+    /// 8XYN: arithmetic and logic between two registers.
+    pub(crate) fn exec_alu(&mut self, opcode: u16) -> Result<(), Chip8Error> {
+        match decode(opcode) {
+            // 10. 8XY0 - VX = VY
+            Instruction::LdVxVy(x, y) => {
+                self.registers[x as usize] = self.registers[y as usize];
+            },
+
+            // 11. 8XY1, 8XY2, 8XY3 - VX _ VY = VX, _ is OR, AND, XOR
+            logic @ (Instruction::Or(x, y) | Instruction::And(x, y) | Instruction::Xor(x, y)) => {
+                let (x, y) = (x as usize, y as usize);
+                match logic {
+                    Instruction::Or(..) => self.registers[x] |= self.registers[y],
+                    Instruction::And(..) => self.registers[x] &= self.registers[y],
+                    _ => self.registers[x] ^= self.registers[y],
+                }
+
+                if self.quirks.logic_resets_vf {
+                    self.registers[0xF] = 0;
+                }
+            },
+
+            // 12. 8XY4 - ADD VX + VY - If VX overflows, set VF to 1
+            Instruction::AddVxVy(x, y) => {
+                let (x, y) = (x as usize, y as usize);
+                let (result, overflow) =
+                    self.registers[x]
+                    .overflowing_add(self.registers[y]);
+
+                let overflow = if overflow {1} else {0};
+
+                // The flag goes last, so it wins if VF is the target
+                self.registers[x] = result;
+                self.registers[0xF] = overflow;
+            },
+
+            // 13. 8XY5 - SUB VX - VY
+            Instruction::Sub(x, y) => {
+                let (x, y) = (x as usize, y as usize);
+                let (result, underflow) =
+                    self.registers[x]
+                    .overflowing_sub(self.registers[y]);
+                
+                let underflow = if underflow {0} else {1};
+
+                self.registers[x] = result;
+                self.registers[0xF] = underflow;
+            },
+
+            // 14. 8XY6 - VX >>= 1 - Bitwise shift VX by 1, and store the dropped bit in VF
+            Instruction::Shr(x, y) => {
+                let x = x as usize;
+
+                // On the COSMAC VIP, VY is shifted into VX
+                if self.quirks.shift_uses_vy {
+                    self.registers[x] = self.registers[y as usize];
+                }
+                
+                // The 1 here is inferred to be an u8, since it cannot be anything else.
+                // 1 as u8 is 0000 0001, so we get the last digit
+                let dropped = self.registers[x] & 1; 
+
+                self.registers[x] >>= 1;
+                self.registers[0xF] = dropped;
+            },
+
+            // 15. 8XY7 - VX = VY - VX - If this borrows, clear VF
+            Instruction::Subn(x, y) => {
+                let (x, y) = (x as usize, y as usize);
+                let (result, underflow) =
+                    self.registers[y]
+                    .overflowing_sub(self.registers[x]);
+                
+                let underflow = if underflow {0} else {1};
+
+                self.registers[x] = result;
+                self.registers[0xF] = underflow;
+            },
+
+            // 16. 8XYE - VX <<= 1 - Bitwise shift VX by 1, and store the dropped bit in VF
+            Instruction::Shl(x, y) => {
+                let x = x as usize;
+
+                if self.quirks.shift_uses_vy {
+                    self.registers[x] = self.registers[y as usize];
+                }
+                
+                // Same as above, but we move the first digit to the last position,
+                // so we don't have to write 1000 0000 (2^8 = 256)
+                let dropped = (self.registers[x] >> 7) & 1;
+
+                self.registers[x] <<= 1;
+                self.registers[0xF] = dropped;
+            },
+
+            // Not one of ours, or an extension we are not running
+            _ => return Err(Chip8Error::UnknownOpcode(opcode)),
+        }
+
+        Ok(())
+    }
+
+    /// DXYN: drawing sprites.
+    pub(crate) fn exec_draw(&mut self, opcode: u16) -> Result<(), Chip8Error> {
+        match decode(opcode) {
+            // 21. DXYN - Draw n bytes from I at coordinates (VX, VY)
+            // Set VF if any pixels were flipped by this action.
+            // DXY0 draws a 16x16 sprite instead, two bytes per row (SCHIP).
+            Instruction::Drw(x, y, n) => {
+                let coord_x = self.registers[x as usize] as u16;
+                let coord_y = self.registers[y as usize] as u16;
+
+                let (rows, sprite_width) = if n == 0 && (self.quirks.schip || self.quirks.xo_chip) {
+                    (16, 16)
+                } else {
+                    (n as u16, 8)
+                };
+
+                // Every selected plane takes a sprite. Rows past the end of
+                // the RAM come from its start again, unless we were asked
+                // to treat them as out of bounds.
+                let planes = (self.plane_mask & 0b11).count_ones() as usize;
+                if !self.quirks.sprite_address_wrap {
+                    self.ram_range(self.i_register, planes * (rows * sprite_width / 8) as usize)?;
+                }
+
+                let mut flipped = false;
+                let mut hit_rows = 0;
+                let mut address = self.i_register;
+                self.last_collisions.clear();
+
+                // Classic CHIP-8 only ever has the first plane selected.
+                // With XO-CHIP, each selected plane takes its own sprite,
+                // one after the other in memory.
+                for plane in 0..2 {
+                    if self.plane_mask & (1 << plane) != 0 {
+                        let (plane_flipped, plane_hit_rows) =
+                            self.draw_sprite(plane, coord_x, coord_y, address, rows, sprite_width);
+                        flipped |= plane_flipped;
+                        hit_rows = hit_rows.max(plane_hit_rows);
+                        address = address.wrapping_add(rows * sprite_width / 8);
+                    }
+                }
+
+                // If we did flip, VX has to be set to 1. SCHIP 1.1 counts
+                // the rows instead, in high resolution.
+                self.registers[0xF] = if self.quirks.schip_drw_vf_rows && self.hires {
+                    hit_rows as u8
+                } else if flipped {1} else {0};
+                self.sync_display_mirror();
+
+                if self.quirks.display_wait {
+                    self.vblank_wait = true;
+                }
+            },
+
+            // Not one of ours, or an extension we are not running
+            _ => return Err(Chip8Error::UnknownOpcode(opcode)),
+        }
+
+        Ok(())
+    }
+
+    /// EX9E and EXA1: skipping on the keypad.
+    pub(crate) fn exec_keys(&mut self, opcode: u16) -> Result<(), Chip8Error> {
+        match decode(opcode) {
+            // 22. EX9E - Skip if the key indexed at VX is currently pressed
+            // A key pressed and released since the frame started counts as
+            // pressed, see `end_frame`.
+            Instruction::Skp(x) => {
+                if self.is_key_down(self.registers[x as usize]) {
+                    self.skip();
+                }
+            },
+
+            // 23. EXA1 - Skip if the key indexed at VX is currently unpressed
+            Instruction::Sknp(x) => {
+                if !self.is_key_down(self.registers[x as usize]) {
+                    self.skip();
+                }
+            },
+
+            // Not one of ours, or an extension we are not running
+            _ => return Err(Chip8Error::UnknownOpcode(opcode)),
+        }
+
+        Ok(())
+    }
+
+    /// FXNN: timers, keys, memory, fonts and the extensions.
+    pub(crate) fn exec_misc(&mut self, opcode: u16) -> Result<(), Chip8Error> {
+        match decode(opcode) {
+            // F000 NNNN - Set I to the 16-bit address NNNN (XO-CHIP)
+            // This is the only 4-byte instruction: the address is in the
+            // word following the opcode, so we consume it here.
+            Instruction::LdILong if self.quirks.xo_chip => {
+                self.i_register = self.fetch()?;
+            },
+
+            // FN01 - Select the drawing planes with the bitmask N (XO-CHIP)
+            Instruction::Plane(n) if self.quirks.xo_chip => {
+                self.plane_mask = n & 0b11;
+            },
+
+            // F002 - Load the 16 bytes at I into the audio pattern (XO-CHIP)
+            Instruction::Audio if self.quirks.xo_chip => {
+                let range = self.ram_range(self.i_register, 16)?;
+                self.audio_pattern.copy_from_slice(&self.ram[range]);
+            },
+
+            // 24. FX07 - Set VX to the delay timer
+            Instruction::LdVxDt(x) => {
+                self.registers[x as usize] = self.delay_timer;
+            },
+
+            // 25. FX0A - Wait for any keypress. Store the keypress index in VX
+            // The CPU here stops until this is the case
+            Instruction::LdVxK(x) => {
+                // I wanted to do this with a while loop, but the guide rightly 
+                // suggested re-doing the instruction instead, so that the
+                // `cycle` function can re-register new key presses.
+                let x = x as usize;
+
+                // Only a key going down while we wait counts. A key that was
+                // already held (or is "pressed" again by key repeat) does
+                // not, or holding a key would answer every FX0A in a row.
+                // So we forget the presses from before we started waiting,
+                // and then look at the latch, which also catches a key that
+                // went down and up again between two cycles.
+                if !self.waiting_for_key {
+                    self.key_latch = [false; 16];
+                }
+                let pressed = self.key_latch.iter().position(|latched| *latched);
+
+                match pressed {
+                    Some(i) => {
+                        self.registers[x] = i as u8;
+                        self.waiting_for_key = false;
+                    },
+                    None => {
+                        self.waiting_for_key = true;
+                        // Back onto this FX0A. The fetch may have wrapped
+                        // past the top of the XO-CHIP RAM, so wrap back too.
+                        self.program_counter = self.program_counter.wrapping_sub(2);
+                    },
+                }
+            },
+
+            // 26. FX15 - Set the delay timer to VX
+            Instruction::LdDtVx(x) => {
+                self.delay_timer = self.registers[x as usize];
+            },
+
+            // 27. FX18 - Set the sound timer to VX
+            Instruction::LdStVx(x) => {
+                self.set_sound_timer(self.registers[x as usize]);
+            },
+
+            // FX3A - Set the audio pitch to VX (XO-CHIP)
+            Instruction::Pitch(x) if self.quirks.xo_chip => {
+                self.pitch = self.registers[x as usize];
+            },
+
+            // 28. FX1E - Set I to I + VX
+            Instruction::AddIVx(x) => {
+                self.i_register = self.i_register.wrapping_add(self.registers[x as usize] as u16);
+            },
+
+            // 29. FX29 - Set I to the position of the interpreter font character in VX
+            Instruction::LdFVx(x) => {
+                // The sprites are all 5 bytes long, and start at location 0
+                // in our ram. Therefore, to get their position, we multiply
+                // their value (in the register) by 5, and get the corresponding
+                // i_register position.
+                // Only the low nibble counts: there are no sprites past F,
+                // and we don't want to point I into the program.
+                self.i_register = (self.registers[x as usize] & 0x0F) as u16 * 5;
+            },
+
+            // FX30 - Set I to the big font character in VX (SCHIP)
+            Instruction::LdHfVx(x) if self.quirks.schip => {
+                // Same as FX29, but the big sprites are 10 bytes long.
+                self.i_register = BIG_FONT_ADDRESS + (self.registers[x as usize] & 0x0F) as u16 * 10;
+            },
+
+            // 30. FX33 - Store the BCD encoding of VX into I
+            Instruction::LdBVx(x) => {
+                // The BCD is a pseudo-decimal representation of a hex, stored
+                // as a series of hex values. For instance, 0x64, equal to 100,
+                // would become 0x1 (1), 0x0 (0), 0x0 (0), so three bytes, one
+                // for each digit. As the values in our registers can go up to
+                // 2^8 -1 = 255, we will always store three hex-encoded digits
+
+                let reg_x = self.registers[x as usize] as f32;
+
+                let hundreds = (reg_x / 100f32).floor() as u8;
+                let tens = ((reg_x / 10f32) % 10f32) as u8;
+                let ones = (reg_x % 10f32) as u8;
+
+                let range = self.ram_range(self.i_register, 3)?;
+                self.ram[range].copy_from_slice(&[hundreds, tens, ones]);
+            },
+
+            // 31. FX55 - Store V0 to VX into the RAM, starting from address I
+            Instruction::LdIVx(x) => {
+                let count = x as usize + 1;
+                let range = self.ram_range(self.i_register, count)?;
+                self.ram[range].copy_from_slice(&self.registers[..count]);
+            },
+
+            // 32. FX65 - Fill V0 to VX with the RAM values starting from address I
+            Instruction::LdVxI(x) => {
+                let count = x as usize + 1;
+                let range = self.ram_range(self.i_register, count)?;
+                self.registers[..count].copy_from_slice(&self.ram[range]);
+            },
+
+            // FX75 - Store V0 to VX in the flags, X at most 7 (SCHIP)
+            Instruction::LdRVx(x) if self.quirks.schip => {
+                // There are only 8 flags, so the rest is dropped
+                let count = (x as usize).min(7) + 1;
+                self.flags[..count].copy_from_slice(&self.registers[..count]);
+            },
+
+            // FX85 - Fill V0 to VX from the flags, X at most 7 (SCHIP)
+            Instruction::LdVxR(x) if self.quirks.schip => {
+                let count = (x as usize).min(7) + 1;
+                self.registers[..count].copy_from_slice(&self.flags[..count]);
+            },
+
+            // Not one of ours, or an extension we are not running
+            _ => return Err(Chip8Error::UnknownOpcode(opcode)),
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,43 @@
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+/// A function given to the processor to call back, like the one
+/// `Chip8Processor::on_sound_state_change` takes.
+///
+/// It is shared rather than boxed, so the processor can still be cloned
+/// (clones call the same function) and sent to other threads.
+pub(crate) struct Hook<T>(Arc<Mutex<dyn FnMut(T) + Send>>);
+
+impl<T> Hook<T> {
+    pub(crate) fn new(hook: impl FnMut(T) + Send + 'static) -> Self {
+        Self(Arc::new(Mutex::new(hook)))
+    }
+
+    pub(crate) fn call(&self, value: T) {
+        // A hook that panicked once is not worth calling again
+        if let Ok(mut hook) = self.0.lock() {
+            hook(value);
+        }
+    }
+}
+
+// Deriving would want `T: Clone`, which the shared function doesn't need.
+impl<T> Clone for Hook<T> {
+    fn clone(&self) -> Self {
+        Self(Arc::clone(&self.0))
+    }
+}
+
+// Two hooks are the same if they are the same function, which is all we
+// can tell about closures.
+impl<T> PartialEq for Hook<T> {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl<T> fmt::Debug for Hook<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Hook")
+    }
+}
@@ -0,0 +1,14 @@
+/// Somewhere to read the keypad from, so the core does not need to know
+/// about SDL, terminals or scripted test input.
+pub trait InputSource {
+    /// The state of every key right now, by keypad index.
+    fn poll(&mut self) -> [bool; 16];
+}
+
+/// A fixed snapshot of the keypad, for callers that already know what is
+/// pressed this step (replays, tests).
+impl InputSource for [bool; 16] {
+    fn poll(&mut self) -> [bool; 16] {
+        *self
+    }
+}
@@ -0,0 +1,182 @@
+// Opcodes, decoded into what they mean.
+//
+// The names follow the mnemonics of Cowgod's specification, with the
+// operands spelled out where one mnemonic covers several instructions
+// (`LD VX, NN` is `LdVxByte`, `LD VX, VY` is `LdVxVy`, and so on).
+// Registers are their index, 0x0 to 0xF.
+
+/// A single decoded instruction.
+///
+/// Decoding does not look at the quirks: the SUPER-CHIP and XO-CHIP
+/// instructions always decode to their variant, and `Chip8Processor`
+/// decides whether it runs them.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Instruction {
+    /// 00E0 - Clear the screen.
+    Cls,
+    /// 00EE - Return from a subroutine.
+    Ret,
+    /// 0NNN - Call a machine code routine, which we ignore.
+    Sys(u16),
+    /// 1NNN - Jump to NNN.
+    Jp(u16),
+    /// 2NNN - Call the subroutine at NNN.
+    Call(u16),
+    /// 3XNN - Skip if VX == NN.
+    SeVxByte(u8, u8),
+    /// 4XNN - Skip if VX != NN.
+    SneVxByte(u8, u8),
+    /// 5XY0 - Skip if VX == VY.
+    SeVxVy(u8, u8),
+    /// 6XNN - VX = NN.
+    LdVxByte(u8, u8),
+    /// 7XNN - VX += NN, without touching VF.
+    AddVxByte(u8, u8),
+    /// 8XY0 - VX = VY.
+    LdVxVy(u8, u8),
+    /// 8XY1 - VX |= VY.
+    Or(u8, u8),
+    /// 8XY2 - VX &= VY.
+    And(u8, u8),
+    /// 8XY3 - VX ^= VY.
+    Xor(u8, u8),
+    /// 8XY4 - VX += VY, VF is the carry.
+    AddVxVy(u8, u8),
+    /// 8XY5 - VX -= VY, VF is "no borrow".
+    Sub(u8, u8),
+    /// 8XY6 - Shift right, VF is the dropped bit.
+    Shr(u8, u8),
+    /// 8XY7 - VX = VY - VX, VF is "no borrow".
+    Subn(u8, u8),
+    /// 8XYE - Shift left, VF is the dropped bit.
+    Shl(u8, u8),
+    /// 9XY0 - Skip if VX != VY.
+    SneVxVy(u8, u8),
+    /// ANNN - I = NNN.
+    LdI(u16),
+    /// BNNN - Jump to V0 + NNN.
+    JpV0(u16),
+    /// CXNN - VX = a random byte & NN.
+    Rnd(u8, u8),
+    /// DXYN - Draw N rows of sprite from I at (VX, VY).
+    Drw(u8, u8, u8),
+    /// EX9E - Skip if the key in VX is pressed.
+    Skp(u8),
+    /// EXA1 - Skip if the key in VX is not pressed.
+    Sknp(u8),
+    /// FX07 - VX = the delay timer.
+    LdVxDt(u8),
+    /// FX0A - Wait for a key press, and put it in VX.
+    LdVxK(u8),
+    /// FX15 - The delay timer = VX.
+    LdDtVx(u8),
+    /// FX18 - The sound timer = VX.
+    LdStVx(u8),
+    /// FX1E - I += VX.
+    AddIVx(u8),
+    /// FX29 - Point I at the font sprite for VX.
+    LdFVx(u8),
+    /// FX33 - Store the BCD of VX at I.
+    LdBVx(u8),
+    /// FX55 - Store V0 to VX at I.
+    LdIVx(u8),
+    /// FX65 - Load V0 to VX from I.
+    LdVxI(u8),
+    /// 00FE - Switch to low resolution (SUPER-CHIP).
+    Low,
+    /// 00FF - Switch to high resolution (SUPER-CHIP).
+    High,
+    /// 00FD - Stop the interpreter (SUPER-CHIP).
+    Exit,
+    /// 00CN - Scroll the screen down N rows (SUPER-CHIP).
+    ScrollDown(u8),
+    /// 00DN - Scroll the screen up N rows (XO-CHIP).
+    ScrollUp(u8),
+    /// 00FB - Scroll the screen right 4 columns (SUPER-CHIP).
+    ScrollRight,
+    /// 00FC - Scroll the screen left 4 columns (SUPER-CHIP).
+    ScrollLeft,
+    /// FX30 - Point I at the big font sprite for VX (SUPER-CHIP).
+    LdHfVx(u8),
+    /// FX75 - Store V0 to VX in the flags (SUPER-CHIP).
+    LdRVx(u8),
+    /// FX85 - Load V0 to VX from the flags (SUPER-CHIP).
+    LdVxR(u8),
+    /// F000 - I = the 16-bit address in the next word (XO-CHIP).
+    LdILong,
+    /// FN01 - Select the drawing planes (XO-CHIP).
+    Plane(u8),
+    /// F002 - Load the audio pattern from I (XO-CHIP).
+    Audio,
+    /// FX3A - The audio pitch = VX (XO-CHIP).
+    Pitch(u8),
+    /// Not an instruction at all.
+    Unknown(u16),
+}
+
+/// Work out which instruction `opcode` is, without running it.
+pub fn decode(opcode: u16) -> Instruction {
+    use Instruction::*;
+
+    let digits = (
+        ((opcode & 0xF000) >> 12) as u8,
+        ((opcode & 0x0F00) >> 8) as u8,
+        ((opcode & 0x00F0) >> 4) as u8,
+        (opcode & 0x000F) as u8,
+    );
+    let nnn = opcode & 0xFFF;
+    let nn = (opcode & 0xFF) as u8;
+
+    match digits {
+        (0, 0, 0xE, 0) => Cls,
+        (0, 0, 0xE, 0xE) => Ret,
+        (0, 0, 0xF, 0xE) => Low,
+        (0, 0, 0xF, 0xF) => High,
+        (0, 0, 0xF, 0xD) => Exit,
+        (0, 0, 0xC, n) => ScrollDown(n),
+        (0, 0, 0xD, n) => ScrollUp(n),
+        (0, 0, 0xF, 0xB) => ScrollRight,
+        (0, 0, 0xF, 0xC) => ScrollLeft,
+        (0, ..) => Sys(nnn),
+        (1, ..) => Jp(nnn),
+        (2, ..) => Call(nnn),
+        (3, x, ..) => SeVxByte(x, nn),
+        (4, x, ..) => SneVxByte(x, nn),
+        (5, x, y, 0) => SeVxVy(x, y),
+        (6, x, ..) => LdVxByte(x, nn),
+        (7, x, ..) => AddVxByte(x, nn),
+        (8, x, y, 0) => LdVxVy(x, y),
+        (8, x, y, 1) => Or(x, y),
+        (8, x, y, 2) => And(x, y),
+        (8, x, y, 3) => Xor(x, y),
+        (8, x, y, 4) => AddVxVy(x, y),
+        (8, x, y, 5) => Sub(x, y),
+        (8, x, y, 6) => Shr(x, y),
+        (8, x, y, 7) => Subn(x, y),
+        (8, x, y, 0xE) => Shl(x, y),
+        (9, x, y, 0) => SneVxVy(x, y),
+        (0xA, ..) => LdI(nnn),
+        (0xB, ..) => JpV0(nnn),
+        (0xC, x, ..) => Rnd(x, nn),
+        (0xD, x, y, n) => Drw(x, y, n),
+        (0xE, x, 9, 0xE) => Skp(x),
+        (0xE, x, 0xA, 1) => Sknp(x),
+        (0xF, 0, 0, 0) => LdILong,
+        (0xF, n, 0, 1) => Plane(n),
+        (0xF, 0, 0, 2) => Audio,
+        (0xF, x, 0, 7) => LdVxDt(x),
+        (0xF, x, 0, 0xA) => LdVxK(x),
+        (0xF, x, 1, 5) => LdDtVx(x),
+        (0xF, x, 1, 8) => LdStVx(x),
+        (0xF, x, 1, 0xE) => AddIVx(x),
+        (0xF, x, 2, 9) => LdFVx(x),
+        (0xF, x, 3, 0) => LdHfVx(x),
+        (0xF, x, 3, 3) => LdBVx(x),
+        (0xF, x, 3, 0xA) => Pitch(x),
+        (0xF, x, 5, 5) => LdIVx(x),
+        (0xF, x, 6, 5) => LdVxI(x),
+        (0xF, x, 7, 5) => LdRVx(x),
+        (0xF, x, 8, 5) => LdVxR(x),
+        _ => Unknown(opcode),
+    }
+}
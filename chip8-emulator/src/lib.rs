@@ -1,7 +1,58 @@
+use std::collections::VecDeque;
 use std::fmt::Display;
 use std::fmt;
-
-use rand::random;
+use std::ops::Range;
+#[cfg(feature = "std")]
+use std::path::Path;
+use std::time::Duration;
+
+use rand::rngs::StdRng;
+
+pub mod asm;
+mod builder;
+mod display;
+mod emulator;
+mod error;
+mod execute;
+mod hook;
+mod input;
+mod instruction;
+#[cfg(feature = "poison")]
+mod poison;
+mod quirks;
+mod sink;
+mod snapshot;
+mod step;
+mod timer;
+#[cfg(feature = "std")]
+mod timer_thread;
+mod timing;
+mod verify;
+#[cfg(feature = "profiling")]
+mod stats;
+
+pub use builder::Chip8Builder;
+use display::Bitplane;
+pub use emulator::{CycleScheduler, Emulator, FrameOutput};
+pub use error::Chip8Error;
+use hook::Hook;
+pub use input::InputSource;
+pub use instruction::{decode, Instruction};
+#[cfg(feature = "poison")]
+use poison::Poison;
+#[cfg(feature = "poison")]
+pub use poison::{Location, UninitializedRead};
+pub use quirks::Quirks;
+pub use sink::{DisplaySink, NullSink};
+pub use snapshot::Chip8Snapshot;
+use snapshot::UndoStep;
+pub use step::ExecutedInstruction;
+pub use timer::TimerMode;
+pub use verify::{verify_rom, RomWarning};
+#[cfg(feature = "std")]
+pub use timer_thread::TimerThread;
+#[cfg(feature = "profiling")]
+pub use stats::OpcodeStats;
 
 // These are taken from Cowgod's CHIP8 specification.
 const INTERPRETER_SPRITES: [u8; 80] = [
@@ -23,17 +74,88 @@ const INTERPRETER_SPRITES: [u8; 80] = [
     0xF0, 0x80, 0xF0, 0x80, 0x80  // F
 ];
 
+// The SUPER-CHIP big font, 8x10 sprites for the digits 0 to 9. SUPER-CHIP
+// stops there, but XO-CHIP goes on to F, like the small font, so FX30 can
+// point at any hex digit.
+const BIG_INTERPRETER_SPRITES: [u8; 160] = [
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, // 0
+    0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3C, // 1
+    0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, // 2
+    0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, // 3
+    0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF, 0x06, 0x06, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, // 5
+    0x3E, 0x7C, 0xE0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, // 6
+    0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60, // 7
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, // 8
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C, // 9
+    0x3C, 0x7E, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFE, 0xC3, 0xC3, 0xFE, 0xFE, 0xC3, 0xC3, 0xFE, 0xFC, // B
+    0x3C, 0x7E, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0x7E, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFC, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFC, 0xC0, 0xC0, 0xC0, 0xC0  // F
+];
+
+// The big font sits right after the small one.
+const BIG_FONT_ADDRESS: u16 = 80;
+
 const START_ADDRESS: u16 = 0x200;
 
+/// How much RAM a CHIP-8 has: 4 KiB. XO-CHIP has 64 KiB instead.
+pub const RAM_SIZE: usize = 4096;
+/// The last address in the RAM of a CHIP-8, 0xFFF.
+pub const MAX_ADDR: u16 = (RAM_SIZE - 1) as u16;
+
+// The timers count down at 60Hz.
+const TIMER_PERIOD: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+// How many instructions `undo` can go back.
+const UNDO_HISTORY_LEN: usize = 256;
+
+// XO-CHIP can address the whole 16-bit space.
+const XO_CHIP_RAM_SIZE: usize = 0x10000;
+
+/// The width of the screen, in pixels.
+///
+/// Front-ends can size their windows from these:
+///
+/// ```
+/// use chip8_emulator::{DISPLAY_MEM_WIDTH, DISPLAY_MEM_HEIGHT};
+///
+/// assert_eq!((DISPLAY_MEM_WIDTH, DISPLAY_MEM_HEIGHT), (64, 32));
+/// ```
 pub const DISPLAY_MEM_WIDTH: usize = 64;
+/// The height of the screen, in pixels.
 pub const DISPLAY_MEM_HEIGHT: usize = 32;
 
-#[derive(PartialEq, Debug)]
+/// Where the screen is mirrored in RAM, when `set_display_mirror` is on.
+///
+/// This is where the COSMAC VIP kept its framebuffer: the last 256 bytes
+/// of the 4 KiB RAM.
+pub const DISPLAY_MIRROR_ADDRESS: u16 = 0xF00;
+
+/// The width of the SUPER-CHIP high resolution screen, double the classic one.
+pub const HIRES_DISPLAY_WIDTH: usize = 128;
+/// The height of the SUPER-CHIP high resolution screen.
+pub const HIRES_DISPLAY_HEIGHT: usize = 64;
+
+/// The height of the HIRES CHIP-8 screen, as wide as the classic one but
+/// twice as tall (see `Quirks::hires_chip8`).
+pub const HIRES_CHIP8_DISPLAY_HEIGHT: usize = 64;
+
+// HIRES CHIP-8 programs start with a jump over the patch they carry for
+// the interpreter, to here...
+const HIRES_CHIP8_PATCH_ADDRESS: u16 = 0x260;
+// ...and the program itself starts here.
+const HIRES_CHIP8_START_ADDRESS: u16 = 0x2C0;
+
+#[derive(Clone, PartialEq, Debug)]
 pub struct Chip8Processor {
     // First, we set out the things as set out in the specification
     //  --- Memory ---
     // Interpreter + working ram
-    ram: [u8; 4096], // A 4096 bytes ram, broken up in 8-bit (1 byte) chunks
+    ram: Vec<u8>, // A 4096 bytes ram (64 KiB for XO-CHIP), broken up in 8-bit (1 byte) chunks
+    rom_end: usize, // One past the last byte of the loaded ROM
     // Registers
     registers: [u8; 16], // 16 8-bit registers
     i_register: u16, // The 16-bit "i" register
@@ -41,19 +163,58 @@ pub struct Chip8Processor {
     program_counter: u16, // The pg, telling the cpu which instruction to run next
     stack: [u16; 16], // A 16-long 16-bit values stack
     stack_ptr: u8, // The stack pointer, pointing at the top of the stack
+    flags: [u8; 8], // The SUPER-CHIP "RPL user flags", kept across resets
+    halted: bool, // Whether the program ran the SUPER-CHIP exit, 00FD
 
     //  --- Peripheral input ---
     keypad: [bool; 16], // The keypad is 16 hex values, 123456789ABCDEF
                         // Each input is represented here as "false" for unpressed and "true" for pressed
+    key_latch: [bool; 16], // The keys that went down since the frame (or FX0A's wait) started
+    waiting_for_key: bool, // Whether FX0A is waiting for a key to go down
 
     //  --- Outputs ---
-    display: [bool; DISPLAY_MEM_WIDTH * DISPLAY_MEM_HEIGHT],
-    // The 64x32 display, represented by an array of bools. Each point is a
-    // pixel, either on or off.
+    display: Bitplane,
+    // The 64x32 (128x64 in hires mode) display, packed one row per word,
+    // see `display.rs`. Each point is a pixel, either on or off.
+    second_plane: Bitplane,
+    // The second XO-CHIP bitplane. Classic CHIP-8 never draws here.
+    plane_mask: u8, // Which planes (bit 0 and bit 1) drawing operations affect
+    hires: bool, // Whether we are in the SUPER-CHIP 128x64 mode
+    hires_chip8: bool, // Whether we are in the HIRES CHIP-8 64x64 mode
+    previous_display: Vec<bool>, // The display as of the last `display_changes` call
+    display_mirror: bool, // Whether to copy the display to RAM, see `set_display_mirror`
+    track_collisions: bool, // Whether to fill `last_collisions`, see `set_collision_tracking`
+    last_collisions: Vec<usize>, // The pixels the last draw turned off
 
     //  --- Timers ---
     delay_timer: u8, // A decreasing 60Hz timer for game time
     sound_timer: u8, // A decreasing 60Hz timer for sounds
+    timer_elapsed: Duration, // Time passed since the last tick, see `advance_timers`
+    vblank_wait: bool, // Idling until the next frame after a draw, see `Quirks::display_wait`
+    timer_mode: TimerMode, // Who ticks the timers
+    sound_hook: Option<Hook<bool>>, // Told when the beep starts and stops
+    cycles_since_tick: usize, // Cycles run since the timers last ticked, for `TimerMode::PerCycles`
+
+    //  --- XO-CHIP audio ---
+    audio_pattern: [u8; 16], // 128 1-bit samples played while the sound timer is set
+    pitch: u8, // The playback rate of the pattern
+
+    //  --- Configuration ---
+    quirks: Quirks, // Which flavour of CHIP-8 we are emulating
+    rng: Option<StdRng>, // A seeded RNG for CXNN. If None, we use the thread RNG
+    rng_constant: Option<u8>, // What CXNN gets instead of a random number, see `set_rng_constant`
+
+    //  --- Debugging ---
+    breakpoints: Vec<u16>, // Sorted addresses where `run_until_breakpoint` stops
+    recording: bool, // Whether to fill `replay_log`, see `set_recording`
+    replay_log: Vec<(u16, u16)>, // The (pc, opcode) pairs run while recording
+    undo_enabled: bool, // Whether to fill `undo_history`, see `set_undo_history`
+    undo_history: VecDeque<UndoStep>, // The state before each of the last cycles, newest last
+    strict_alignment: bool, // Whether fetching from an odd address is an error
+    #[cfg(feature = "profiling")]
+    stats: OpcodeStats, // How often each instruction ran
+    #[cfg(feature = "poison")]
+    poison: Option<Poison>, // What the program wrote, see `on_uninitialized_read`
 }
 
 
@@ -82,47 +243,170 @@ impl Display for Chip8Processor{
     }
 }
 
-impl Chip8Processor {
-    // The processor does 3 things: fetch, decode, execute.
-    // We therefore need functions that do these three things for us.
-
-    /// Make a new Processor, ready for execution. 
-    pub fn new() -> Self {
+impl Default for Chip8Processor {
+    /// Make a new Processor, with the font loaded and the program counter
+    /// at the start of the program space.
+    fn default() -> Self {
         let mut new_processor = Self {
-            ram: [0; 4096], // The ram is empty
+            ram: vec![0; RAM_SIZE], // The ram is empty
+            rom_end: 0, // No ROM yet
             registers: [0; 16], // The registers are empty
             i_register: 0,
             program_counter: START_ADDRESS, // Programs always start @ ram location 0x200
             stack: [0; 16], // The stack is empty
             stack_ptr: 0, // The start of the stack is at location 0
+            flags: [0; 8], // Nothing saved yet
+            halted: false, // Running
             keypad: [false; 16], // No buttons are pressed
-            display: [false; DISPLAY_MEM_WIDTH * DISPLAY_MEM_HEIGHT], // The screen is completely off
+            key_latch: [false; 16], // No presses yet
+            waiting_for_key: false, // Not waiting for a key
+            display: Bitplane::new(DISPLAY_MEM_WIDTH, DISPLAY_MEM_HEIGHT), // The screen is completely off
+            second_plane: Bitplane::new(DISPLAY_MEM_WIDTH, DISPLAY_MEM_HEIGHT),
+            plane_mask: 0b01, // Only the first plane is drawn to
+            hires: false, // Classic resolution
+            hires_chip8: false,
+            previous_display: vec![false; DISPLAY_MEM_WIDTH * DISPLAY_MEM_HEIGHT],
+            display_mirror: false, // The RAM is all for the program
+            track_collisions: false, // VF is enough
+            last_collisions: Vec::new(),
             delay_timer: 0, // The timer is not set
             sound_timer: 0, // The sound timer is off
+            timer_elapsed: Duration::ZERO,
+            vblank_wait: false,
+            timer_mode: TimerMode::Manual, // The front-end ticks the timers
+            sound_hook: None,
+            cycles_since_tick: 0,
+            audio_pattern: [0; 16], // Silence
+            pitch: 64, // 4000 samples per second
+            quirks: Quirks::default(), // Plain CHIP-8
+            rng: None, // Truly random
+            rng_constant: None,
+            breakpoints: Vec::new(), // Nowhere to stop
+            recording: false,
+            undo_enabled: false,
+            undo_history: VecDeque::new(),
+            strict_alignment: false, // Run whatever the program jumps to
+            replay_log: Vec::new(),
+            #[cfg(feature = "profiling")]
+            stats: OpcodeStats::default(),
+            #[cfg(feature = "poison")]
+            poison: None,
         };
 
         new_processor.ram[..80].copy_from_slice(&INTERPRETER_SPRITES);
+        new_processor.ram[80..240].copy_from_slice(&BIG_INTERPRETER_SPRITES);
 
         new_processor
     }
+}
+
+impl Chip8Processor {
+    // The processor does 3 things: fetch, decode, execute.
+    // We therefore need functions that do these three things for us.
+
+    /// Make a new Processor, ready for execution. 
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Where the next instruction will be fetched from.
+    pub fn pc(&self) -> u16 {
+        self.program_counter
+    }
+
+    /// How many return addresses are on the stack.
+    pub fn stack_depth(&self) -> usize {
+        self.stack_ptr as usize
+    }
+
+    /// The return addresses on the stack, from the outermost call in.
+    pub fn call_stack(&self) -> &[u16] {
+        &self.stack[..self.stack_ptr as usize]
+    }
+
+    /// Get the quirks the processor is running with.
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    /// Change the quirks the processor is running with.
+    ///
+    /// Toggling `xo_chip` grows (or shrinks) the RAM to the size that
+    /// platform can address.
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        let ram_size = if quirks.xo_chip { XO_CHIP_RAM_SIZE } else { RAM_SIZE };
+        self.ram.resize(ram_size, 0);
+        self.quirks = quirks;
+    }
+
+    /// Have `CXNN` use `constant` (still ANDed with NN) instead of a random
+    /// number, so programs drawing random things always draw the same.
+    /// `None` goes back to random numbers.
+    pub fn set_rng_constant(&mut self, constant: Option<u8>) {
+        self.rng_constant = constant;
+    }
+
+    /// Go back to a freshly built processor, ready for a ROM to be loaded.
+    ///
+    /// The quirks, the RNG (or its constant), the debugging state (breakpoints, replay log)
+    /// and the SUPER-CHIP flags (see `FX75`) are kept.
+    pub fn reset(&mut self) {
+        let mut fresh = Self::default();
+
+        fresh.set_quirks(self.quirks);
+        fresh.rng = self.rng.take();
+        fresh.rng_constant = self.rng_constant;
+        fresh.breakpoints = std::mem::take(&mut self.breakpoints);
+        fresh.recording = self.recording;
+        fresh.undo_enabled = self.undo_enabled;
+        fresh.strict_alignment = self.strict_alignment;
+        fresh.replay_log = std::mem::take(&mut self.replay_log);
+        fresh.flags = self.flags;
+        fresh.display_mirror = self.display_mirror;
+        fresh.track_collisions = self.track_collisions;
+        fresh.timer_mode = self.timer_mode;
+        fresh.sound_hook = self.sound_hook.take();
+        #[cfg(feature = "profiling")]
+        {
+            fresh.stats = std::mem::take(&mut self.stats);
+        }
+        #[cfg(feature = "poison")]
+        {
+            fresh.poison = self.poison.as_ref().map(|poison| poison.restarted(fresh.ram.len()));
+        }
+
+        *self = fresh;
+    }
+
+    /// Replace the interpreter font, the 16 sprites `FX29` points at, with
+    /// `font`, one 5-byte sprite per hex digit.
+    ///
+    /// `new` and `reset` always start with the built-in font.
+    pub fn set_font(&mut self, font: &[[u8; 5]; 16]) {
+        for (digit, sprite) in font.iter().enumerate() {
+            self.ram[digit * 5..digit * 5 + 5].copy_from_slice(sprite);
+        }
+    }
 
     /// Push a value to the stack
-    fn push(&mut self, val: u16) {
+    fn push(&mut self, val: u16) -> Result<(), Chip8Error> {
         // Protect against stack overflow
-        if self.stack_ptr > self.stack.len() as u8 {
-            panic!("Stack overflow!");
+        if self.stack_ptr as usize >= self.stack.len() {
+            return Err(Chip8Error::StackOverflow);
         }
         // Push the value where the pointer is
         self.stack[self.stack_ptr as usize] = val;
         // Point up by one.
         self.stack_ptr += 1;
+
+        Ok(())
     }
 
     /// Pop a value from the stack
-    fn pop(&mut self) -> u16 {
+    fn pop(&mut self) -> Result<u16, Chip8Error> {
         // Protect against a stack underflow
         if self.stack_ptr == 0 {
-            panic!("Stack underflow!");
+            return Err(Chip8Error::StackUnderflow);
         }
         // Pop a value
         self.stack_ptr -= 1;
@@ -130,465 +414,914 @@ impl Chip8Processor {
         let result = self.stack[self.stack_ptr as usize];
         self.stack[self.stack_ptr as usize] = 0;
 
-        result
+        Ok(result)
     }
 
-    /// Execute one Fetch-Decode-Execute cycle
-    pub fn cycle(&mut self) {
+    /// Execute one Fetch-Decode-Execute cycle, returning how many COSMAC
+    /// VIP machine cycles the instruction took (0 if we are idling until
+    /// the next frame).
+    pub fn cycle(&mut self) -> Result<u32, Chip8Error> {
+        // The program is over, and nothing changes anymore
+        if self.halted {
+            return Ok(0);
+        }
+
+        if self.undo_enabled {
+            if self.undo_history.len() == UNDO_HISTORY_LEN {
+                self.undo_history.pop_front();
+            }
+            self.undo_history.push_back(self.undo_step());
+        }
+
+        // This comes first, or waiting for the next frame would wait forever
+        if let TimerMode::PerCycles(period) = self.timer_mode {
+            self.cycles_since_tick += 1;
+            if period > 0 && self.cycles_since_tick >= period {
+                self.cycles_since_tick = 0;
+                self.tick_timers();
+            }
+        }
+
+        // We drew this frame, and are waiting for the next one to start
+        if self.vblank_wait {
+            return Ok(0);
+        }
+
         // Fetch an instruction
-        let opcode = self.fetch();
+        let pc = self.program_counter;
+        let opcode = self.fetch()?;
+
+        if self.recording {
+            self.replay_log.push((pc, opcode));
+        }
+
+        #[cfg(feature = "profiling")]
+        self.stats.record(opcode);
+
+        #[cfg(feature = "poison")]
+        self.check_poison(pc, opcode);
 
         // Decode and execute the function
-        self.execute(opcode);
+        self.execute(opcode)?;
+
+        Ok(timing::cost(opcode))
+    }
+
+    /// Like `cycle`, but read the keypad from `input` first.
+    pub fn cycle_with_input(&mut self, input: &mut impl InputSource) -> Result<u32, Chip8Error> {
+        self.set_keypad(input.poll());
+        self.cycle()
+    }
+
+    /// The execution counters collected so far.
+    #[cfg(feature = "profiling")]
+    pub fn stats(&self) -> &OpcodeStats {
+        &self.stats
+    }
+
+    /// Start counting from zero again.
+    #[cfg(feature = "profiling")]
+    pub fn reset_stats(&mut self) {
+        self.stats = OpcodeStats::default();
+    }
+
+    /// Run `n` cycles in a row, stopping at the first error.
+    pub fn cycle_n(&mut self, n: usize) -> Result<(), Chip8Error> {
+        for _ in 0..n {
+            self.cycle()?;
+        }
+
+        Ok(())
+    }
+
+    /// Like `cycle_n`, but read the keypad from `input` before every cycle.
+    pub fn cycle_n_with_input(&mut self, n: usize, input: &mut impl InputSource) -> Result<(), Chip8Error> {
+        for _ in 0..n {
+            self.cycle_with_input(input)?;
+        }
+
+        Ok(())
+    }
+
+    /// Run instructions until they took at least `budget` machine cycles,
+    /// and return how many they took.
+    ///
+    /// The COSMAC VIP ran about 3668 machine cycles per 60Hz frame, so a
+    /// front-end running that budget every frame gets the original speed,
+    /// whatever mix of instructions the program uses. This stops early if
+    /// the processor starts idling until the next frame.
+    pub fn run_budget(&mut self, budget: u32) -> Result<u32, Chip8Error> {
+        let mut spent = 0;
+
+        while spent < budget {
+            match self.cycle()? {
+                0 => break,
+                cost => spent += cost,
+            }
+        }
+
+        Ok(spent)
+    }
+
+    /// Run at most `max_cycles` cycles, returning how many were run.
+    ///
+    /// This stops early on the first error, if the program is stuck in a
+    /// jump to itself (the usual way CHIP-8 programs "end"), or if it
+    /// exited with `00FD`.
+    pub fn run_for(&mut self, max_cycles: usize) -> Result<usize, Chip8Error> {
+        for count in 0..max_cycles {
+            // A 1NNN that jumps to its own address will never go anywhere
+            if self.halted || self.peek(self.program_counter) == 0x1000 | self.program_counter {
+                return Ok(count);
+            }
+
+            self.cycle()?;
+        }
+
+        Ok(max_cycles)
+    }
+
+    /// Stop `run_until_breakpoint` when the program counter reaches `addr`.
+    pub fn add_breakpoint(&mut self, addr: u16) {
+        if let Err(position) = self.breakpoints.binary_search(&addr) {
+            self.breakpoints.insert(position, addr);
+        }
+    }
+
+    /// Forget the breakpoint at `addr`, if there is one.
+    pub fn remove_breakpoint(&mut self, addr: u16) {
+        if let Ok(position) = self.breakpoints.binary_search(&addr) {
+            self.breakpoints.remove(position);
+        }
+    }
+
+    /// The addresses with a breakpoint, in order.
+    pub fn breakpoints(&self) -> &[u16] {
+        &self.breakpoints
+    }
+
+    /// Refuse to fetch instructions from odd addresses, with
+    /// `Chip8Error::MisalignedPc`. Instructions are two bytes long, so a
+    /// program jumping to an odd address is usually reading garbage from
+    /// then on. Off by default, since some programs do it on purpose.
+    pub fn set_strict_alignment(&mut self, enabled: bool) {
+        self.strict_alignment = enabled;
+    }
+
+    /// Start (or stop) logging every instruction run, as (pc, opcode)
+    /// pairs, to reproduce a run later with `replay`.
+    pub fn set_recording(&mut self, enabled: bool) {
+        self.recording = enabled;
+    }
+
+    /// Start (or stop) remembering the state before each cycle, so that
+    /// `undo` can step back, up to 256 instructions. Stopping forgets the
+    /// history.
+    pub fn set_undo_history(&mut self, enabled: bool) {
+        self.undo_enabled = enabled;
+        if !enabled {
+            self.undo_history.clear();
+        }
+    }
+
+    /// Go back to before the last cycle. Returns false if there is nothing
+    /// left to undo.
+    ///
+    /// The RNG is not rewound, so redoing a `CXNN` can roll another number.
+    pub fn undo(&mut self) -> bool {
+        match self.undo_history.pop_back() {
+            Some(step) => {
+                self.apply_undo_step(&step);
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// The instructions run while recording, oldest first.
+    pub fn replay_log(&self) -> &[(u16, u16)] {
+        &self.replay_log
+    }
+
+    /// Hand over the log recorded so far, starting a new one.
+    pub fn take_replay_log(&mut self) -> Vec<(u16, u16)> {
+        std::mem::take(&mut self.replay_log)
+    }
+
+    /// Run the instructions in `log`, each as if it had just been fetched
+    /// from its address, stopping at the first error.
+    ///
+    /// Starting from the same state (and RNG seed) as the recording, this
+    /// ends in the same state, so the two can be compared.
+    pub fn replay(&mut self, log: &[(u16, u16)]) -> Result<(), Chip8Error> {
+        for &(pc, opcode) in log {
+            self.program_counter = pc.wrapping_add(2);
+            self.execute(opcode)?;
+        }
+
+        Ok(())
+    }
+
+    /// Run until the program counter lands on a breakpoint, and return it,
+    /// for at most `max_cycles` cycles. `None` if none was hit by then, so a
+    /// program that never gets to one can't hang the caller.
+    ///
+    /// At least one cycle is always run, so calling this again while
+    /// stopped on a breakpoint continues to the next one. If the program
+    /// exits with `00FD` first, this returns where it stopped.
+    pub fn run_until_breakpoint(&mut self, max_cycles: usize) -> Result<Option<u16>, Chip8Error> {
+        for _ in 0..max_cycles {
+            self.cycle()?;
+
+            if self.halted || self.breakpoints.binary_search(&self.program_counter).is_ok() {
+                return Ok(Some(self.program_counter));
+            }
+        }
+
+        Ok(None)
     }
 
     /// Fetch the current opcode to be executed
-    fn fetch(&mut self) -> u16 {
-        let high_byte = self.ram[self.program_counter as usize] as u16;
-        let low_byte = self.ram[(self.program_counter + 1) as usize] as u16;
+    ///
+    /// Opcodes are big-endian: the high byte comes first. An opcode starting
+    /// on the last byte of the ROM (an odd-length ROM) or of the RAM has no
+    /// low byte of its own, so we refuse it instead of running whatever
+    /// happens to come after.
+    fn fetch(&mut self) -> Result<u16, Chip8Error> {
+        let pc = self.program_counter as usize;
+        if pc + 1 >= self.ram.len() || pc + 1 == self.rom_end {
+            return Err(Chip8Error::OutOfBoundsMemory(self.program_counter));
+        }
+        if self.strict_alignment && !self.program_counter.is_multiple_of(2) {
+            return Err(Chip8Error::MisalignedPc);
+        }
+
+        let opcode = self.peek(self.program_counter);
+
+        self.program_counter = self.program_counter.wrapping_add(2);
+
+        Ok(opcode)
+    }
 
-        let opcode = (high_byte << 8) | low_byte;
+    /// Read the 16-bit word at `address`, without moving the program counter.
+    /// Bytes outside of the RAM read as 0.
+    fn peek(&self, address: u16) -> u16 {
+        let byte = |address: usize| self.ram.get(address).copied().unwrap_or(0) as u16;
 
-        self.program_counter += 2;
+        (byte(address as usize) << 8) | byte(address as usize + 1)
+    }
+
+    /// Skip over the next instruction.
+    fn skip(&mut self) {
+        // XO-CHIP has a single 4-byte instruction, F000 NNNN, that must be
+        // skipped as a whole, or we would end up executing its argument.
+        if self.quirks.xo_chip && self.peek(self.program_counter) == 0xF000 {
+            self.program_counter = self.program_counter.wrapping_add(4);
+        } else {
+            // 2 as we skip 2 bytes, so 1 opcode. Like `fetch`, this wraps
+            // around the top of the XO-CHIP 64 KiB RAM.
+            self.program_counter = self.program_counter.wrapping_add(2);
+        }
+    }
 
-        opcode
+    /// Choose what makes the timers count down, see `TimerMode`.
+    pub fn set_timer_mode(&mut self, mode: TimerMode) {
+        self.timer_mode = mode;
+        self.cycles_since_tick = 0;
     }
 
     /// Tick the timers down by one unit (if set).
     pub fn tick_timers(&mut self) {
+        // A new frame starts, so we can draw again
+        self.vblank_wait = false;
+
         if self.delay_timer > 0 {
             self.delay_timer -= 1;
         }
 
         if self.sound_timer > 0 {
-            if self.sound_timer == 1 {
-                // Code that makes it beep
+            self.set_sound_timer(self.sound_timer - 1);
+        }
+    }
+
+    /// Call `hook` with the new state whenever the beep starts (`true`) or
+    /// stops (`false`), for haptics, flashes or anything else that should
+    /// follow the sound. Only the program and the timers count down, not
+    /// `restore` or `reset`.
+    pub fn on_sound_state_change(&mut self, hook: impl FnMut(bool) + Send + 'static) {
+        self.sound_hook = Some(Hook::new(hook));
+    }
+
+    /// Stop calling the hook given to `on_sound_state_change`.
+    pub fn clear_sound_hook(&mut self) {
+        self.sound_hook = None;
+    }
+
+    fn set_sound_timer(&mut self, value: u8) {
+        let was_beeping = self.is_beeping();
+        self.sound_timer = value;
+
+        if was_beeping != self.is_beeping() {
+            if let Some(hook) = &self.sound_hook {
+                hook.call(self.is_beeping());
             }
-            self.sound_timer -= 1;
-        }
-    }
-
-    /// Execute the input opcode.
-    fn execute(&mut self, opcode: u16) {
-        // What we do here is "OR" out the parts of the opcode that we don't
-        // need, and then shift the bytes to the left, to the start of the 
-        // u16. This causes the code to be left-padded by zeroes, and can
-        // be interpreted directly as the new single-digit u16.
-        let digits = (
-            (opcode & 0xF000) >> 12,
-            (opcode & 0x0F00) >> 8,
-            (opcode & 0x00F0) >> 4,
-            opcode & 0x000F
-        );
-
-        match digits {
-            // 0. 0000 - NOP - Do nothing
-            (0, 0, 0, 0) => return,
-
-            // 1. 00E0 - CLS - Clear Display
-            (0, 0, 0xE, 0) => {
-                println!("Opcode: {:#06x} {}", opcode, self);
-                self.display = [false; DISPLAY_MEM_WIDTH * DISPLAY_MEM_HEIGHT]
-            },
+        }
+    }
 
-            // 2. 00EE - Return from subroutine
-            (0, 0, 0xE, 0xE) => {
-                println!("Opcode: {:#06x} {}", opcode, self);
-                let return_value = self.pop();
-                self.program_counter = return_value;
-            },
+    /// The XO-CHIP audio pattern: 128 samples, one per bit, most
+    /// significant bit first.
+    pub fn audio_pattern(&self) -> &[u8; 16] {
+        &self.audio_pattern
+    }
 
-            // 3. 1NNN - JMP NNN - Jump to location NNN
-            (1, ..) => {
-                println!("Opcode: {:#06x} {}", opcode, self);
-                let nnn = opcode & 0xFFF;
-                self.program_counter = nnn;
-            },
+    /// The XO-CHIP pitch register, as set by `FX3A`.
+    pub fn audio_pitch(&self) -> u8 {
+        self.pitch
+    }
 
-            // 4. 2NNN - CALL NNN - Call Subroutine @NNN
-            (2, ..) => {
-                println!("Opcode: {:#06x} {}", opcode, self);
-                let nnn: u16 = opcode & 0xFFF;
-                self.push(self.program_counter); // This works because u16 is Copy
-                self.program_counter = nnn;
-            },
+    /// How many pattern bits should be played per second at the current pitch.
+    pub fn audio_sample_rate(&self) -> f32 {
+        // From the XO-CHIP spec: 4000 * 2 ^ ((pitch - 64) / 48)
+        4000.0 * 2f32.powf((self.pitch as f32 - 64.0) / 48.0)
+    }
 
-            // 5. 3XNN - SKIP VX == NN - Skip ahead if
-            (3, x, ..) => {
-                println!("Opcode: {:#06x} {}", opcode, self);
-                let nn = (opcode & 0xFF) as u8;
-                if self.registers[x as usize] == nn {
-                    self.program_counter += 2; // 2 as we skip 2 bytes, so 1 opcode
-                }
-            },
+    /// Whether the program exited with the SUPER-CHIP `00FD`. From then
+    /// on, `cycle` does nothing, until a `reset`.
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
 
-            // 6. 4XNN - SKIP VX != NN - Skip ahead if not
-            (4, x, ..) => {
-                println!("Opcode: {:#06x} {}", opcode, self);
-                let nn = (opcode & 0xFF) as u8;
-                if self.registers[x as usize] != nn {
-                    self.program_counter += 2; // 2 as we skip 2 bytes, so 1 opcode
-                }
-            },
+    /// Whether the program is stuck on `FX0A`, waiting for a key to be
+    /// pressed, so front-ends can show a "press any key" prompt.
+    pub fn is_waiting_for_key(&self) -> bool {
+        self.waiting_for_key
+    }
 
-            // 7. 5XY0 - SKIP VX == VY - Skip ahead if X == Y
-            (5, x, y, 0) => {
-                println!("Opcode: {:#06x} {}", opcode, self);
-                if self.registers[x as usize] == self.registers[y as usize] {
-                    self.program_counter += 2; // 2 as we skip 2 bytes, so 1 opcode
-                }
-            },
-            
-            // 8. 6XNN - VX = NN - Set register X to NN
-            (6, x, ..) => {
-                println!("Opcode: {:#06x} {}", opcode, self);
-                let nn = opcode & 0xFF;
-                self.registers[x as usize] = nn as u8; 
-            },
+    /// The delay timer, which counts down at 60 Hz.
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
 
-            // 9. 7XNN - VX + NN
-            (7, x, ..) => {
-                println!("Opcode: {:#06x} {}", opcode, self);
-                // Rust could overflow here, but Chip8 expects the numbers to wrap
-                let nn = opcode & 0xFF;
-                
-                self.registers[x as usize] = self.registers[x as usize].wrapping_add(nn as u8); 
-            },
+    /// The sound timer, which counts down at 60 Hz and beeps until it is 0.
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
 
-            // 10. 8XY0 - VX = VY
-            (8, x, y, 0) => {
-                println!("Opcode: {:#06x} {}", opcode, self);
-                self.registers[x as usize] = self.registers[y as usize];
-            },
+    /// How long until the delay timer gets to 0, if nothing sets it again.
+    pub fn delay_remaining(&self) -> Duration {
+        Duration::from_secs_f64(self.delay_timer as f64 / 60.0)
+    }
 
-            // 11. 8XY1, 8XY2, 8XY3 - VX _ VY = VX, _ is OR, AND, XOR
-            (8, x, y, n @ 1..=3) => {
-                println!("Opcode: {:#06x} {}", opcode, self);
-                let (x, y) = (x as usize, y as usize);
-                match n {
-                    0x1 => self.registers[x] |= self.registers[y],
-                    0x2 => self.registers[x] &= self.registers[y],
-                    0x3 => self.registers[x] ^= self.registers[y],
-                    _ => panic!("This is impossible to reach.")
-                }
-            },
+    /// How long the beep will last, if nothing sets the sound timer again.
+    /// Audio backends can queue a tone that long instead of checking every
+    /// frame.
+    pub fn sound_remaining(&self) -> Duration {
+        Duration::from_secs_f64(self.sound_timer as f64 / 60.0)
+    }
 
-            // 12. 8XY4 - ADD VX + VY - If VX overflows, set VF to 1
-            (8, x, y, 4) => {
-                println!("Opcode: {:#06x} {}", opcode, self);
-                let (x, y) = (x as usize, y as usize);
-                let (result, overflow) =
-                    self.registers[x]
-                    .overflowing_add(self.registers[y]);
+    /// Whether the sound timer is running, and the beep should be playing.
+    pub fn is_beeping(&self) -> bool {
+        self.sound_timer > 0
+    }
 
-                let overflow = if overflow {1} else {0};
+    /// Let `elapsed` time pass for the timers, ticking them once for every
+    /// full 60th of a second. The leftover time is kept for the next call,
+    /// so the timers run at the right speed whatever the frame rate.
+    pub fn advance_timers(&mut self, elapsed: Duration) {
+        self.timer_elapsed += elapsed;
 
-                self.registers[0xF] = overflow;
-                self.registers[x] = result;
-            },
+        while self.timer_elapsed >= TIMER_PERIOD {
+            self.timer_elapsed -= TIMER_PERIOD;
+            self.tick_timers();
+        }
+    }
 
-            // 13. 8XY5 - SUB VX - VY
-            (8, x, y, 5) => {
-                println!("Opcode: {:#06x} {}", opcode, self);
-                let (x, y) = (x as usize, y as usize);
-                let (result, underflow) =
-                    self.registers[x]
-                    .overflowing_sub(self.registers[y]);
-                
-                let underflow = if underflow {0} else {1};
-
-                self.registers[0xF] = underflow;
-                self.registers[x] = result;
-            },
+    /// The width and height of the screen in the current mode.
+    ///
+    /// This is the shape of the buffer `get_display` returns, row by row.
+    pub fn display_dimensions(&self) -> (usize, usize) {
+        if self.hires {
+            (HIRES_DISPLAY_WIDTH, HIRES_DISPLAY_HEIGHT)
+        } else if self.hires_chip8 {
+            (DISPLAY_MEM_WIDTH, HIRES_CHIP8_DISPLAY_HEIGHT)
+        } else {
+            (DISPLAY_MEM_WIDTH, DISPLAY_MEM_HEIGHT)
+        }
+    }
 
-            // 14. 8XY6 - VX >>= 1 - Bitwise shift VX by 1, and store the dropped bit in VF
-            (8, x, _, 6) => {
-                println!("Opcode: {:#06x} {}", opcode, self);
-                let x = x as usize;
-                
-                // The 1 here is inferred to be an u8, since it cannot be anything else.
-                // 1 as u8 is 0000 0001, so we get the last digit
-                let dropped = self.registers[x] & 1; 
-
-                self.registers[x] >>= 1;
-                self.registers[0xF] = dropped;
-            },
+    /// Switch between the low and high resolution modes, clearing the screen.
+    fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        self.hires_chip8 = false;
+        self.resize_display();
+    }
 
-            // 15. 8XY7 - SUB VY - VX  - If VX underflows, clear VF
-            (8, x, y, 7) => {
-                println!("Opcode: {:#06x} {}", opcode, self);
-                let (x, y) = (x as usize, y as usize);
-                let (result, underflow) =
-                    self.registers[x]
-                    .overflowing_sub(self.registers[y]);
-                
-                let underflow = if underflow {0} else {1};
-
-                self.registers[0xF] = underflow;
-                self.registers[x] = result;
-            },
+    /// Switch to the 64x64 HIRES CHIP-8 mode, clearing the screen.
+    fn set_hires_chip8(&mut self) {
+        self.hires = false;
+        self.hires_chip8 = true;
+        self.resize_display();
+    }
 
-            // 16. 8XY6 - VX >>= 1 - Bitwise shift VX by 1, and store the dropped bit in VF
-            (8, x, _, 0xE) => {
-                println!("Opcode: {:#06x} {}", opcode, self);
-                let x = x as usize;
-                
-                // Same as above, but we move the first digit to the last position,
-                // so we don't have to write 1000 0000 (2^8 = 256)
-                let dropped = (self.registers[x] >> 7) & 1;
-
-                self.registers[x] <<= 1;
-                self.registers[0xF] = dropped;
-            },
+    /// Make the display planes as big as the current mode, and blank.
+    fn resize_display(&mut self) {
+        let (width, height) = self.display_dimensions();
+        self.display = Bitplane::new(width, height);
+        self.second_plane = Bitplane::new(width, height);
+        self.sync_display_mirror();
+    }
 
-            // 17. 9XY0 - Skip if VX != VY
-            (9, x, y, 0) => {
-                println!("Opcode: {:#06x} {}", opcode, self);
-                if self.registers[x as usize] != self.registers[y as usize] {
-                    self.program_counter += 2; // 2 as we skip 2 bytes, so 1 opcode
-                }
-            },
+    /// Move the selected planes `dx` columns right and `dy` rows down (left
+    /// and up when negative). What goes past an edge is lost.
+    fn scroll_planes(&mut self, dx: isize, dy: isize) {
+        if self.plane_mask & 0b01 != 0 {
+            self.display.scroll(dx, dy);
+        }
+        if self.plane_mask & 0b10 != 0 {
+            self.second_plane.scroll(dx, dy);
+        }
+        self.sync_display_mirror();
+    }
 
-            // 18. ANNN - Set I to 0xNNN
-            (0xA, ..) => {
-                println!("Opcode: {:#06x} {}", opcode, self);
-                let nnn: u16 = opcode & 0xFFF;
+    /// How many pixels of the current mode a scroll by `n` moves, see
+    /// `Quirks::schip_lores_scroll_half`.
+    fn scroll_distance(&self, n: u8) -> isize {
+        if !self.hires && self.quirks.schip_lores_scroll_half {
+            (n / 2) as isize
+        } else {
+            n as isize
+        }
+    }
 
-                self.i_register = nnn;
-            },
+    /// Keep a copy of the screen in RAM from `DISPLAY_MIRROR_ADDRESS` on,
+    /// updated after every draw, for programs that read the framebuffer.
+    ///
+    /// The pixels are packed 8 to a byte, most significant bit first, row
+    /// after row. The 128x64 SUPER-CHIP screen takes 1 KiB, so past the end
+    /// of the 4 KiB RAM it is cut short.
+    pub fn set_display_mirror(&mut self, enabled: bool) {
+        self.display_mirror = enabled;
+        self.sync_display_mirror();
+
+        // The program reads the mirror, but never writes it
+        #[cfg(feature = "poison")]
+        if let (true, Some(poison)) = (enabled, &mut self.poison) {
+            let start = DISPLAY_MIRROR_ADDRESS as usize;
+            poison.mark_ram(start..(start + self.display.pixels().len() / 8).min(self.ram.len()));
+        }
+    }
 
-            // 19. BNNN - Jump to address V0 + NNN
-            (0xB, ..) => {
-                println!("Opcode: {:#06x} {}", opcode, self);
-                let nnn: u16 = opcode & 0xFFF;
-                self.program_counter = self.registers[0] as u16 + nnn;
-            },
+    /// Keep track of which pixels each draw turns off, not just whether any
+    /// did, see `last_collisions`. Off by default, to spare the bookkeeping.
+    pub fn set_collision_tracking(&mut self, enabled: bool) {
+        self.track_collisions = enabled;
+        self.last_collisions.clear();
+    }
 
-            // 20. CXNN - Make a random number and AND it in VX
-            (0xC, x, ..) => {
-                println!("Opcode: {:#06x} {}", opcode, self);
-                let random_num: u8 = random();
-                let nn = (opcode & 0xFF) as u8;
+    /// The pixels (as indices into `get_display`) that the last `DXYN` turned
+    /// off. With XO-CHIP, collisions on the second plane are in there too.
+    ///
+    /// Always empty unless `set_collision_tracking` is on.
+    pub fn last_collisions(&self) -> &[usize] {
+        &self.last_collisions
+    }
 
-                self.registers[x as usize] = random_num & nn; 
-            },
+    /// Copy the packed display to the mirror region, if it is enabled.
+    fn sync_display_mirror(&mut self) {
+        if !self.display_mirror {
+            return;
+        }
 
-            // 21. DXYN - Draw n bytes from I at coordinates (VX, VY)
-            // Set VF if any pixels were flipped by this action.
-            (0xD, x, y, rows) => {
-                println!("Opcode: {:#06x} {}", opcode, self);
-                let coord_x = self.registers[x as usize] as u16;
-                let coord_y = self.registers[y as usize] as u16;
-
-                let mut flipped = false;
-
-                for y_line in 0..rows {
-                    // Get the pixels we have to draw
-                    let row_address = self.i_register + y_line as u16;
-                    let pixels = self.ram[row_address as usize];
-
-                    for x_line in 0..8 {
-                        // We can now check for collisions and update the display
-                        // Get to the pixel we are working on...
-                        // We use a 1-bit mask that we move around to get
-                        // the value of our pixel. If it is 1, we have to flip.
-                        if (pixels & (0b10000000 >> x_line)) != 0 {
-                            // The sprite can wrap the screen. so we use the modulo
-                            // to go back to the beginning if we do "overflow".
-                            let x = (coord_x + x_line) as usize % DISPLAY_MEM_WIDTH;
-                            let y = (coord_y + y_line) as usize % DISPLAY_MEM_HEIGHT;
-
-                            // Get the coordinate of the pixel in the screen
-                            // remember that it is a 1-D array.
-                            let position = x + DISPLAY_MEM_WIDTH * y;
-
-                            flipped |= self.display[position]; // Make it true if it is not already
-                            self.display[position] ^= true; // XOR on the current pixel
-                        }
-                    }
-                }
+        let (width, height) = self.display_dimensions();
+        let start = DISPLAY_MIRROR_ADDRESS as usize;
+        let mirror = self.ram[start..].iter_mut();
 
-                // If we did flip, VX has to be set to 1
-                self.registers[0xF] = if flipped {1} else {0};
-            },
+        // The rows are already packed the same way, we only have to cut
+        // them to the width of the screen
+        let packed = (0..height).flat_map(|y| {
+            let bytes = self.display.row(y).to_be_bytes();
+            bytes.into_iter().take(width / 8)
+        });
 
-            // 22. EX9E - Skip if the key indexed at VX is currently pressed
-            (0xE, x, 9, 0xE) => {
-                println!("Opcode: {:#06x} {}", opcode, self);
-                if self.keypad[(self.registers[x as usize]) as usize] {
-                    self.program_counter += 2
-                }
-            },
+        for (byte, pixels) in mirror.zip(packed) {
+            *byte = pixels;
+        }
+    }
 
-            // 23. EXA1 - Skip if the key indexed at VX is currently unpressed
-            (0xE, x, 0xA, 1) => {
-                println!("Opcode: {:#06x} {}", opcode, self);
-                if self.keypad[(self.registers[x as usize]) as usize] {
-                    self.program_counter += 2
-                }
-            },
+    /// Draw a sprite, `sprite_width` (8 or 16) pixels wide and `rows` tall, read
+    /// from `address`, onto one of the display planes. Returns true if any
+    /// pixel was turned off, and how many rows either turned a pixel off or
+    /// were clipped at the bottom (for `Quirks::schip_drw_vf_rows`).
+    fn draw_sprite(&mut self, plane: usize, coord_x: u16, coord_y: u16, address: u16, rows: u16, sprite_width: u16) -> (bool, u16) {
+        let (width, height) = self.display_dimensions();
+        let wrapping = self.quirks.sprite_wrapping;
+        let track_collisions = self.track_collisions;
+        let display = if plane == 0 { &mut self.display } else { &mut self.second_plane };
+
+        // The starting position always wraps, even when clipping the sprite.
+        let coord_x = coord_x as usize % width;
+        let coord_y = coord_y as usize % height;
+
+        let mut flipped = false;
+        let mut hit_rows = 0;
+
+        let row_bytes = (sprite_width / 8) as usize;
+        let sprite_width = sprite_width as usize;
+
+        for y_line in 0..rows as usize {
+            let y = coord_y + y_line;
+            if !wrapping && y >= height {
+                // This row is off the screen, and gets clipped, and so
+                // do the ones after it.
+                hit_rows += rows - y_line as u16;
+                break;
+            }
+            // The sprite can wrap the screen. so we use the modulo
+            // to go back to the beginning if we do "overflow".
+            let y = y % height;
+
+            // Read the row of the sprite, and line it up with the left
+            // edge of the screen. The address is masked into the RAM, so
+            // a sprite near the end carries on from the start...
+            let start = address as usize + y_line * row_bytes;
+            let sprite_row = (start..start + row_bytes)
+                .map(|address| self.ram[address % self.ram.len()])
+                .fold(0u128, |row, byte| (row << 8) | byte as u128);
+            let sprite_row = sprite_row << (128 - sprite_width);
+
+            // ...then move it over to VX. Whatever falls off the right edge
+            // (past `width`) is either clipped or comes back on the left.
+            let mut line = sprite_row >> coord_x;
+            if wrapping && coord_x + sprite_width > width {
+                line |= sprite_row.checked_shl((width - coord_x) as u32).unwrap_or(0);
+            }
 
-            // 24. FX07 - Set VX to the delay timer
-            (0xF, x, 0, 7) => {
-                println!("Opcode: {:#06x} {}", opcode, self);
-                self.registers[x as usize] = self.delay_timer;
-            },
+            let collided = display.xor_row(y, line);
+            if track_collisions {
+                self.last_collisions.extend(display::columns(collided).map(|x| x + width * y));
+            }
+            flipped |= collided != 0; // Make it true if it is not already
+            if collided != 0 {
+                hit_rows += 1;
+            }
+        }
 
-            // 25. FX0A - Wait for any keypress. Store the keypress index in VX
-            // The CPU here stops until this is the case
-            (0xF, x, 0, 0xA) => {
-                println!("Opcode: {:#06x} {}", opcode, self);
-                // I wanted to do this with a while loop, but the guide rightly 
-                // suggested re-doing the instruction instead, so that the
-                // `cycle` function can re-register new key presses.
-                let x = x as usize;
-
-                let mut pressed = false;
-
-                for i in 0..self.keypad.len() {
-                    if self.keypad[i] {
-                        self.registers[x] = i as u8;
-                        pressed = true;
-                        break
-                    }
-                }
+        (flipped, hit_rows)
+    }
 
-                if ! pressed {
-                    self.program_counter -= 2;
-                }
-            },
+    /// Load a ROM into the RAM at the point of execution.
+    ///
+    /// ROMs bigger than the space between 0x200 and the end of the RAM are
+    /// refused with `Chip8Error::RomTooLarge`, and nothing is loaded.
+    pub fn load_rom(&mut self, rom:&[u8]) -> Result<(), Chip8Error> {
+        self.load_rom_at(START_ADDRESS, rom)
+    }
 
-            // 26. FX15 - Set the delay timer to VX
-            (0xF, x, 1, 5) => {
-                println!("Opcode: {:#06x} {}", opcode, self);
-                self.delay_timer = self.registers[x as usize];
-            },
+    /// Assemble `source` (see `asm::assemble`) and load it, like `load_rom`.
+    /// Handy for test programs, which are much easier to read than hex.
+    pub fn load_asm(&mut self, source: &str) -> Result<(), Chip8Error> {
+        let rom = asm::assemble(source)?;
+        self.load_rom(&rom)
+    }
 
-            // 27. FX18 - Set the sound timer to VX
-            (0xF, x, 1, 8) => {
-                println!("Opcode: {:#06x} {}", opcode, self);
-                self.sound_timer = self.registers[x as usize];
-            },
+    /// Read the ROM at `path` and load it, like `load_rom`.
+    ///
+    /// With the `flate2` feature, gzipped ROMs (like `.ch8.gz` files) are
+    /// decompressed first.
+    #[cfg(feature = "std")]
+    pub fn load_rom_from_path<P: AsRef<Path>>(&mut self, path: P) -> Result<(), Chip8Error> {
+        let path = path.as_ref();
+        let rom = std::fs::read(path).map_err(|error| Chip8Error::Io {
+            kind: error.kind(),
+            message: format!("{}: {}", path.display(), error),
+        })?;
+
+        #[cfg(feature = "flate2")]
+        return self.load_rom_gz(&rom);
+        #[cfg(not(feature = "flate2"))]
+        return self.load_rom(&rom);
+    }
 
-            // 28. FX1E - Set I to I + VX
-            (0xF, x, 1, 0xE) => {
-                println!("Opcode: {:#06x} {}", opcode, self);
-                self.i_register = self.i_register.wrapping_add(self.registers[x as usize] as u16);
-            },
+    /// Decompress a gzipped ROM and load it, like `load_rom`.
+    ///
+    /// We look at the gzip magic bytes first, so plain ROMs are loaded
+    /// as they are.
+    #[cfg(feature = "flate2")]
+    pub fn load_rom_gz(&mut self, bytes: &[u8]) -> Result<(), Chip8Error> {
+        use std::io::Read;
 
-            // 29. FX29 - Set I to the position of the interpreter font character in VX
-            (0xF, x, 2, 9) => {
-                println!("Opcode: {:#06x} {}", opcode, self);
-                // The sprites are all 5 bytes long, and start at location 0
-                // in our ram. Therefore, to get their position, we multiply
-                // their value (in the register) by 5, and get the corresponding
-                // i_register position.
-                self.i_register = (self.registers[x as usize] as u16) * 5;
-            },
+        if !bytes.starts_with(&[0x1F, 0x8B]) {
+            return self.load_rom(bytes);
+        }
 
-            // 30. FX33 - Store the BCD encoding of VX into I
-            (0xF, x, 3, 3) => {
-                println!("Opcode: {:#06x} {}", opcode, self);
-                // The BCD is a pseudo-decimal representation of a hex, stored
-                // as a series of hex values. For instance, 0x64, equal to 100,
-                // would become 0x1 (1), 0x0 (0), 0x0 (0), so three bytes, one
-                // for each digit. As the values in our registers can go up to
-                // 2^8 -1 = 255, we will always store three hex-encoded digits
+        let mut rom = Vec::new();
+        flate2::read::GzDecoder::new(bytes).read_to_end(&mut rom).map_err(|error| {
+            Chip8Error::Io {
+                kind: error.kind(),
+                message: format!("bad gzip data: {}", error),
+            }
+        })?;
 
-                let reg_x = self.registers[x as usize] as f32;
+        self.load_rom(&rom)
+    }
 
-                let hundreds = (reg_x / 100f32).floor() as u8;
-                let tens = ((reg_x / 10f32) % 10f32) as u8;
-                let ones = (reg_x % 10f32) as u8;
+    /// Load a ROM into the RAM at `addr`, and start execution from there.
+    ///
+    /// Most programs start at 0x200, but some platforms (like the ETI-660,
+    /// at 0x600) use a different address.
+    pub fn load_rom_at(&mut self, addr: u16, rom: &[u8]) -> Result<(), Chip8Error> {
+        let start = addr as usize;
+        // The address itself might already be out of the RAM
+        let max = self.ram.len().saturating_sub(start);
+
+        if rom.len() > max {
+            return Err(Chip8Error::RomTooLarge { size: rom.len(), max });
+        }
 
-                self.ram[self.i_register as usize] = hundreds;
-                self.ram[(self.i_register + 1) as usize] = tens;
-                self.ram[(self.i_register + 2) as usize] = ones;
-            },
+        // Load whatever ROM is given to us into the RAM
+        let end = start + rom.len();
+        self.ram[start..end].copy_from_slice(rom);
+        self.program_counter = addr;
+        self.rom_end = end;
+
+        #[cfg(feature = "poison")]
+        if let Some(poison) = &mut self.poison {
+            poison.mark_ram(start..end);
+        }
 
-            // 31. FX55 - Store V0 to VX into the RAM, starting from address I
-            (0xF, x, 5, 5) => {
-                println!("Opcode: {:#06x} {}", opcode, self);
-                for i in 0..=x {
-                    self.registers[i as usize] = self.ram[(self.i_register + i) as usize];
-                }
-            },
+        Ok(())
+    }
+
+    /// Copy `image` over the RAM from 0x200 on, e.g. a program assembled in
+    /// memory or a data segment to splice in. Unlike `load_rom`, nothing
+    /// else changes: the program counter stays where it is, and the fonts
+    /// below 0x200 are never touched.
+    ///
+    /// An image running past the end of the RAM is refused with
+    /// `Chip8Error::OutOfBoundsMemory`, and nothing is copied.
+    pub fn set_ram(&mut self, image: &[u8]) -> Result<(), Chip8Error> {
+        let range = self.ram_range(START_ADDRESS, image.len())?;
+        self.ram[range.clone()].copy_from_slice(image);
+
+        #[cfg(feature = "poison")]
+        if let Some(poison) = &mut self.poison {
+            poison.mark_ram(range);
+        }
+
+        Ok(())
+    }
+
+    /// The `len` bytes of RAM from `start`, or the first address past the end
+    /// of the RAM as an error if they don't all fit.
+    fn ram_range(&self, start: u16, len: usize) -> Result<Range<usize>, Chip8Error> {
+        let range = start as usize..start as usize + len;
+        if range.end > self.ram.len() {
+            let first_outside = self.ram.len().max(range.start);
+            return Err(Chip8Error::OutOfBoundsMemory(first_outside.min(u16::MAX as usize) as u16));
+        }
+
+        Ok(range)
+    }
+
+    /// Read the byte at `addr`. Addresses outside of the RAM read as 0.
+    pub fn read_mem(&self, addr: u16) -> u8 {
+        self.ram.get(addr as usize).copied().unwrap_or(0)
+    }
 
-            // 32. FX65 - Fill V0 to VX with the RAM values starting from address I
-            (0xF, x, 6, 5) => {
-                println!("Opcode: {:#06x} {}", opcode, self);
-                for i in 0..=x {
-                    self.ram[(self.i_register + i) as usize] = self.registers[i as usize];
+    /// Overwrite the byte at `addr`, e.g. to patch a running ROM.
+    pub fn write_mem(&mut self, addr: u16, val: u8) -> Result<(), Chip8Error> {
+        match self.ram.get_mut(addr as usize) {
+            Some(byte) => {
+                *byte = val;
+                #[cfg(feature = "poison")]
+                if let Some(poison) = &mut self.poison {
+                    poison.mark_ram(addr as usize..addr as usize + 1);
                 }
+                Ok(())
             },
-
-            // Catch-all 
-            (_, _, _, _) => panic!("Unimplemented opcode: {}", opcode),
+            None => Err(Chip8Error::OutOfBoundsMemory(addr)),
         }
     }
 
-    /// Load a ROM into the RAM at the point of execution.
-    pub fn load_rom(&mut self, rom:&[u8]) {
-        // Load whatever ROM is given to us into the RAM
-        let start = START_ADDRESS as usize;
-        let end = (START_ADDRESS as usize) + rom.len();
-        self.ram[start..end].copy_from_slice(&rom);
+    /// How many bytes of RAM there are: 4 KiB, or 64 KiB with XO-CHIP.
+    pub fn ram_size(&self) -> usize {
+        self.ram.len()
+    }
+
+    /// Get a view of the RAM over `range`, cut short at the end of the RAM.
+    pub fn dump_mem(&self, range: Range<u16>) -> &[u8] {
+        let end = (range.end as usize).min(self.ram.len());
+        let start = (range.start as usize).min(end);
+
+        &self.ram[start..end]
     }
 
     pub fn get_display(&self) -> &[bool] {
-        &self.display
+        self.display.pixels()
     }
 
-    pub fn press_key(&mut self, key: Chip8Key) {
-        let id: usize = match key {
-            Chip8Key::K0 => 0,
-            Chip8Key::K1 => 1,
-            Chip8Key::K2 => 2,
-            Chip8Key::K3 => 3,
-            Chip8Key::K4 => 4,
-            Chip8Key::K5 => 5,
-            Chip8Key::K6 => 6,
-            Chip8Key::K7 => 7,
-            Chip8Key::K8 => 8,
-            Chip8Key::K9 => 9,
-            Chip8Key::KA => 10,
-            Chip8Key::KB => 11,
-            Chip8Key::KC => 12,
-            Chip8Key::KD => 13,
-            Chip8Key::KE => 14,
-            Chip8Key::KF => 15,
+    /// Whether the pixel at (`x`, `y`) is lit. Pixels outside of the screen,
+    /// in the current resolution, are always dark.
+    pub fn get_pixel(&self, x: usize, y: usize) -> bool {
+        let (width, height) = self.display_dimensions();
+        x < width && y < height && self.display.get(x, y)
+    }
+
+    /// Light up (or turn off) the pixel at (`x`, `y`), for tools that draw
+    /// on the screen. Pixels outside of the screen are ignored.
+    pub fn set_pixel(&mut self, x: usize, y: usize, on: bool) {
+        let (width, height) = self.display_dimensions();
+        if x < width && y < height {
+            self.display.set(x, y, on);
+            self.sync_display_mirror();
+        }
+    }
+
+    /// Get the pixels that changed since the last call, as (index, new value)
+    /// pairs, so front-ends only have to redraw those.
+    ///
+    /// If the resolution changed in the meantime, every pixel is reported.
+    pub fn display_changes(&mut self) -> Vec<(usize, bool)> {
+        let display = self.display.pixels();
+        let changes = if self.previous_display.len() == display.len() {
+            display
+                .iter()
+                .zip(&self.previous_display)
+                .enumerate()
+                .filter(|(_, (now, before))| now != before)
+                .map(|(index, (now, _))| (index, *now))
+                .collect()
+        } else {
+            display.iter().copied().enumerate().collect()
         };
 
-        self.keypad[id] = true;
+        self.previous_display.clear();
+        self.previous_display.extend_from_slice(display);
+
+        changes
+    }
+
+    /// Every register in hex, as a small table: V0 to VF on two rows, then
+    /// I, the program counter, the stack pointer and the two timers.
+    ///
+    /// ```text
+    /// V0=2a V1=00 V2=00 V3=00 V4=00 V5=00 V6=00 V7=00
+    /// V8=00 V9=00 VA=00 VB=00 VC=00 VD=00 VE=00 VF=01
+    /// I=0300 PC=0202 SP=00 DT=00 ST=00
+    /// ```
+    pub fn registers_dump(&self) -> String {
+        let mut dump = String::new();
+
+        for row in 0..2 {
+            let cells: Vec<String> = (row * 8..row * 8 + 8)
+                .map(|x| format!("V{:X}={:02x}", x, self.registers[x]))
+                .collect();
+            dump.push_str(&cells.join(" "));
+            dump.push('\n');
+        }
+        dump.push_str(&format!(
+            "I={:04x} PC={:04x} SP={:02x} DT={:02x} ST={:02x}\n",
+            self.i_register, self.program_counter, self.stack_ptr, self.delay_timer, self.sound_timer
+        ));
+
+        dump
+    }
+
+    /// Render the screen as ASCII art, `#` for lit pixels and `.` for dark
+    /// ones, one line per row. Much easier to read in a failing test than
+    /// an array of 2048 bools.
+    pub fn display_ascii(&self) -> String {
+        let (width, _) = self.display_dimensions();
+
+        self.display
+            .pixels()
+            .chunks(width)
+            .map(|row| {
+                let mut line: String = row.iter().map(|pixel| if *pixel { '#' } else { '.' }).collect();
+                line.push('\n');
+                line
+            })
+            .collect()
+    }
+
+    /// Show the current screen on `sink`. With XO-CHIP, both planes go
+    /// through `DisplaySink::present_colors`.
+    pub fn present(&self, sink: &mut impl DisplaySink) {
+        let (width, height) = self.display_dimensions();
+        if self.quirks.xo_chip {
+            sink.present_colors(&self.get_display_colors(), width, height);
+        } else {
+            sink.present(self.get_display(), width, height);
+        }
+    }
+
+    /// Get both XO-CHIP display planes, so front-ends can composite them.
+    ///
+    /// The first plane is the same buffer `get_display` returns.
+    pub fn get_display_planes(&self) -> (&[bool], &[bool]) {
+        (self.display.pixels(), self.second_plane.pixels())
+    }
+
+    /// Both planes composited, one color index per pixel, in the same order
+    /// as `get_display`: bit 0 is set where the first plane is on, bit 1
+    /// where the second is. XO-CHIP front-ends map the four to colors.
+    pub fn get_display_colors(&self) -> Vec<u8> {
+        let (first, second) = self.get_display_planes();
+        first.iter()
+            .zip(second)
+            .map(|(first, second)| *first as u8 | (*second as u8) << 1)
+            .collect()
+    }
+
+    /// Press a key. It stays down for `EX9E`, `EXA1` and `FX0A` until the
+    /// end of the frame, even if it is released sooner.
+    pub fn press_key(&mut self, key: Chip8Key) {
+        if !self.keypad[key.index()] {
+            self.key_latch[key.index()] = true;
+        }
+        self.keypad[key.index()] = true;
     }
 
     pub fn release_key(&mut self, key: Chip8Key) {
-        let id: usize = match key {
-            Chip8Key::K0 => 0,
-            Chip8Key::K1 => 1,
-            Chip8Key::K2 => 2,
-            Chip8Key::K3 => 3,
-            Chip8Key::K4 => 4,
-            Chip8Key::K5 => 5,
-            Chip8Key::K6 => 6,
-            Chip8Key::K7 => 7,
-            Chip8Key::K8 => 8,
-            Chip8Key::K9 => 9,
-            Chip8Key::KA => 10,
-            Chip8Key::KB => 11,
-            Chip8Key::KC => 12,
-            Chip8Key::KD => 13,
-            Chip8Key::KE => 14,
-            Chip8Key::KF => 15,
-        };
+        self.keypad[key.index()] = false;
+    }
 
-        self.keypad[id] = false;
+    /// Which keys are pressed, by keypad index.
+    pub fn keypad(&self) -> &[bool; 16] {
+        &self.keypad
+    }
+
+    /// Set the state of every key at once, by keypad index.
+    pub fn set_keypad(&mut self, keys: [bool; 16]) {
+        for (i, down) in keys.iter().enumerate() {
+            if *down && !self.keypad[i] {
+                self.key_latch[i] = true;
+            }
+        }
+        self.keypad = keys;
+    }
+
+    /// Tell the processor a frame is over, forgetting the keys that were
+    /// pressed and already released during it.
+    ///
+    /// A key can go down and up again between two polls of the keypad, and
+    /// `EX9E` or `FX0A` would never see it. So presses are latched until
+    /// the end of the frame: call this once per frame, after the cycles.
+    pub fn end_frame(&mut self) {
+        self.key_latch = [false; 16];
+    }
+
+    /// Whether the key at `index` is down, or was pressed this frame.
+    /// Indexes past F only look at their low nibble, like the keypad does.
+    fn is_key_down(&self, index: u8) -> bool {
+        let index = (index & 0xF) as usize;
+        self.keypad[index] || self.key_latch[index]
     }
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub enum Chip8Key {
     K0, K1, K2, K3, K4, K5, K6, K7, K8, K9, KA, KB, KC, KD, KE, KF
 }
 
+impl Chip8Key {
+    /// All the keys, in keypad index order.
+    const ALL: [Chip8Key; 16] = [
+        Chip8Key::K0, Chip8Key::K1, Chip8Key::K2, Chip8Key::K3,
+        Chip8Key::K4, Chip8Key::K5, Chip8Key::K6, Chip8Key::K7,
+        Chip8Key::K8, Chip8Key::K9, Chip8Key::KA, Chip8Key::KB,
+        Chip8Key::KC, Chip8Key::KD, Chip8Key::KE, Chip8Key::KF,
+    ];
+
+    /// The position of this key in the keypad, from 0x0 to 0xF.
+    pub fn index(self) -> usize {
+        // The variants are declared in keypad order, so the discriminant
+        // is already the index we want.
+        self as usize
+    }
+
+    /// Get the key at keypad position `i`, if there is one.
+    pub fn from_index(i: u8) -> Option<Chip8Key> {
+        Self::ALL.get(i as usize).copied()
+    }
+}
+
+/// The key at a keypad position, like `from_index`. Anything past 0xF is
+/// handed back as the error.
+impl TryFrom<u8> for Chip8Key {
+    type Error = u8;
+
+    fn try_from(i: u8) -> Result<Self, Self::Error> {
+        Self::from_index(i).ok_or(i)
+    }
+}
+
+/// The keypad position of a key, like `index`.
+impl From<Chip8Key> for u8 {
+    fn from(key: Chip8Key) -> u8 {
+        key as u8
+    }
+}
+
 #[cfg(test)]
 mod tests;
@@ -0,0 +1,175 @@
+// Poison mode: warnings for reads of registers and memory that the
+// program never wrote, a classic bug in hand-written ROMs (they happen to
+// work on interpreters that start with zeroes, and break elsewhere).
+//
+// We don't watch every access as it happens: `accesses` says what each
+// instruction reads and writes, and `cycle` checks that before running it.
+use std::ops::Range;
+
+use crate::{
+    decode, Chip8Processor, Hook, Instruction, BIG_FONT_ADDRESS, BIG_INTERPRETER_SPRITES, START_ADDRESS,
+};
+
+/// Something a program can read.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Location {
+    /// A general purpose register, V0 to VF.
+    Register(u8),
+    /// The I register.
+    I,
+    /// A byte of RAM.
+    Ram(u16),
+}
+
+/// A read of a location nothing was ever written to.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct UninitializedRead {
+    /// Where the instruction doing the read is.
+    pub pc: u16,
+    pub opcode: u16,
+    pub location: Location,
+}
+
+/// What the program wrote so far, and who to tell about the rest.
+#[derive(Clone, PartialEq, Debug)]
+pub(crate) struct Poison {
+    registers: u16, // Bit X is set once VX was written
+    i_register: bool, // Whether I was written
+    ram: Vec<bool>, // One flag per byte of RAM
+    hook: Hook<UninitializedRead>,
+}
+
+impl Poison {
+    /// Nothing written yet, but the fonts.
+    fn new(ram_size: usize, hook: Hook<UninitializedRead>) -> Self {
+        let mut poison = Self { registers: 0, i_register: false, ram: vec![false; ram_size], hook };
+        poison.mark_ram(0..BIG_FONT_ADDRESS as usize + BIG_INTERPRETER_SPRITES.len());
+        poison
+    }
+
+    /// The same hook, for a program starting over.
+    pub(crate) fn restarted(&self, ram_size: usize) -> Self {
+        Self::new(ram_size, self.hook.clone())
+    }
+
+    pub(crate) fn mark_ram(&mut self, range: Range<usize>) {
+        if self.ram.len() < range.end {
+            self.ram.resize(range.end, false);
+        }
+        self.ram[range].fill(true);
+    }
+}
+
+/// What one instruction touches.
+#[derive(Default)]
+struct Accesses {
+    reads: u16, // Bit X for VX
+    writes: u16,
+    reads_i: bool,
+    writes_i: bool,
+    ram_reads: Range<usize>,
+    ram_writes: Range<usize>,
+}
+
+/// V0 to VX, as a register mask.
+fn up_to(x: u8) -> u16 {
+    u16::MAX >> (15 - x)
+}
+
+impl Chip8Processor {
+    /// Call `hook` whenever the program reads a register, I or a byte of
+    /// RAM that it never wrote. Each location is only reported once.
+    ///
+    /// The fonts and the ROM count as written: load ROMs that don't start
+    /// at 0x200 after turning this on, so they are counted too.
+    pub fn on_uninitialized_read(&mut self, hook: impl FnMut(UninitializedRead) + Send + 'static) {
+        let mut poison = Poison::new(self.ram.len(), Hook::new(hook));
+        poison.mark_ram(START_ADDRESS as usize..self.rom_end.max(START_ADDRESS as usize));
+        self.poison = Some(poison);
+    }
+
+    /// Stop checking for uninitialized reads.
+    pub fn clear_poison(&mut self) {
+        self.poison = None;
+    }
+
+    /// Report the uninitialized locations `opcode` at `pc` reads, and mark
+    /// the ones it writes.
+    pub(crate) fn check_poison(&mut self, pc: u16, opcode: u16) {
+        let accesses = self.accesses(decode(opcode));
+        let Some(poison) = &mut self.poison else { return };
+
+        let mut unwritten = Vec::new();
+        for x in 0..16 {
+            if accesses.reads & !poison.registers & (1 << x) != 0 {
+                unwritten.push(Location::Register(x));
+            }
+        }
+        if accesses.reads_i && !poison.i_register {
+            unwritten.push(Location::I);
+        }
+        for address in accesses.ram_reads.clone() {
+            // Past the end of what we know, nothing was written either
+            if address < self.ram.len() && !poison.ram.get(address).copied().unwrap_or(false) {
+                unwritten.push(Location::Ram(address as u16));
+            }
+        }
+
+        for location in unwritten {
+            poison.hook.call(UninitializedRead { pc, opcode, location });
+        }
+
+        // Reported once is enough, so what we warned about counts as
+        // written from now on
+        poison.registers |= accesses.reads | accesses.writes;
+        poison.i_register |= accesses.reads_i || accesses.writes_i;
+        poison.mark_ram(accesses.ram_reads);
+        poison.mark_ram(accesses.ram_writes);
+    }
+
+    /// What `instruction` would read and write, if it ran now.
+    fn accesses(&self, instruction: Instruction) -> Accesses {
+        use Instruction::*;
+
+        let bit = |x: u8| 1u16 << x;
+        let at_i = |len: usize| self.i_register as usize..self.i_register as usize + len;
+        let vf = bit(0xF);
+
+        match instruction {
+            SeVxByte(x, _) | SneVxByte(x, _) | Skp(x) | Sknp(x) => Accesses { reads: bit(x), ..Default::default() },
+            SeVxVy(x, y) | SneVxVy(x, y) => Accesses { reads: bit(x) | bit(y), ..Default::default() },
+            LdVxByte(x, _) | Rnd(x, _) | LdVxDt(x) | LdVxK(x) => Accesses { writes: bit(x), ..Default::default() },
+            AddVxByte(x, _) => Accesses { reads: bit(x), writes: bit(x), ..Default::default() },
+            LdVxVy(x, y) => Accesses { reads: bit(y), writes: bit(x), ..Default::default() },
+            Or(x, y) | And(x, y) | Xor(x, y) => Accesses {
+                reads: bit(x) | bit(y),
+                writes: bit(x) | if self.quirks.logic_resets_vf { vf } else { 0 },
+                ..Default::default()
+            },
+            AddVxVy(x, y) | Sub(x, y) | Subn(x, y) => Accesses { reads: bit(x) | bit(y), writes: bit(x) | vf, ..Default::default() },
+            Shr(x, y) | Shl(x, y) => Accesses {
+                reads: if self.quirks.shift_uses_vy { bit(y) } else { bit(x) },
+                writes: bit(x) | vf,
+                ..Default::default()
+            },
+            JpV0(_) => Accesses { reads: bit(0), ..Default::default() },
+            LdI(_) | LdILong => Accesses { writes_i: true, ..Default::default() },
+            LdFVx(x) | LdHfVx(x) => Accesses { reads: bit(x), writes_i: true, ..Default::default() },
+            AddIVx(x) => Accesses { reads: bit(x), reads_i: true, writes_i: true, ..Default::default() },
+            Drw(x, y, n) => {
+                let sprite = if n == 0 && (self.quirks.schip || self.quirks.xo_chip) { 32 } else { n as usize };
+                let planes = (self.plane_mask & 0b11).count_ones() as usize;
+                Accesses { reads: bit(x) | bit(y), writes: vf, reads_i: true, ram_reads: at_i(planes * sprite), ..Default::default() }
+            },
+            LdDtVx(x) | LdStVx(x) | Pitch(x) => Accesses { reads: bit(x), ..Default::default() },
+            LdBVx(x) => Accesses { reads: bit(x), reads_i: true, ram_writes: at_i(3), ..Default::default() },
+            LdIVx(x) => Accesses { reads: up_to(x), reads_i: true, ram_writes: at_i(x as usize + 1), ..Default::default() },
+            LdVxI(x) => Accesses { writes: up_to(x), reads_i: true, ram_reads: at_i(x as usize + 1), ..Default::default() },
+            LdRVx(x) => Accesses { reads: up_to(x.min(7)), ..Default::default() },
+            LdVxR(x) => Accesses { writes: up_to(x.min(7)), ..Default::default() },
+            Audio => Accesses { reads_i: true, ram_reads: at_i(16), ..Default::default() },
+            Cls | Ret | Sys(_) | Jp(_) | Call(_) | Low | High | Exit | Plane(_) | Unknown(_) => Accesses::default(),
+            ScrollDown(_) | ScrollUp(_) | ScrollRight | ScrollLeft => Accesses::default(),
+        }
+    }
+}
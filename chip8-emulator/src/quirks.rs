@@ -0,0 +1,130 @@
+/// Behaviour switches for the places where CHIP-8 interpreters disagree.
+///
+/// The default is the classic CHIP-8 behaviour this emulator has always had.
+/// With the `serde` feature, missing fields take their default value.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize), serde(default, deny_unknown_fields))]
+pub struct Quirks {
+    /// Enable the XO-CHIP extensions: 64 KiB of RAM and the extra opcodes.
+    pub xo_chip: bool,
+    /// Enable the SUPER-CHIP extensions, like the 128x64 high resolution mode.
+    pub schip: bool,
+    /// `8XY6` and `8XYE` shift VY into VX, like the COSMAC VIP did, instead
+    /// of shifting VX in place.
+    pub shift_uses_vy: bool,
+    /// Sprites going past the edge of the screen wrap around to the other
+    /// side. When off, the parts past the edge are clipped instead, like on
+    /// the original hardware.
+    pub sprite_wrapping: bool,
+    /// `DXYN` waits for the vertical blank, like on the COSMAC VIP: after a
+    /// draw, the processor idles until the next `tick_timers`, so at most
+    /// one sprite is drawn per frame.
+    pub display_wait: bool,
+    /// `8XY1`, `8XY2` and `8XY3` clear VF, as a side effect of how the
+    /// COSMAC VIP ran them.
+    pub logic_resets_vf: bool,
+    /// In low resolution, the SUPER-CHIP scroll instructions move by half
+    /// as many pixels, like SUPER-CHIP 1.1 did: it scrolled by high
+    /// resolution pixels whatever the mode. When off, they move by pixels
+    /// of the current mode, like Octo.
+    pub schip_lores_scroll_half: bool,
+    /// Run the COSMAC VIP "HIRES" CHIP-8 programs: a ROM starting with
+    /// `1260` switches to a 64x64 screen and starts at 0x2C0, and `0230`
+    /// clears that screen. This has nothing to do with SUPER-CHIP.
+    pub hires_chip8: bool,
+    /// In high resolution, `DXYN` sets VF to the number of sprite rows
+    /// that turned a pixel off or were clipped at the bottom of the
+    /// screen, like SUPER-CHIP 1.1, instead of just 1 or 0.
+    pub schip_drw_vf_rows: bool,
+    /// `DXYN` rows past the end of the RAM are read from its start again,
+    /// like most interpreters do by masking the address. When off, a sprite
+    /// that does not fit in the RAM is a `Chip8Error::OutOfBoundsMemory`.
+    pub sprite_address_wrap: bool,
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Self {
+            xo_chip: false,
+            schip: false,
+            shift_uses_vy: false,
+            sprite_wrapping: true,
+            display_wait: false,
+            logic_resets_vf: false,
+            schip_lores_scroll_half: false,
+            hires_chip8: false,
+            schip_drw_vf_rows: false,
+            sprite_address_wrap: true,
+        }
+    }
+}
+
+// The presets follow the usual quirk table of the CHIP-8 test suites, for
+// the quirks we have switches for.
+impl Quirks {
+    /// The original CHIP-8 interpreter on the COSMAC VIP.
+    pub fn cosmac_vip() -> Self {
+        Self {
+            xo_chip: false,
+            schip: false,
+            shift_uses_vy: true,
+            sprite_wrapping: false,
+            display_wait: true,
+            logic_resets_vf: true,
+            schip_lores_scroll_half: false,
+            hires_chip8: false,
+            schip_drw_vf_rows: false,
+            sprite_address_wrap: true,
+        }
+    }
+
+    /// SUPER-CHIP 1.1 on the HP 48 calculators.
+    pub fn schip() -> Self {
+        Self {
+            xo_chip: false,
+            schip: true,
+            shift_uses_vy: false,
+            sprite_wrapping: false,
+            display_wait: false,
+            logic_resets_vf: false,
+            schip_lores_scroll_half: true,
+            hires_chip8: false,
+            schip_drw_vf_rows: true,
+            sprite_address_wrap: true,
+        }
+    }
+
+    /// XO-CHIP, as Octo runs it. It builds on top of SUPER-CHIP, so the
+    /// SUPER-CHIP instructions are there too.
+    pub fn xo_chip() -> Self {
+        Self {
+            xo_chip: true,
+            schip: true,
+            shift_uses_vy: true,
+            sprite_wrapping: true,
+            display_wait: false,
+            logic_resets_vf: false,
+            schip_lores_scroll_half: false,
+            hires_chip8: false,
+            schip_drw_vf_rows: false,
+            sprite_address_wrap: true,
+        }
+    }
+
+    /// What most modern CHIP-8 interpreters do, which is also our default.
+    pub fn modern() -> Self {
+        Self::default()
+    }
+
+    /// The preset called `name`: "cosmac-vip" (or "vip"), "schip",
+    /// "xo-chip" or "modern".
+    pub fn preset(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "cosmac-vip" | "vip" => Some(Self::cosmac_vip()),
+            "schip" => Some(Self::schip()),
+            "xo-chip" => Some(Self::xo_chip()),
+            "modern" => Some(Self::modern()),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,24 @@
+/// Somewhere to show the screen on, so the core does not need to know
+/// about SDL, terminals or anything else a front-end might use.
+pub trait DisplaySink {
+    /// Show a frame. `pixels` holds `height` rows of `width` pixels each.
+    fn present(&mut self, pixels: &[bool], width: usize, height: usize);
+
+    /// Show an XO-CHIP frame, with a color index from 0 to 3 per pixel (see
+    /// `Chip8Processor::get_display_colors`).
+    ///
+    /// Sinks with only two colors don't have to do anything: they get the
+    /// first plane, like `present` would.
+    fn present_colors(&mut self, colors: &[u8], width: usize, height: usize) {
+        let pixels: Vec<bool> = colors.iter().map(|color| color & 0b01 != 0).collect();
+        self.present(&pixels, width, height);
+    }
+}
+
+/// A sink that throws every frame away, for running headless.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NullSink;
+
+impl DisplaySink for NullSink {
+    fn present(&mut self, _pixels: &[bool], _width: usize, _height: usize) {}
+}
@@ -0,0 +1,460 @@
+use std::time::Duration;
+
+use crate::display::Bitplane;
+use crate::{
+    Chip8Error, Chip8Processor, Quirks, DISPLAY_MEM_HEIGHT, DISPLAY_MEM_WIDTH, DISPLAY_MIRROR_ADDRESS,
+    HIRES_CHIP8_DISPLAY_HEIGHT, HIRES_DISPLAY_HEIGHT, HIRES_DISPLAY_WIDTH, RAM_SIZE, XO_CHIP_RAM_SIZE,
+};
+
+/// A copy of everything a program can change in a `Chip8Processor`, to go
+/// back to later with `Chip8Processor::restore`.
+///
+/// The RNG, breakpoints and other debugging state are not part of it:
+/// restoring a snapshot leaves them as they are.
+///
+/// It is `Clone` but not `Copy`: the RAM and the display planes are sized
+/// at run time (XO-CHIP has 64 KiB, SUPER-CHIP a bigger screen), so they
+/// live on the heap. The undo history also keeps its snapshots with an
+/// empty RAM, which a fixed-size array couldn't do.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Chip8Snapshot {
+    ram: Vec<u8>,
+    rom_end: usize,
+    registers: [u8; 16],
+    i_register: u16,
+    program_counter: u16,
+    stack: [u16; 16],
+    stack_ptr: u8,
+    flags: [u8; 8],
+    halted: bool,
+    keypad: [bool; 16],
+    key_latch: [bool; 16],
+    waiting_for_key: bool,
+    display: Bitplane,
+    second_plane: Bitplane,
+    plane_mask: u8,
+    hires: bool,
+    hires_chip8: bool,
+    delay_timer: u8,
+    sound_timer: u8,
+    timer_elapsed: Duration,
+    vblank_wait: bool,
+    cycles_since_tick: usize,
+    audio_pattern: [u8; 16],
+    pitch: u8,
+    quirks: Quirks,
+}
+
+/// What it takes to undo one instruction: the whole state but the RAM, and
+/// only the parts of the RAM an instruction can write.
+#[derive(Clone, PartialEq, Debug)]
+pub(crate) struct UndoStep {
+    state: Chip8Snapshot, // With an empty RAM
+    ram_patches: Vec<(usize, Vec<u8>)>, // (start, bytes) to copy back
+}
+
+impl Chip8Processor {
+    /// Take a snapshot of the current state.
+    pub fn snapshot(&self) -> Chip8Snapshot {
+        self.snapshot_with_ram(self.ram.clone())
+    }
+
+    fn snapshot_with_ram(&self, ram: Vec<u8>) -> Chip8Snapshot {
+        Chip8Snapshot {
+            ram,
+            rom_end: self.rom_end,
+            registers: self.registers,
+            i_register: self.i_register,
+            program_counter: self.program_counter,
+            stack: self.stack,
+            stack_ptr: self.stack_ptr,
+            flags: self.flags,
+            halted: self.halted,
+            keypad: self.keypad,
+            key_latch: self.key_latch,
+            waiting_for_key: self.waiting_for_key,
+            display: self.display.clone(),
+            second_plane: self.second_plane.clone(),
+            plane_mask: self.plane_mask,
+            hires: self.hires,
+            hires_chip8: self.hires_chip8,
+            delay_timer: self.delay_timer,
+            sound_timer: self.sound_timer,
+            timer_elapsed: self.timer_elapsed,
+            vblank_wait: self.vblank_wait,
+            cycles_since_tick: self.cycles_since_tick,
+            audio_pattern: self.audio_pattern,
+            pitch: self.pitch,
+            quirks: self.quirks,
+        }
+    }
+
+    /// Go back to the state in `snapshot`.
+    ///
+    /// The RAM and display buffers are reused, so restoring over and over
+    /// (e.g. while fuzzing) does not allocate.
+    pub fn restore(&mut self, snapshot: &Chip8Snapshot) {
+        self.ram.clone_from(&snapshot.ram);
+        self.restore_without_ram(snapshot);
+    }
+
+    fn restore_without_ram(&mut self, snapshot: &Chip8Snapshot) {
+        self.rom_end = snapshot.rom_end;
+        self.registers = snapshot.registers;
+        self.i_register = snapshot.i_register;
+        self.program_counter = snapshot.program_counter;
+        self.stack = snapshot.stack;
+        self.stack_ptr = snapshot.stack_ptr;
+        self.flags = snapshot.flags;
+        self.halted = snapshot.halted;
+        self.keypad = snapshot.keypad;
+        self.key_latch = snapshot.key_latch;
+        self.waiting_for_key = snapshot.waiting_for_key;
+        self.display.clone_from(&snapshot.display);
+        self.second_plane.clone_from(&snapshot.second_plane);
+        self.plane_mask = snapshot.plane_mask;
+        self.hires = snapshot.hires;
+        self.hires_chip8 = snapshot.hires_chip8;
+        self.delay_timer = snapshot.delay_timer;
+        self.sound_timer = snapshot.sound_timer;
+        self.timer_elapsed = snapshot.timer_elapsed;
+        self.vblank_wait = snapshot.vblank_wait;
+        self.cycles_since_tick = snapshot.cycles_since_tick;
+        self.audio_pattern = snapshot.audio_pattern;
+        self.pitch = snapshot.pitch;
+        self.quirks = snapshot.quirks;
+    }
+
+    /// Save what the next instruction could change. Instructions only write
+    /// the RAM at I (`FX33` and `FX55`, 16 bytes at most) and in the display
+    /// mirror, so that's all the RAM we keep.
+    pub(crate) fn undo_step(&self) -> UndoStep {
+        let mut regions = vec![(self.i_register as usize, 16)];
+        if self.display_mirror {
+            regions.push((DISPLAY_MIRROR_ADDRESS as usize, self.display.pixels().len() / 8));
+        }
+
+        let ram_patches = regions
+            .into_iter()
+            .filter(|(start, _)| *start < self.ram.len())
+            .map(|(start, len)| {
+                let end = (start + len).min(self.ram.len());
+                (start, self.ram[start..end].to_vec())
+            })
+            .collect();
+
+        UndoStep { state: self.snapshot_with_ram(Vec::new()), ram_patches }
+    }
+
+    /// Go back to before the instruction `step` was saved for.
+    pub(crate) fn apply_undo_step(&mut self, step: &UndoStep) {
+        for (start, bytes) in &step.ram_patches {
+            self.ram[*start..*start + bytes.len()].copy_from_slice(bytes);
+        }
+        self.restore_without_ram(&step.state);
+    }
+}
+
+// Save states: a snapshot as bytes, to keep in a file.
+//
+// The layout is the fields of `Chip8Snapshot` one after the other, in
+// little-endian, after a magic number and a version byte:
+//
+//  "C8ST" version ram_len:u32 ram registers I PC stack SP flags halted
+//  keypad:u16 key_latch:u16 waiting mode plane_mask display second_plane
+//  delay sound timer_elapsed:u64(ns) vblank_wait cycles_since_tick:u64
+//  audio_pattern pitch quirks:u32 rom_end:u32
+//
+// Booleans are a byte, the keys and the quirks are bitmasks, and each
+// display plane is its rows, packed 8 pixels to a byte. The screen mode
+// is 0 for 64x32, 1 for the SUPER-CHIP 128x64 and 2 for the HIRES CHIP-8
+// 64x64.
+//
+// New fields go at the end, and bump the version. Older states are still
+// read, with a default for what they lack:
+//
+//  1: no rom_end, so the end of the ROM isn't known (0, see `fetch`)
+//  2: no HIRES CHIP-8 mode, but the layout is the same as 3
+const STATE_MAGIC: &[u8; 4] = b"C8ST";
+const STATE_VERSION: u8 = 3;
+
+/// Writes the fields of a save state.
+struct StateWriter(Vec<u8>);
+
+impl StateWriter {
+    fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.0.extend_from_slice(bytes);
+    }
+
+    fn bits(&mut self, bits: &[bool]) {
+        let mask = bits.iter().rev().fold(0u16, |mask, bit| (mask << 1) | *bit as u16);
+        self.u16(mask);
+    }
+
+    fn plane(&mut self, plane: &Bitplane) {
+        for y in 0..plane.height() {
+            self.bytes(&plane.row(y).to_be_bytes()[..plane.width() / 8]);
+        }
+    }
+}
+
+/// Reads the fields of a save state back, refusing to go past the end.
+struct StateReader<'a>(&'a [u8]);
+
+impl StateReader<'_> {
+    fn bytes(&mut self, len: usize) -> Result<&[u8], Chip8Error> {
+        if self.0.len() < len {
+            return Err(Chip8Error::InvalidState("the state is cut short".to_string()));
+        }
+        let (bytes, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], Chip8Error> {
+        Ok(self.bytes(N)?.try_into().unwrap())
+    }
+
+    fn u8(&mut self) -> Result<u8, Chip8Error> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn bool(&mut self) -> Result<bool, Chip8Error> {
+        Ok(self.u8()? != 0)
+    }
+
+    fn u16(&mut self) -> Result<u16, Chip8Error> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, Chip8Error> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn u64(&mut self) -> Result<u64, Chip8Error> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    fn bits(&mut self) -> Result<[bool; 16], Chip8Error> {
+        let mask = self.u16()?;
+        Ok(std::array::from_fn(|bit| mask & (1 << bit) != 0))
+    }
+
+    fn plane(&mut self, width: usize, height: usize) -> Result<Bitplane, Chip8Error> {
+        let rows = (0..height)
+            .map(|_| {
+                let row = self.bytes(width / 8)?;
+                let mut bytes = [0; 16];
+                bytes[..row.len()].copy_from_slice(row);
+                Ok(u128::from_be_bytes(bytes))
+            })
+            .collect::<Result<_, Chip8Error>>()?;
+
+        Ok(Bitplane::from_rows(width, rows))
+    }
+}
+
+/// The quirks as a bitmask, in the order of the fields.
+fn quirk_bits(quirks: &Quirks) -> u32 {
+    [
+        quirks.xo_chip,
+        quirks.schip,
+        quirks.shift_uses_vy,
+        quirks.sprite_wrapping,
+        quirks.display_wait,
+        quirks.logic_resets_vf,
+        quirks.schip_lores_scroll_half,
+        quirks.hires_chip8,
+        quirks.schip_drw_vf_rows,
+        quirks.sprite_address_wrap,
+    ]
+    .iter()
+    .rev()
+    .fold(0, |bits, quirk| (bits << 1) | *quirk as u32)
+}
+
+fn quirks_from_bits(bits: u32) -> Quirks {
+    let bit = |index: u32| bits & (1 << index) != 0;
+    Quirks {
+        xo_chip: bit(0),
+        schip: bit(1),
+        shift_uses_vy: bit(2),
+        sprite_wrapping: bit(3),
+        display_wait: bit(4),
+        logic_resets_vf: bit(5),
+        schip_lores_scroll_half: bit(6),
+        hires_chip8: bit(7),
+        schip_drw_vf_rows: bit(8),
+        sprite_address_wrap: bit(9),
+    }
+}
+
+impl Chip8Snapshot {
+    /// The snapshot as a save state, for `from_bytes` to read back.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut state = StateWriter(Vec::with_capacity(self.ram.len() + 1024));
+
+        state.bytes(STATE_MAGIC);
+        state.u8(STATE_VERSION);
+        state.u32(self.ram.len() as u32);
+        state.bytes(&self.ram);
+        state.bytes(&self.registers);
+        state.u16(self.i_register);
+        state.u16(self.program_counter);
+        for value in self.stack {
+            state.u16(value);
+        }
+        state.u8(self.stack_ptr);
+        state.bytes(&self.flags);
+        state.u8(self.halted as u8);
+        state.bits(&self.keypad);
+        state.bits(&self.key_latch);
+        state.u8(self.waiting_for_key as u8);
+        state.u8(if self.hires { 1 } else if self.hires_chip8 { 2 } else { 0 });
+        state.u8(self.plane_mask);
+        state.plane(&self.display);
+        state.plane(&self.second_plane);
+        state.u8(self.delay_timer);
+        state.u8(self.sound_timer);
+        state.u64(self.timer_elapsed.as_nanos() as u64);
+        state.u8(self.vblank_wait as u8);
+        state.u64(self.cycles_since_tick as u64);
+        state.bytes(&self.audio_pattern);
+        state.u8(self.pitch);
+        state.u32(quirk_bits(&self.quirks));
+        state.u32(self.rom_end as u32);
+
+        state.0
+    }
+
+    /// Read a save state written by `to_bytes`, by this version or an
+    /// older one. States from newer versions are refused with
+    /// `Chip8Error::UnsupportedStateVersion`.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Chip8Error> {
+        let mut state = StateReader(bytes);
+
+        if state.bytes(4).ok() != Some(STATE_MAGIC) {
+            return Err(Chip8Error::InvalidState("not a save state".to_string()));
+        }
+        let version = state.u8()?;
+        if version > STATE_VERSION {
+            return Err(Chip8Error::UnsupportedStateVersion(version));
+        }
+        if version == 0 {
+            return Err(Chip8Error::InvalidState("unknown version 0".to_string()));
+        }
+
+        let ram_len = state.u32()? as usize;
+        let ram = state.bytes(ram_len)?.to_vec();
+        let registers = state.array()?;
+        let i_register = state.u16()?;
+        let program_counter = state.u16()?;
+        let mut stack = [0; 16];
+        for value in &mut stack {
+            *value = state.u16()?;
+        }
+        let stack_ptr = state.u8()?;
+        let flags = state.array()?;
+        let halted = state.bool()?;
+        let keypad = state.bits()?;
+        let key_latch = state.bits()?;
+        let waiting_for_key = state.bool()?;
+        let (hires, hires_chip8) = match state.u8()? {
+            0 => (false, false),
+            1 => (true, false),
+            2 if version >= 3 => (false, true),
+            mode => return Err(Chip8Error::InvalidState(format!("unknown screen mode {}", mode))),
+        };
+        let plane_mask = state.u8()?;
+        let (width, height) = if hires {
+            (HIRES_DISPLAY_WIDTH, HIRES_DISPLAY_HEIGHT)
+        } else if hires_chip8 {
+            (DISPLAY_MEM_WIDTH, HIRES_CHIP8_DISPLAY_HEIGHT)
+        } else {
+            (DISPLAY_MEM_WIDTH, DISPLAY_MEM_HEIGHT)
+        };
+        let display = state.plane(width, height)?;
+        let second_plane = state.plane(width, height)?;
+
+        let mut snapshot = Chip8Snapshot {
+            ram,
+            rom_end: 0,
+            registers,
+            i_register,
+            program_counter,
+            stack,
+            stack_ptr,
+            flags,
+            halted,
+            keypad,
+            key_latch,
+            waiting_for_key,
+            display,
+            second_plane,
+            plane_mask,
+            hires,
+            hires_chip8,
+            delay_timer: state.u8()?,
+            sound_timer: state.u8()?,
+            timer_elapsed: Duration::from_nanos(state.u64()?),
+            vblank_wait: state.bool()?,
+            cycles_since_tick: state.u64()? as usize,
+            audio_pattern: state.array()?,
+            pitch: state.u8()?,
+            quirks: quirks_from_bits(state.u32()?),
+        };
+        if version >= 2 {
+            snapshot.rom_end = state.u32()? as usize;
+        }
+
+        if snapshot.stack_ptr as usize > snapshot.stack.len() {
+            return Err(Chip8Error::InvalidState("the stack pointer is past the stack".to_string()));
+        }
+        // The rest of the processor counts on the RAM having the size of
+        // the platform
+        let ram_size = if snapshot.quirks.xo_chip { XO_CHIP_RAM_SIZE } else { RAM_SIZE };
+        if snapshot.ram.len() != ram_size {
+            return Err(Chip8Error::InvalidState(format!(
+                "the RAM is {} bytes, not {}",
+                snapshot.ram.len(),
+                ram_size
+            )));
+        }
+
+        Ok(snapshot)
+    }
+}
+
+impl Chip8Processor {
+    /// Save the current state as bytes, e.g. to write to a file.
+    ///
+    /// This is `snapshot` in the save state format, see
+    /// `Chip8Snapshot::to_bytes`.
+    pub fn save_state_binary(&self) -> Vec<u8> {
+        self.snapshot().to_bytes()
+    }
+
+    /// Go back to a state saved with `save_state_binary`. Nothing changes
+    /// if the state can't be read.
+    pub fn load_state_binary(&mut self, bytes: &[u8]) -> Result<(), Chip8Error> {
+        let snapshot = Chip8Snapshot::from_bytes(bytes)?;
+        self.restore(&snapshot);
+        Ok(())
+    }
+}
@@ -0,0 +1,76 @@
+// Execution counters, to see where a ROM spends its time.
+use std::collections::HashMap;
+
+use crate::{decode, Instruction};
+
+/// How many times each kind of instruction was executed, by mnemonic
+/// (`"DRW"`, `"JP"`, ...), and how many instructions were run in total.
+/// The machine cycles they took are what `cycle` returns.
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct OpcodeStats {
+    counts: HashMap<&'static str, u64>,
+    total_instructions: u64,
+}
+
+impl OpcodeStats {
+    /// How many times instructions with this mnemonic were executed.
+    pub fn count(&self, mnemonic: &str) -> u64 {
+        self.counts.get(mnemonic).copied().unwrap_or(0)
+    }
+
+    /// All the counts, by mnemonic.
+    pub fn counts(&self) -> &HashMap<&'static str, u64> {
+        &self.counts
+    }
+
+    /// How many instructions were run in total.
+    pub fn total_instructions(&self) -> u64 {
+        self.total_instructions
+    }
+
+    pub(crate) fn record(&mut self, opcode: u16) {
+        *self.counts.entry(mnemonic(opcode)).or_insert(0) += 1;
+        self.total_instructions += 1;
+    }
+}
+
+/// The mnemonic of an opcode, as in Cowgod's specification, and David
+/// Winter's and Octo's for the SUPER-CHIP and XO-CHIP instructions.
+fn mnemonic(opcode: u16) -> &'static str {
+    use Instruction::*;
+
+    match decode(opcode) {
+        Cls => "CLS",
+        Ret => "RET",
+        Sys(_) => "SYS",
+        Jp(_) | JpV0(_) => "JP",
+        Call(_) => "CALL",
+        SeVxByte(..) | SeVxVy(..) => "SE",
+        SneVxByte(..) | SneVxVy(..) => "SNE",
+        AddVxByte(..) | AddVxVy(..) | AddIVx(_) => "ADD",
+        Or(..) => "OR",
+        And(..) => "AND",
+        Xor(..) => "XOR",
+        Sub(..) => "SUB",
+        Shr(..) => "SHR",
+        Subn(..) => "SUBN",
+        Shl(..) => "SHL",
+        Rnd(..) => "RND",
+        Drw(..) => "DRW",
+        Skp(_) => "SKP",
+        Sknp(_) => "SKNP",
+        LdVxByte(..) | LdVxVy(..) | LdI(_) | LdVxDt(_) | LdVxK(_) | LdDtVx(_) | LdStVx(_) | LdFVx(_)
+        | LdBVx(_) | LdIVx(_) | LdVxI(_) | LdHfVx(_) | LdRVx(_) | LdVxR(_) | LdILong => "LD",
+        Low => "LOW",
+        High => "HIGH",
+        Exit => "EXIT",
+        ScrollDown(_) => "SCD",
+        ScrollUp(_) => "SCU",
+        ScrollRight => "SCR",
+        ScrollLeft => "SCL",
+        Plane(_) => "PLANE",
+        Audio => "AUDIO",
+        Pitch(_) => "PITCH",
+        Unknown(_) => "???",
+    }
+}
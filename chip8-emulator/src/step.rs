@@ -0,0 +1,43 @@
+// Running a program one instruction at a time, as an iterator, for
+// scripts and tests: `for step in processor.steps().take(100)`.
+use crate::{decode, Chip8Error, Chip8Processor, Instruction};
+
+/// An instruction that just ran.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ExecutedInstruction {
+    /// Where the instruction was.
+    pub pc: u16,
+    pub opcode: u16,
+    pub instruction: Instruction,
+    /// The COSMAC VIP machine cycles it took, like `cycle` returns.
+    pub cycles: u32,
+}
+
+impl Chip8Processor {
+    /// Run one instruction per `next()`, and yield what ran.
+    ///
+    /// The iterator ends when nothing can run until the timers tick (with
+    /// the `display_wait` quirk, after a draw), when the program has exited,
+    /// and right after the first error.
+    pub fn steps(&mut self) -> impl Iterator<Item = Result<ExecutedInstruction, Chip8Error>> + '_ {
+        let mut failed = false;
+
+        std::iter::from_fn(move || {
+            if failed || self.halted || self.vblank_wait {
+                return None;
+            }
+
+            let pc = self.program_counter;
+            let opcode = u16::from_be_bytes([self.read_mem(pc), self.read_mem(pc.wrapping_add(1))]);
+            let step = self.cycle().map(|cycles| ExecutedInstruction {
+                pc,
+                opcode,
+                instruction: decode(opcode),
+                cycles,
+            });
+            failed = step.is_err();
+
+            Some(step)
+        })
+    }
+}
@@ -1,4 +1,6 @@
-use rand::{thread_rng, Rng};
+use rand::{thread_rng, Rng, SeedableRng};
+use rand::rngs::StdRng;
+use std::time::Duration;
 
 use crate::*;
 
@@ -6,7 +8,7 @@ use crate::*;
 fn test_opcode_0000() {
     let mut processor = Chip8Processor::new();
 
-    processor.execute(0x0000);
+    processor.execute(0x0000).unwrap();
 
     let expected_state = Chip8Processor::new();
 
@@ -21,9 +23,9 @@ fn test_opcode_00e0() {
     let mut new_display = [true; DISPLAY_MEM_HEIGHT * DISPLAY_MEM_WIDTH];
     thread_rng().fill(&mut new_display);
 
-    processor.display = new_display;
+    processor.display = Bitplane::from_pixels(DISPLAY_MEM_WIDTH, &new_display);
 
-    processor.execute(0x00E0);
+    processor.execute(0x00E0).unwrap();
 
     let expected_state = Chip8Processor::new();
 
@@ -35,41 +37,57 @@ fn test_opcode_00ee_2nnn() {
     let mut processor = Chip8Processor::new();
 
     // Simulate a jump in memory
-    processor.execute(0x2210); // Jump to subroutine @ pos. 210 
+    processor.execute(0x2210).unwrap(); // Jump to subroutine @ pos. 210 
     
     assert_eq!(processor.stack, [0x200, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
     assert_eq!(processor.program_counter, 0x210);
 
-    processor.execute(0x00EE); // Return
+    processor.execute(0x00EE).unwrap(); // Return
     assert_eq!(processor.stack, [0; 16]);
     assert_eq!(processor.program_counter, START_ADDRESS);
 
     // Do it again but jump twice
-    processor.execute(0x2210); // Jump to subroutine @ pos. 210 
-    processor.execute(0x2230); // Jump to subroutine @ pos. 230 
+    processor.execute(0x2210).unwrap(); // Jump to subroutine @ pos. 210 
+    processor.execute(0x2230).unwrap(); // Jump to subroutine @ pos. 230 
 
     assert_eq!(processor.stack, [0x200, 0x210, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
     assert_eq!(processor.program_counter, 0x230);
 
-    processor.execute(0x00EE); // Return
+    processor.execute(0x00EE).unwrap(); // Return
     assert_eq!(processor.stack, [0x200, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
     assert_eq!(processor.program_counter, 0x210);
 
-    processor.execute(0x00EE); // Return
+    processor.execute(0x00EE).unwrap(); // Return
     assert_eq!(processor.stack, [0; 16]);
     assert_eq!(processor.program_counter, START_ADDRESS);
 
 }
 
+#[test]
+fn test_call_stack() {
+    let mut processor = Chip8Processor::new();
+    // CALL 0x204; (0x202) nothing; CALL 0x208; (0x206) nothing; RET
+    processor.load_rom(&[0x22, 0x04, 0x00, 0x00, 0x22, 0x08, 0x00, 0x00, 0x00, 0xEE]).unwrap();
+    assert_eq!(processor.stack_depth(), 0);
+    assert_eq!(processor.call_stack(), &[] as &[u16]);
+
+    processor.cycle_n(2).unwrap();
+    assert_eq!(processor.stack_depth(), 2);
+    assert_eq!(processor.call_stack(), &[0x202, 0x206]);
+
+    processor.cycle().unwrap();
+    assert_eq!(processor.call_stack(), &[0x202]);
+}
+
 
 #[test]
 fn test_opcode_1nnn() {
     let mut processor = Chip8Processor::new();
 
-    processor.execute(0x1300);
+    processor.execute(0x1300).unwrap();
     assert_eq!(processor.program_counter, 0x300);
 
-    processor.execute(0x1353);
+    processor.execute(0x1353).unwrap();
     assert_eq!(processor.program_counter, 0x353);
 }
 
@@ -78,11 +96,11 @@ fn test_opcode_1nnn() {
 fn test_opcode_3xkk() {
     let mut processor = Chip8Processor::new();
 
-    processor.execute(0x3500); // V5 == 0, skip 2
+    processor.execute(0x3500).unwrap(); // V5 == 0, skip 2
 
     assert_eq!(processor.program_counter, START_ADDRESS + 2);
 
-    processor.execute(0x3523); // Should do nothing.
+    processor.execute(0x3523).unwrap(); // Should do nothing.
     assert_eq!(processor.program_counter, START_ADDRESS + 2);
 }
 
@@ -90,10 +108,10 @@ fn test_opcode_3xkk() {
 fn test_opcode_4xkk() {
     let mut processor = Chip8Processor::new();
 
-    processor.execute(0x4500);
+    processor.execute(0x4500).unwrap();
     assert_eq!(processor.program_counter, START_ADDRESS);
 
-    processor.execute(0x4210);
+    processor.execute(0x4210).unwrap();
     assert_eq!(processor.program_counter, START_ADDRESS + 2);
 }
 
@@ -101,12 +119,12 @@ fn test_opcode_4xkk() {
 fn test_opcode_5xy0() {
     let mut processor = Chip8Processor::new();
 
-    processor.execute(0x5F00);
+    processor.execute(0x5F00).unwrap();
     assert_eq!(processor.program_counter, START_ADDRESS + 2);
 
     processor.registers[0xF] = 10;
 
-    processor.execute(0x5F00);
+    processor.execute(0x5F00).unwrap();
     assert_eq!(processor.program_counter, START_ADDRESS + 2);
 }
 
@@ -114,10 +132,10 @@ fn test_opcode_5xy0() {
 fn test_opcode_6xkk() {
     let mut processor = Chip8Processor::new();
 
-    processor.execute(0x601F);
+    processor.execute(0x601F).unwrap();
     assert_eq!(processor.registers[0x0], 0x1F);
 
-    processor.execute(0x6F88);
+    processor.execute(0x6F88).unwrap();
     assert_eq!(processor.registers[0xF], 0x88);
 
     assert_eq!(processor.registers[0x5], 0);
@@ -128,22 +146,57 @@ fn test_opcode_7xkk() {
     let mut processor = Chip8Processor::new();
 
     processor.registers[0x0] += 0x10;
-    processor.execute(0x7025);
+    processor.execute(0x7025).unwrap();
 
     assert_eq!(processor.registers[0x0], 0x10 + 0x25);
 
-    processor.execute(0x7F44);
+    processor.execute(0x7F44).unwrap();
     assert_eq!(processor.registers[0xF], 0x44);
 }
 
 #[test]
+fn test_opcode_7xnn_leaves_vf() {
+    // Unlike 8XY4, 7XNN has no carry: wrapping around leaves VF alone,
+    // whatever it held and whichever platform we are
+    let presets = [Quirks::cosmac_vip(), Quirks::schip(), Quirks::xo_chip(), Quirks::modern()];
+
+    for quirks in presets {
+        for flag in [0x00, 0x01, 0x55] {
+            let mut processor = Chip8Builder::new().quirks(quirks).build();
+            processor.load_rom(&[0x71, 0x02]).unwrap(); // V1 += 2
+            processor.registers[0x1] = 0xFF;
+            processor.registers[0xF] = flag;
+
+            processor.cycle().unwrap();
+            assert_eq!(processor.registers[0x1], 0x01);
+            assert_eq!(processor.registers[0xF], flag, "{:?}", quirks);
+        }
+    }
+
+    // When VF is the target, it gets the wrapped sum and nothing else
+    let mut processor = Chip8Processor::new();
+    processor.registers[0xF] = 0xFF;
+    processor.execute(0x7F02).unwrap();
+    assert_eq!(processor.registers[0xF], 0x01);
+
+    // 8XY4 does set the carry, for comparison
+    processor.registers[0x1] = 0xFF;
+    processor.registers[0x2] = 0x02;
+    processor.registers[0xF] = 0x55;
+    processor.execute(0x8124).unwrap();
+    assert_eq!(processor.registers[0x1], 0x01);
+    assert_eq!(processor.registers[0xF], 0x01);
+}
+
+#[test]
+#[allow(unused_assignments)] // The picture is only there for reading, see test_opcode_dxny_position
 fn test_opcode_dxny() {
     let mut processor: Chip8Processor = Chip8Processor::new();
 
     processor.i_register = 0; // Draw the first (0) sprite
     processor.registers[0x0] = 10;
     processor.registers[0x1] = 20; // At (10, 20)
-    processor.execute(0xD051); // Draw x=0, 5 rows, y=1
+    processor.execute(0xD051).unwrap(); // Draw x=0, 5 rows, y=1
 
     let mut expected_mem: [bool; DISPLAY_MEM_HEIGHT * DISPLAY_MEM_WIDTH] = [false; DISPLAY_MEM_HEIGHT * DISPLAY_MEM_WIDTH];
     // Draw the 0 manually
@@ -167,8 +220,2591 @@ fn test_opcode_dxny() {
     expected_mem[269] = true;
     //assert_eq!(processor.display, expected_mem);
 
-    processor.execute(0xD051); // Draw x=0, 5 rows, y=1
+    processor.execute(0xD051).unwrap(); // Draw x=0, 5 rows, y=1
+
+    assert_eq!(processor.display.pixels(), [false; DISPLAY_MEM_HEIGHT * DISPLAY_MEM_WIDTH]);
+    assert_eq!(processor.registers[0xF], 1);
+}
+
+#[test]
+fn test_opcode_dxny_position() {
+    let mut processor: Chip8Processor = Chip8Processor::new();
+
+    processor.i_register = 0; // Draw the first (0) sprite
+    processor.registers[0x0] = 10;
+    processor.registers[0x1] = 20; // At (10, 20)
+    processor.execute(0xD015).unwrap(); // Draw x=V0, y=V1, 5 rows
+
+    let mut expected_mem = [false; DISPLAY_MEM_HEIGHT * DISPLAY_MEM_WIDTH];
+    // The rows of the 0, 20 rows down
+    let offset = 20 * DISPLAY_MEM_WIDTH;
+    for (row, columns) in [&[10, 11, 12, 13][..], &[10, 13], &[10, 13], &[10, 13], &[10, 11, 12, 13]].iter().enumerate() {
+        for column in columns.iter() {
+            expected_mem[offset + row * DISPLAY_MEM_WIDTH + column] = true;
+        }
+    }
+    assert_eq!(processor.display.pixels(), expected_mem);
+    assert_eq!(processor.registers[0xF], 0);
+
+    // Drawing it again rubs it out
+    processor.execute(0xD015).unwrap();
+
+    assert_eq!(processor.display.pixels(), [false; DISPLAY_MEM_HEIGHT * DISPLAY_MEM_WIDTH]);
+    assert_eq!(processor.registers[0xF], 1);
+}
+
+#[test]
+fn test_key_index_round_trip() {
+    for i in 0..16u8 {
+        let key = Chip8Key::from_index(i).unwrap();
+        assert_eq!(key.index(), i as usize);
+    }
+
+    assert_eq!(Chip8Key::from_index(16), None);
 
-    assert_eq!(processor.display, [false; DISPLAY_MEM_HEIGHT * DISPLAY_MEM_WIDTH]);
+    // The same, with the standard conversions
+    for (i, key) in (0..16u8).zip(Chip8Key::ALL) {
+        assert_eq!(Chip8Key::try_from(i), Ok(key));
+        assert_eq!(u8::from(key), i);
+    }
+    assert_eq!(Chip8Key::try_from(16), Err(16));
+    assert_eq!(Chip8Key::try_from(0xFF), Err(0xFF));
+
+    let mut processor = Chip8Processor::new();
+    processor.press_key(Chip8Key::KA);
+    assert!(processor.keypad[0xA]);
+    processor.release_key(Chip8Key::KA);
+    assert!(!processor.keypad[0xA]);
+}
+
+
+#[test]
+fn test_opcode_f000_nnnn() {
+    let mut processor = Chip8Processor::new();
+    processor.set_quirks(Quirks { xo_chip: true, ..Quirks::default() });
+
+    assert_eq!(processor.ram.len(), 0x10000);
+
+    processor.load_rom(&[0xF0, 0x00, 0x12, 0x34]).unwrap();
+    processor.cycle().unwrap();
+
+    assert_eq!(processor.i_register, 0x1234);
+    assert_eq!(processor.program_counter, START_ADDRESS + 4);
+
+    // Skips have to jump over the whole long instruction
+    processor.load_rom(&[0x30, 0x00, 0xF0, 0x00, 0x12, 0x34]).unwrap();
+    processor.cycle().unwrap();
+
+    assert_eq!(processor.program_counter, START_ADDRESS + 6);
+
+    // At the top of the 64 KiB RAM, skips wrap around to 0, like fetches
+    processor.write_mem(0xFFFC, 0x30).unwrap();
+    processor.write_mem(0xFFFD, 0x00).unwrap();
+    processor.program_counter = 0xFFFC;
+    processor.cycle().unwrap();
+    assert_eq!(processor.program_counter, 0x0000);
+
+    processor.write_mem(0xFFFA, 0x30).unwrap();
+    processor.write_mem(0xFFFB, 0x00).unwrap();
+    processor.write_mem(0xFFFC, 0xF0).unwrap();
+    processor.write_mem(0xFFFD, 0x00).unwrap();
+    processor.program_counter = 0xFFFA;
+    processor.cycle().unwrap();
+    assert_eq!(processor.program_counter, 0x0000);
+}
+
+
+#[test]
+fn test_opcode_fn01() {
+    let mut processor = Chip8Processor::new();
+    processor.set_quirks(Quirks { xo_chip: true, ..Quirks::default() });
+
+    // Without the XO-CHIP plane selection, we only draw on the first plane
+    processor.i_register = 0; // The "0" sprite
+    processor.execute(0xD001).unwrap();
+    assert!(processor.display.get(0, 0));
+    assert!(!processor.second_plane.get(0, 0));
+
+    // Select the second plane only, and draw the "0" again there
+    processor.execute(0xF201).unwrap();
+    assert_eq!(processor.plane_mask, 0b10);
+    processor.execute(0xD001).unwrap();
+    assert!(processor.display.get(0, 0));
+    assert!(processor.second_plane.get(0, 0));
+    assert_eq!(processor.registers[0xF], 0);
+
+    // With both planes, the second plane reads the rows after the first's
+    processor.execute(0xF301).unwrap();
+    processor.i_register = 5; // The "1" sprite, then the "2" one
+    processor.execute(0xD101).unwrap();
+    // 0x20 goes on the first plane, 0x60 on the second
+    assert_eq!(&processor.display.pixels()[..8], &[true, true, false, true, false, false, false, false]);
+    assert_eq!(&processor.second_plane.pixels()[..8], &[true, false, false, true, false, false, false, false]);
     assert_eq!(processor.registers[0xF], 1);
-}
\ No newline at end of file
+
+    // CLS only clears the selected planes
+    processor.execute(0xF101).unwrap();
+    processor.execute(0x00E0).unwrap();
+    assert_eq!(processor.display.pixels(), [false; DISPLAY_MEM_HEIGHT * DISPLAY_MEM_WIDTH]);
+    assert!(processor.second_plane.get(0, 0));
+
+    let (first, second) = processor.get_display_planes();
+    assert!(!first[0]);
+    assert!(second[0]);
+}
+
+#[test]
+fn test_display_colors() {
+    let mut processor = Chip8Builder::new().quirks(Quirks::xo_chip()).build();
+    processor.i_register = 0; // The top row of the "0" is 0xF0
+
+    // The first plane at x = 0, the second at x = 2, both at x = 4 (and
+    // the sprites overlap, so the first plane covers 0 to 3, and so on)
+    processor.registers[0x0] = 0;
+    processor.registers[0x1] = 0;
+    processor.execute(0xF101).unwrap();
+    processor.execute(0xD011).unwrap();
+    processor.registers[0x0] = 2;
+    processor.execute(0xF201).unwrap();
+    processor.execute(0xD011).unwrap();
+
+    let colors = processor.get_display_colors();
+    assert_eq!(colors.len(), DISPLAY_MEM_WIDTH * DISPLAY_MEM_HEIGHT);
+    assert_eq!(&colors[..8], &[1, 1, 3, 3, 2, 2, 0, 0]);
+    assert!(colors[8..].iter().all(|color| *color == 0));
+
+    // Sinks that don't know about colors see the first plane
+    struct BoolSink(Vec<bool>);
+    impl DisplaySink for BoolSink {
+        fn present(&mut self, pixels: &[bool], _width: usize, _height: usize) {
+            self.0 = pixels.to_vec();
+        }
+    }
+    let mut sink = BoolSink(Vec::new());
+    processor.present(&mut sink);
+    assert_eq!(sink.0, processor.get_display());
+}
+
+
+#[test]
+fn test_opcode_f002_fx3a() {
+    let mut processor = Chip8Processor::new();
+    processor.set_quirks(Quirks { xo_chip: true, ..Quirks::default() });
+
+    let pattern: [u8; 16] = [
+        0x00, 0xFF, 0x00, 0xFF, 0x0F, 0xF0, 0x0F, 0xF0,
+        0xAA, 0x55, 0xAA, 0x55, 0x01, 0x02, 0x04, 0x08,
+    ];
+    processor.ram[0x300..0x310].copy_from_slice(&pattern);
+    processor.i_register = 0x300;
+
+    processor.execute(0xF002).unwrap();
+    assert_eq!(processor.audio_pattern(), &pattern);
+
+    assert_eq!(processor.audio_pitch(), 64);
+    assert_eq!(processor.audio_sample_rate(), 4000.0);
+
+    processor.registers[0x4] = 112;
+    processor.execute(0xF43A).unwrap();
+    assert_eq!(processor.audio_pitch(), 112);
+    assert_eq!(processor.audio_sample_rate(), 8000.0);
+}
+
+
+#[test]
+fn test_load_rom_at() {
+    let mut processor = Chip8Processor::new();
+
+    processor.load_rom_at(0x600, &[0x60, 0x2A]).unwrap();
+    assert_eq!(processor.program_counter, 0x600);
+    assert_eq!(&processor.ram[0x600..0x602], &[0x60, 0x2A]);
+
+    processor.cycle().unwrap();
+    assert_eq!(processor.registers[0x0], 0x2A);
+
+    // Only 0x100 bytes fit between 0xF00 and the end of the RAM
+    let rom = [0; 0x101];
+    assert_eq!(
+        processor.load_rom_at(0xF00, &rom),
+        Err(Chip8Error::RomTooLarge { size: 0x101, max: 0x100 })
+    );
+    assert_eq!(processor.program_counter, 0x602);
+}
+
+
+#[test]
+fn test_set_ram() {
+    let mut processor = Chip8Processor::new();
+    let fonts = processor.dump_mem(0..0x200).to_vec();
+
+    processor.set_ram(&[0x60, 0x2A, 0x12, 0x00]).unwrap();
+    assert_eq!(processor.dump_mem(0x200..0x204), &[0x60, 0x2A, 0x12, 0x00]);
+    assert_eq!(processor.read_mem(0x204), 0);
+    assert_eq!(processor.dump_mem(0..0x200), fonts.as_slice());
+    assert_eq!(processor.pc(), START_ADDRESS);
+
+    // A bigger image goes over the old one, up to the end of the RAM
+    let mut image = vec![0xAB; RAM_SIZE - 0x200];
+    image[2] = 0xCD;
+    processor.set_ram(&image).unwrap();
+    assert_eq!(processor.dump_mem(0x200..0x204), &[0xAB, 0xAB, 0xCD, 0xAB]);
+    assert_eq!(processor.read_mem(0xFFF), 0xAB);
+    assert_eq!(processor.dump_mem(0..0x200), fonts.as_slice());
+
+    // One byte more and nothing is copied
+    let image = vec![0; RAM_SIZE - 0x200 + 1];
+    assert_eq!(processor.set_ram(&image), Err(Chip8Error::OutOfBoundsMemory(0x1000)));
+    assert_eq!(processor.read_mem(0x200), 0xAB);
+}
+
+#[test]
+fn test_load_asm() {
+    let mut processor = Chip8Processor::new();
+    processor.load_asm("
+            LD V0, 0x20
+            LD V1, 0x0A
+            ADD V0, V1
+    ").unwrap();
+    processor.cycle_n(3).unwrap();
+    assert_eq!(processor.registers[0x0], 0x2A);
+    assert_eq!(processor.pc(), START_ADDRESS + 6);
+
+    // Assembly errors come back as they are, and nothing is loaded
+    let mut processor = Chip8Processor::new();
+    assert_eq!(
+        processor.load_asm("JP nowhere"),
+        Err(Chip8Error::Asm(asm::AsmError::UndefinedLabel { line: 1, label: "nowhere".to_string() }))
+    );
+    assert_eq!(processor.read_mem(START_ADDRESS), 0);
+}
+
+#[test]
+fn test_verify_rom() {
+    let rom = [
+        0x60, 0x05, // LD V0, 5
+        0x21, 0x00, // CALL 0x100, into the interpreter
+        0x12, 0x00, // JP 0x200, fine
+        0xFF, 0xFF, // Not an instruction
+        0x0A, // Cut short
+    ];
+
+    assert_eq!(verify_rom(&rom), [
+        RomWarning::OddLength(9),
+        RomWarning::ReservedTarget { address: 0x202, opcode: 0x2100, target: 0x100 },
+        RomWarning::UnknownOpcode { address: 0x206, opcode: 0xFFFF },
+    ]);
+    assert_eq!(
+        RomWarning::ReservedTarget { address: 0x202, opcode: 0x2100, target: 0x100 }.to_string(),
+        "0x202: 0x2100 goes to 0x100, below the program"
+    );
+
+    // Nothing to say about a clean ROM
+    assert_eq!(verify_rom(&[0x00, 0xE0, 0x12, 0x02]), []);
+
+    // Up to the last byte of the RAM is fine, one word more is not, and
+    // a ROM too big to address doesn't wrap around to 0x200
+    let fits = vec![0x00; RAM_SIZE - START_ADDRESS as usize];
+    assert_eq!(verify_rom(&fits), []);
+    let mut too_long = fits.clone();
+    too_long.extend_from_slice(&[0xFF, 0xFF]);
+    assert_eq!(verify_rom(&too_long), [RomWarning::TooLong(RAM_SIZE - 0x200 + 2)]);
+    // Past 64 KiB, the offsets overflowed a u16. Before that, the word
+    // at 0xFE00 wrapped around to address 0x000
+    let mut huge = vec![0x00; 0x10002];
+    huge[0xFE00] = 0xFF;
+    huge[0xFE01] = 0xFF;
+    assert_eq!(verify_rom(&huge), [RomWarning::TooLong(0x10002)]);
+}
+
+#[test]
+fn test_assemble() {
+    let source = "
+        ; Draw a digit, then spin forever
+        start:
+            LD V0, 0x0A     ; The digit
+            LD F, V0
+            LD V1, 10
+            ld v2, #14
+            DRW V1, V2, 5
+        loop: JP loop
+            CALL sprite
+        sprite:
+            DB 0xF0, 0x90
+            DW 0x1234
+    ";
+
+    let rom = asm::assemble(source).unwrap();
+
+    assert_eq!(rom, vec![
+        0x60, 0x0A,
+        0xF0, 0x29,
+        0x61, 0x0A,
+        0x62, 0x14,
+        0xD1, 0x25,
+        0x12, 0x0A, // loop is at 0x20A
+        0x22, 0x0E, // sprite is at 0x20E
+        0xF0, 0x90,
+        0x12, 0x34,
+    ]);
+}
+
+#[test]
+fn test_disassemble() {
+    assert_eq!(asm::disassemble(0x00E0), "CLS");
+    assert_eq!(asm::disassemble(0x6A12), "LD VA, 0x12");
+    assert_eq!(asm::disassemble(0xD125), "DRW V1, V2, 5");
+    assert_eq!(asm::disassemble(0xF365), "LD V3, [I]");
+    assert_eq!(asm::disassemble(0x5121), "DW 0x5121");
+
+    // Whatever comes out assembles back to the same opcode
+    for opcode in 0..=u16::MAX {
+        let source = asm::disassemble(opcode);
+        assert_eq!(asm::assemble(&source), Ok(opcode.to_be_bytes().to_vec()), "{}", source);
+    }
+}
+
+#[test]
+fn test_assemble_errors() {
+    assert_eq!(
+        asm::assemble("JP nowhere"),
+        Err(asm::AsmError::UndefinedLabel { line: 1, label: "nowhere".to_string() })
+    );
+    assert_eq!(
+        asm::assemble("CLS\nFOO V0"),
+        Err(asm::AsmError::UnknownMnemonic { line: 2, mnemonic: "FOO".to_string() })
+    );
+    assert_eq!(
+        asm::assemble("LD V0, 0x100"),
+        Err(asm::AsmError::ValueOutOfRange { line: 1, value: 0x100 })
+    );
+    assert_eq!(
+        asm::assemble("DRW V0, 5"),
+        Err(asm::AsmError::InvalidOperands { line: 1, mnemonic: "DRW".to_string() })
+    );
+
+    // Past the end of the address space, an error instead of wrapping
+    let zeros = vec!["0"; 65400].join(", ");
+    assert_eq!(
+        asm::assemble(&format!("DB {}\nDB 1, 2", zeros)),
+        Err(asm::AsmError::ProgramTooLarge { line: 1 })
+    );
+    // Up to the very last byte is fine, but nothing comes after it
+    let zeros = vec!["0"; 0x10000 - 0x200].join(", ");
+    assert_eq!(asm::assemble(&format!("DB {}", zeros)).map(|rom| rom.len()), Ok(0x10000 - 0x200));
+    assert_eq!(
+        asm::assemble(&format!("DB {}\nDB 1, 2", zeros)),
+        Err(asm::AsmError::ProgramTooLarge { line: 2 })
+    );
+    assert_eq!(
+        asm::assemble(&format!("DB {}\nend:", zeros)),
+        Err(asm::AsmError::ProgramTooLarge { line: 2 })
+    );
+}
+
+
+#[test]
+fn test_run_for() {
+    let mut processor = Chip8Processor::new();
+
+    // Count V0 up forever
+    processor.load_rom(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+    assert_eq!(processor.run_for(10), Ok(10));
+    assert_eq!(processor.registers[0x0], 5);
+
+    // Count V0 up twice, then spin in place
+    let mut processor = Chip8Processor::new();
+    processor.load_rom(&[0x70, 0x01, 0x70, 0x01, 0x12, 0x04]).unwrap();
+    assert_eq!(processor.run_for(100), Ok(2));
+    assert_eq!(processor.registers[0x0], 2);
+    assert_eq!(processor.program_counter, 0x204);
+
+    // Errors stop the run
+    let mut processor = Chip8Processor::new();
+    processor.load_rom(&[0x00, 0xEE]).unwrap();
+    assert_eq!(processor.run_for(100), Err(Chip8Error::StackUnderflow));
+}
+
+#[test]
+fn test_stack_overflow() {
+    let mut processor = Chip8Processor::new();
+
+    for _ in 0..16 {
+        processor.execute(0x2300).unwrap();
+    }
+
+    assert_eq!(processor.execute(0x2300), Err(Chip8Error::StackOverflow));
+}
+
+
+#[test]
+fn test_breakpoints() {
+    let mut processor = Chip8Processor::new();
+
+    processor.load_rom(&[
+        0x60, 0x01, // 0x200: V0 = 1
+        0x61, 0x02, // 0x202: V1 = 2
+        0x62, 0x03, // 0x204: V2 = 3
+        0x70, 0x01, // 0x206: V0 += 1
+        0x12, 0x06, // 0x208: Jump to 0x206
+    ]).unwrap();
+
+    processor.add_breakpoint(0x204);
+    processor.add_breakpoint(0x208);
+    processor.add_breakpoint(0x204);
+    assert_eq!(processor.breakpoints(), &[0x204, 0x208]);
+
+    assert_eq!(processor.run_until_breakpoint(1000), Ok(Some(0x204)));
+    assert_eq!(processor.registers[..3], [1, 2, 0]);
+
+    assert_eq!(processor.run_until_breakpoint(1000), Ok(Some(0x208)));
+    assert_eq!(processor.registers[..3], [2, 2, 3]);
+
+    // Stopped on the loop, we go around once more
+    processor.remove_breakpoint(0x204);
+    assert_eq!(processor.run_until_breakpoint(1000), Ok(Some(0x208)));
+    assert_eq!(processor.registers[..3], [3, 2, 3]);
+
+    // A breakpoint the program never gets to gives up after the budget
+    processor.remove_breakpoint(0x208);
+    processor.add_breakpoint(0x200);
+    assert_eq!(processor.run_until_breakpoint(100), Ok(None));
+    assert_eq!(processor.registers[0x0], 3 + 50);
+
+    // And so does having none at all
+    processor.remove_breakpoint(0x200);
+    assert_eq!(processor.run_until_breakpoint(10), Ok(None));
+    assert_eq!(processor.registers[0x0], 3 + 55);
+}
+
+
+#[test]
+fn test_memory_accessors() {
+    let mut processor = Chip8Processor::new();
+
+    assert_eq!(processor.write_mem(0x300, 0xAB), Ok(()));
+    assert_eq!(processor.read_mem(0x300), 0xAB);
+
+    assert_eq!(processor.write_mem(0x1000, 0xAB), Err(Chip8Error::OutOfBoundsMemory(0x1000)));
+    assert_eq!(processor.read_mem(0x1000), 0);
+
+    // The font is at the start of the RAM
+    assert_eq!(processor.dump_mem(0..5), &[0xF0, 0x90, 0x90, 0x90, 0xF0]);
+    assert_eq!(processor.dump_mem(0x2FF..0x301), &[0x00, 0xAB]);
+    assert_eq!(processor.dump_mem(0xFFE..0x1010).len(), 2);
+}
+
+
+#[test]
+fn test_default() {
+    let processor = Chip8Processor::default();
+
+    assert_eq!(processor.pc(), 0x200);
+    assert_eq!(&processor.ram[..80], &INTERPRETER_SPRITES);
+    assert_eq!(processor, Chip8Processor::new());
+}
+
+
+#[test]
+fn test_builder() {
+    let quirks = Quirks { shift_uses_vy: true, ..Quirks::default() };
+    let mut processor = Chip8Builder::new()
+        .quirks(quirks)
+        .seed(42)
+        .build();
+
+    assert_eq!(processor.quirks(), quirks);
+
+    // With the quirk, VY is shifted into VX
+    processor.registers[0x1] = 0b110;
+    processor.execute(0x8016).unwrap();
+    assert_eq!(processor.registers[0x0], 0b11);
+    assert_eq!(processor.registers[0xF], 0);
+
+    // The seed makes CXNN predictable
+    let expected: u8 = StdRng::seed_from_u64(42).gen();
+    processor.execute(0xC2FF).unwrap();
+    processor.execute(0xC30F).unwrap();
+    assert_eq!(processor.registers[0x2], expected);
+
+    let mut other = Chip8Builder::new().seed(42).build();
+    other.execute(0xC2FF).unwrap();
+    other.execute(0xC30F).unwrap();
+    assert_eq!(other.registers[0x2..0x4], processor.registers[0x2..0x4]);
+}
+
+#[test]
+fn test_opcode_00fe_00ff() {
+    let mut processor = Chip8Builder::new()
+        .quirks(Quirks { schip: true, ..Quirks::default() })
+        .hires(true)
+        .build();
+
+    assert_eq!(processor.display_dimensions(), (HIRES_DISPLAY_WIDTH, HIRES_DISPLAY_HEIGHT));
+    assert_eq!(processor.get_display().len(), HIRES_DISPLAY_WIDTH * HIRES_DISPLAY_HEIGHT);
+
+    // Sprites wrap around the larger screen
+    processor.registers[0x0] = 100;
+    processor.execute(0xD011).unwrap();
+    assert!(processor.get_display()[100]);
+
+    processor.execute(0x00FE).unwrap();
+    assert_eq!(processor.display_dimensions(), (DISPLAY_MEM_WIDTH, DISPLAY_MEM_HEIGHT));
+    assert_eq!(processor.get_display(), &[false; DISPLAY_MEM_WIDTH * DISPLAY_MEM_HEIGHT]);
+
+    processor.execute(0x00FF).unwrap();
+    assert_eq!(processor.get_display().len(), HIRES_DISPLAY_WIDTH * HIRES_DISPLAY_HEIGHT);
+}
+
+#[test]
+fn test_opcode_00fd() {
+    let mut processor = Chip8Builder::new()
+        .quirks(Quirks { schip: true, ..Quirks::default() })
+        .build();
+    processor.load_rom(&[
+        0x60, 0x01, // V0 = 1
+        0x00, 0xFD, // EXIT
+        0x60, 0x02, // V0 = 2, never run
+    ]).unwrap();
+    processor.set_timer_mode(TimerMode::PerCycles(1));
+    processor.delay_timer = 100;
+
+    processor.cycle_n(2).unwrap();
+    assert!(processor.is_halted());
+    let halted = processor.snapshot();
+
+    // Nothing moves anymore, not even the timers
+    assert_eq!(processor.cycle().unwrap(), 0);
+    processor.cycle_n(10).unwrap();
+    assert_eq!(processor.snapshot(), halted);
+    assert_eq!(processor.registers[0x0], 1);
+
+    // The runners that would wait for something else stop too
+    assert_eq!(processor.run_for(100).unwrap(), 0);
+    assert_eq!(processor.run_until_breakpoint(1000).unwrap(), Some(0x204));
+
+    // Until the program starts over
+    processor.reset();
+    assert!(!processor.is_halted());
+
+    // Without SCHIP, 00FD is just another SYS call
+    let mut processor = Chip8Processor::new();
+    processor.execute(0x00FD).unwrap();
+    assert!(!processor.is_halted());
+}
+
+/// The lit pixels of the screen, as (x, y).
+fn lit_pixels(processor: &Chip8Processor) -> Vec<(usize, usize)> {
+    let (width, _) = processor.display_dimensions();
+    processor.get_display()
+        .iter()
+        .enumerate()
+        .filter(|(_, on)| **on)
+        .map(|(index, _)| (index % width, index / width))
+        .collect()
+}
+
+#[test]
+fn test_opcode_00cn_lores() {
+    // SCHIP 1.1 scrolls by high resolution pixels, so half as far
+    let mut processor = Chip8Builder::new().quirks(Quirks::schip()).build();
+    processor.set_pixel(10, 5, true);
+    processor.execute(0x00C4).unwrap();
+    assert_eq!(lit_pixels(&processor), [(10, 7)]);
+
+    // Odd distances round down
+    processor.execute(0x00C1).unwrap();
+    assert_eq!(lit_pixels(&processor), [(10, 7)]);
+
+    // Octo scrolls by pixels of the current mode
+    let mut processor = Chip8Builder::new()
+        .quirks(Quirks { schip_lores_scroll_half: false, ..Quirks::schip() })
+        .build();
+    processor.set_pixel(10, 5, true);
+    processor.execute(0x00C4).unwrap();
+    assert_eq!(lit_pixels(&processor), [(10, 9)]);
+
+    // What goes past the bottom is gone
+    processor.execute(0x00CF).unwrap();
+    processor.execute(0x00CF).unwrap();
+    assert_eq!(lit_pixels(&processor), []);
+}
+
+#[test]
+fn test_scroll_hires() {
+    // In high resolution, the quirk makes no difference
+    let mut processor = Chip8Builder::new().quirks(Quirks::xo_chip()).hires(true).build();
+    processor.set_pixel(0, 0, true);
+    processor.set_pixel(127, 63, true);
+
+    processor.execute(0x00FB).unwrap(); // Right 4
+    assert_eq!(lit_pixels(&processor), [(4, 0)]);
+    processor.execute(0x00C3).unwrap(); // Down 3
+    assert_eq!(lit_pixels(&processor), [(4, 3)]);
+    processor.execute(0x00D2).unwrap(); // Up 2
+    processor.execute(0x00FC).unwrap(); // Left 4
+    assert_eq!(lit_pixels(&processor), [(0, 1)]);
+    processor.execute(0x00FC).unwrap();
+    assert_eq!(lit_pixels(&processor), []);
+
+    // Only the selected planes move
+    processor.execute(0xF201).unwrap();
+    processor.set_pixel(0, 0, true);
+    processor.execute(0x00C1).unwrap();
+    assert_eq!(lit_pixels(&processor), [(0, 0)]);
+
+    // Without the extensions, they are SYS calls
+    let mut processor = Chip8Processor::new();
+    processor.set_pixel(0, 0, true);
+    processor.execute(0x00C1).unwrap();
+    processor.execute(0x00FB).unwrap();
+    assert_eq!(lit_pixels(&processor), [(0, 0)]);
+}
+
+
+#[test]
+fn test_cycle_n() {
+    let mut processor = Chip8Processor::new();
+
+    processor.load_rom(&[
+        0x60, 0x01, // 0x200: V0 = 1
+        0x22, 0x08, // 0x202: Call 0x208
+        0x12, 0x04, // 0x204: Spin
+        0x00, 0x00, // 0x206: Padding
+        0x70, 0x01, // 0x208: V0 += 1
+        0x00, 0xEE, // 0x20A: Return
+    ]).unwrap();
+
+    assert_eq!(processor.cycle_n(5), Ok(()));
+    assert_eq!(processor.pc(), 0x204);
+    assert_eq!(processor.registers[0x0], 2);
+
+    // Errors cut the batch short
+    processor.load_rom(&[0x00, 0xEE, 0x60, 0x01]).unwrap();
+    assert_eq!(processor.cycle_n(2), Err(Chip8Error::StackUnderflow));
+    assert_eq!(processor.pc(), 0x202);
+}
+
+
+#[test]
+fn test_advance_timers() {
+    let mut processor = Chip8Processor::new();
+    processor.delay_timer = 10;
+    processor.sound_timer = 10;
+
+    let frame = Duration::from_micros(16_670);
+    for expected in (5..10).rev() {
+        processor.advance_timers(frame);
+        assert_eq!(processor.delay_timer, expected);
+        assert_eq!(processor.sound_timer, expected);
+    }
+
+    // Short frames add up
+    processor.advance_timers(Duration::from_millis(8));
+    assert_eq!(processor.delay_timer, 5);
+    processor.advance_timers(Duration::from_millis(9));
+    assert_eq!(processor.delay_timer, 4);
+
+    // Long frames tick more than once
+    processor.advance_timers(Duration::from_millis(50));
+    assert_eq!(processor.delay_timer, 1);
+}
+
+#[test]
+fn test_timers_remaining() {
+    let mut processor = Chip8Processor::new();
+    assert_eq!(processor.sound_remaining(), Duration::ZERO);
+
+    processor.sound_timer = 30;
+    processor.delay_timer = 120;
+    assert_eq!(processor.sound_remaining(), Duration::from_millis(500));
+    assert_eq!(processor.delay_remaining(), Duration::from_secs(2));
+
+    processor.sound_timer = 1;
+    assert_eq!(processor.sound_remaining(), Duration::from_secs_f64(1.0 / 60.0));
+}
+
+
+#[test]
+fn test_display_sink() {
+    #[derive(Default)]
+    struct RecordingSink {
+        frame: Vec<bool>,
+        width: usize,
+        height: usize,
+    }
+
+    impl DisplaySink for RecordingSink {
+        fn present(&mut self, pixels: &[bool], width: usize, height: usize) {
+            self.frame = pixels.to_vec();
+            self.width = width;
+            self.height = height;
+        }
+    }
+
+    let mut processor = Chip8Processor::new();
+    let mut sink = RecordingSink::default();
+
+    processor.execute(0xD001).unwrap(); // The top row of the "0", at (0, 0)
+    processor.present(&mut sink);
+    processor.present(&mut NullSink);
+
+    assert_eq!((sink.width, sink.height), (DISPLAY_MEM_WIDTH, DISPLAY_MEM_HEIGHT));
+    assert_eq!(sink.frame.len(), DISPLAY_MEM_WIDTH * DISPLAY_MEM_HEIGHT);
+    assert_eq!(&sink.frame[..5], &[true, true, true, true, false]);
+    assert_eq!(sink.frame.iter().filter(|pixel| **pixel).count(), 4);
+}
+
+
+#[test]
+fn test_display_changes() {
+    let mut processor = Chip8Processor::new();
+    assert_eq!(processor.display_changes(), vec![]);
+
+    // The "1" sprite's top row, 0x20, lights up a single pixel
+    processor.i_register = 5;
+    processor.registers[0x1] = 3;
+    processor.execute(0xD011).unwrap();
+    processor.registers[0x0] = 10;
+    processor.execute(0xD011).unwrap();
+
+    assert_eq!(processor.display_changes(), vec![(3 * 64 + 2, true), (3 * 64 + 12, true)]);
+    assert_eq!(processor.display_changes(), vec![]);
+
+    // Drawing again turns the first one off
+    processor.registers[0x0] = 0;
+    processor.execute(0xD011).unwrap();
+    assert_eq!(processor.display_changes(), vec![(3 * 64 + 2, false)]);
+}
+
+
+#[test]
+fn test_display_ascii() {
+    let mut processor = Chip8Processor::new();
+
+    processor.i_register = 8 * 5; // The "8" sprite
+    processor.registers[0x0] = 1;
+    processor.execute(0xD005).unwrap();
+
+    let ascii = processor.display_ascii();
+    let lines: Vec<&str> = ascii.lines().collect();
+    assert_eq!(lines.len(), DISPLAY_MEM_HEIGHT);
+    assert!(lines.iter().all(|line| line.len() == DISPLAY_MEM_WIDTH));
+
+    let corner: Vec<&str> = lines[..7].iter().map(|line| &line[..6]).collect();
+    assert_eq!(corner.join("\n"), "\
+......
+.####.
+.#..#.
+.####.
+.#..#.
+.####.
+......");
+}
+
+
+#[test]
+fn test_opcode_0nnn() {
+    let mut processor = Chip8Processor::new();
+
+    processor.execute(0x0123).unwrap();
+    processor.execute(0x00FF).unwrap(); // Only a SCHIP opcode with the quirk on
+
+    assert_eq!(processor, Chip8Processor::new());
+}
+
+
+#[cfg(feature = "profiling")]
+#[test]
+fn test_stats() {
+    let mut processor = Chip8Processor::new();
+
+    processor.load_rom(&[
+        0x60, 0x00, // 0x200: V0 = 0
+        0xD0, 0x05, // 0x202: Draw
+        0x70, 0x08, // 0x204: V0 += 8
+        0x12, 0x02, // 0x206: Jump to 0x202
+    ]).unwrap();
+
+    processor.cycle_n(12).unwrap(); // Stops right before the fourth jump
+
+    let stats = processor.stats();
+    assert_eq!(stats.count("DRW"), 4);
+    assert_eq!(stats.count("JP"), 3);
+    assert_eq!(stats.count("LD"), 1);
+    assert_eq!(stats.count("ADD"), 4);
+    assert_eq!(stats.count("RET"), 0);
+    assert_eq!(stats.total_instructions(), 12);
+
+    processor.reset_stats();
+    assert_eq!(processor.stats().total_instructions(), 0);
+    assert_eq!(processor.stats().count("DRW"), 0);
+    // The SUPER-CHIP instructions have names of their own, not SYS
+    processor.set_quirks(Quirks::schip());
+    processor.load_rom(&[0x00, 0xFF, 0x00, 0xC2, 0x00, 0xFB, 0x00, 0xFE]).unwrap();
+    processor.cycle_n(4).unwrap();
+    let stats = processor.stats();
+    assert_eq!(stats.count("SYS"), 0);
+    assert_eq!([stats.count("HIGH"), stats.count("SCD"), stats.count("SCR"), stats.count("LOW")], [1; 4]);
+}
+
+
+#[cfg(feature = "std")]
+#[test]
+fn test_load_rom_from_path() {
+    let directory = std::env::temp_dir();
+    let small = directory.join("chip8_test_load_rom_from_path_small.ch8");
+    let large = directory.join("chip8_test_load_rom_from_path_large.ch8");
+    std::fs::write(&small, [0x60, 0x2A]).unwrap();
+    std::fs::write(&large, [0; 4096]).unwrap();
+
+    let mut processor = Chip8Processor::new();
+
+    assert_eq!(processor.load_rom_from_path(&small), Ok(()));
+    assert_eq!(&processor.ram[0x200..0x202], &[0x60, 0x2A]);
+
+    assert_eq!(
+        processor.load_rom_from_path(&large),
+        Err(Chip8Error::RomTooLarge { size: 4096, max: 4096 - 0x200 })
+    );
+
+    let missing = processor.load_rom_from_path(directory.join("chip8_no_such_rom.ch8"));
+    assert!(matches!(missing, Err(Chip8Error::Io { kind: std::io::ErrorKind::NotFound, .. })));
+
+    std::fs::remove_file(small).unwrap();
+    std::fs::remove_file(large).unwrap();
+}
+
+
+#[cfg(feature = "flate2")]
+#[test]
+fn test_load_rom_gz() {
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    let rom = [0x60, 0x2A, 0x12, 0x02];
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(&rom).unwrap();
+    let gzipped = encoder.finish().unwrap();
+
+    let mut processor = Chip8Processor::new();
+    assert_eq!(processor.load_rom_gz(&gzipped), Ok(()));
+    assert_eq!(&processor.ram[0x200..0x204], &rom);
+
+    // Plain ROMs go through untouched
+    let mut processor = Chip8Processor::new();
+    assert_eq!(processor.load_rom_gz(&[0x61, 0x01]), Ok(()));
+    assert_eq!(&processor.ram[0x200..0x202], &[0x61, 0x01]);
+
+    // And so do gzipped files on disk
+    let path = std::env::temp_dir().join("chip8_test_load_rom_gz.ch8.gz");
+    std::fs::write(&path, &gzipped).unwrap();
+    let mut processor = Chip8Processor::new();
+    assert_eq!(processor.load_rom_from_path(&path), Ok(()));
+    assert_eq!(&processor.ram[0x200..0x204], &rom);
+    std::fs::remove_file(path).unwrap();
+
+    // Broken gzip data is an error, not a garbage ROM
+    let broken = processor.load_rom_gz(&gzipped[..gzipped.len() / 2]);
+    assert!(matches!(broken, Err(Chip8Error::Io { .. })));
+}
+
+
+#[test]
+fn test_load_rom_size() {
+    let mut processor = Chip8Processor::new();
+    let max = 4096 - 0x200;
+
+    // A ROM filling the whole program space fits
+    let rom = vec![0xAA; max];
+    assert_eq!(processor.load_rom(&rom), Ok(()));
+    assert_eq!(processor.ram[4095], 0xAA);
+
+    // One more byte does not, and nothing gets written
+    let rom = vec![0xBB; max + 1];
+    assert_eq!(processor.load_rom(&rom), Err(Chip8Error::RomTooLarge { size: max + 1, max }));
+    assert_eq!(processor.ram[0x200], 0xAA);
+
+    // XO-CHIP has a lot more room
+    processor.set_quirks(Quirks { xo_chip: true, ..Quirks::default() });
+    assert_eq!(processor.load_rom(&rom), Ok(()));
+    let rom = vec![0xBB; 0x10000 - 0x200 + 1];
+    assert_eq!(
+        processor.load_rom(&rom),
+        Err(Chip8Error::RomTooLarge { size: 0x10000 - 0x200 + 1, max: 0x10000 - 0x200 })
+    );
+}
+
+
+#[test]
+fn test_sprite_wrapping() {
+    // The top row of the "0" sprite is 4 pixels wide: drawn at x=62, it
+    // goes 2 pixels past the right edge.
+    let mut wrapping = Chip8Processor::new();
+    wrapping.registers[0x0] = 62;
+    wrapping.registers[0x1] = 0;
+    wrapping.execute(0xD011).unwrap();
+
+    let row = &wrapping.get_display()[..DISPLAY_MEM_WIDTH];
+    assert_eq!(&row[..2], &[true, true]);
+    assert_eq!(&row[62..], &[true, true]);
+
+    let mut clipping = Chip8Builder::new()
+        .quirks(Quirks { sprite_wrapping: false, ..Quirks::default() })
+        .build();
+    clipping.registers[0x0] = 62;
+    clipping.registers[0x1] = 0;
+    clipping.execute(0xD011).unwrap();
+
+    let row = &clipping.get_display()[..DISPLAY_MEM_WIDTH];
+    assert_eq!(&row[..2], &[false, false]);
+    assert_eq!(&row[62..], &[true, true]);
+
+    // The starting position itself still wraps: x=66 is x=2
+    clipping.registers[0x0] = 66;
+    clipping.execute(0xD011).unwrap();
+    assert_eq!(&clipping.get_display()[..6], &[false, false, true, true, true, true]);
+}
+
+
+#[test]
+fn test_display_wait() {
+    let mut processor = Chip8Builder::new()
+        .quirks(Quirks { display_wait: true, ..Quirks::default() })
+        .build();
+
+    processor.load_rom(&[
+        0xD0, 0x05, // 0x200: Draw
+        0x70, 0x01, // 0x202: V0 += 1
+        0x12, 0x00, // 0x204: Jump to 0x200
+    ]).unwrap();
+
+    // The draw happens, then nothing else this frame
+    processor.cycle_n(10).unwrap();
+    assert_eq!(processor.pc(), 0x202);
+    assert_eq!(processor.registers[0x0], 0);
+
+    processor.tick_timers();
+    processor.cycle_n(10).unwrap();
+    assert_eq!(processor.pc(), 0x202);
+    assert_eq!(processor.registers[0x0], 1);
+
+    // Without the quirk, we loop around freely
+    let mut processor = Chip8Processor::new();
+    processor.load_rom(&[0xD0, 0x05, 0x70, 0x01, 0x12, 0x00]).unwrap();
+    processor.cycle_n(9).unwrap();
+    assert_eq!(processor.registers[0x0], 3);
+}
+
+
+#[test]
+fn test_set_keypad() {
+    let mut processor = Chip8Processor::new();
+
+    let mut keys = [false; 16];
+    keys[0x7] = true;
+    keys[0xC] = true;
+    processor.set_keypad(keys);
+    assert_eq!(processor.keypad(), &keys);
+
+    // EX9E skips when the key in VX is pressed...
+    processor.registers[0x3] = 0x7;
+    processor.execute(0xE39E).unwrap();
+    assert_eq!(processor.pc(), START_ADDRESS + 2);
+
+    // ...and not when it isn't
+    processor.registers[0x3] = 0x8;
+    processor.execute(0xE39E).unwrap();
+    assert_eq!(processor.pc(), START_ADDRESS + 2);
+
+    // The per-key methods still work on top
+    processor.release_key(Chip8Key::K7);
+    assert!(!processor.keypad()[0x7]);
+    assert!(processor.keypad()[0xC]);
+}
+
+
+#[test]
+fn test_is_beeping() {
+    let mut processor = Chip8Processor::new();
+    assert!(!processor.is_beeping());
+
+    processor.registers[0x2] = 2;
+    processor.execute(0xF218).unwrap();
+    assert!(processor.is_beeping());
+
+    processor.tick_timers();
+    assert!(processor.is_beeping());
+    processor.tick_timers();
+    assert!(!processor.is_beeping());
+}
+
+
+#[test]
+fn test_snapshot_restore() {
+    let mut processor = Chip8Processor::new();
+    processor.load_rom(&[0x60, 0x12, 0xA2, 0x34]).unwrap();
+    processor.cycle_n(2).unwrap();
+
+    let snapshot = processor.snapshot();
+    // Snapshots aren't Copy, but a clone is just as good to restore from
+    let copy = snapshot.clone();
+
+    // 6XNN and 7XNN are always valid, whatever the operands
+    let opcode = thread_rng().gen_range(0x6000..0x8000);
+    processor.execute(opcode).unwrap();
+    processor.restore(&snapshot);
+
+    assert_eq!(processor.snapshot(), snapshot);
+    assert_eq!(processor.pc(), 0x204);
+    assert_eq!(processor.registers[0x0], 0x12);
+
+    processor.execute(opcode).unwrap();
+    processor.restore(&copy);
+    assert_eq!(processor.snapshot(), snapshot);
+}
+
+#[test]
+fn test_save_state_binary() {
+    // A bit of everything: SCHIP hires, a sprite, a call, timers and keys
+    let mut processor = Chip8Builder::new()
+        .quirks(Quirks::schip())
+        .seed(7)
+        .build();
+    processor.load_rom(&asm::assemble("
+            DW 0x00FF ; HIGH
+            LD V0, 100
+            LD DT, V0
+            CALL draw
+        draw:
+            LD I, 0x050
+            DRW V0, V0, 0
+            RND V3, 0xFF
+    ").unwrap()).unwrap();
+    processor.cycle_n(7).unwrap();
+    processor.press_key(Chip8Key::KA);
+    processor.flags = [1, 2, 3, 4, 5, 6, 7, 8];
+
+    let state = processor.save_state_binary();
+    let mut loaded = Chip8Processor::new();
+    loaded.load_state_binary(&state).unwrap();
+    assert_eq!(loaded.snapshot(), processor.snapshot());
+    assert_eq!(loaded.get_display(), processor.get_display());
+    assert_eq!(loaded.save_state_binary(), state);
+
+    // Anything else is refused, and changes nothing
+    let before = loaded.snapshot();
+    assert!(matches!(loaded.load_state_binary(b"not a state"), Err(Chip8Error::InvalidState(_))));
+    assert!(matches!(loaded.load_state_binary(&state[..state.len() - 1]), Err(Chip8Error::InvalidState(_))));
+    let mut future = state.clone();
+    future[4] = 99;
+    assert_eq!(loaded.load_state_binary(&future), Err(Chip8Error::UnsupportedStateVersion(99)));
+    assert_eq!(loaded.snapshot(), before);
+}
+
+#[test]
+fn test_load_state_binary_ram_size() {
+    let processor = Chip8Processor::new();
+    let state = processor.save_state_binary();
+    // The RAM comes right after the magic, the version and its length
+    let with_ram = |ram: &[u8]| {
+        let mut bytes = state[..5].to_vec();
+        bytes.extend_from_slice(&(ram.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(ram);
+        bytes.extend_from_slice(&state[9 + RAM_SIZE..]);
+        bytes
+    };
+    assert_eq!(with_ram(&processor.ram), state);
+
+    let mut loaded = Chip8Processor::new();
+    for size in [0, 16, RAM_SIZE + 1, XO_CHIP_RAM_SIZE] {
+        let result = loaded.load_state_binary(&with_ram(&vec![0; size]));
+        assert!(matches!(result, Err(Chip8Error::InvalidState(_))), "{} bytes of RAM", size);
+    }
+    assert_eq!(loaded.snapshot(), processor.snapshot());
+
+    // The XO-CHIP RAM goes with the XO-CHIP quirk
+    let mut xo_chip = Chip8Processor::new();
+    xo_chip.set_quirks(Quirks::xo_chip());
+    let state = xo_chip.save_state_binary();
+    loaded.load_state_binary(&state).unwrap();
+    assert_eq!(loaded.ram.len(), XO_CHIP_RAM_SIZE);
+}
+
+#[test]
+fn test_load_state_binary_v1() {
+    let mut processor = Chip8Processor::new();
+    processor.load_rom(&[0x60, 0x2A, 0x00, 0xE0, 0x12, 0x02]).unwrap();
+    processor.cycle_n(3).unwrap();
+
+    // Version 1 is version 2 (and so 3, on a 64x32 screen) without the
+    // end of the ROM
+    let state = processor.save_state_binary();
+    assert_eq!(state[4], 3);
+    let mut v1 = state[..state.len() - 4].to_vec();
+    v1[4] = 1;
+
+    let mut loaded = Chip8Processor::new();
+    loaded.load_state_binary(&v1).unwrap();
+    assert_eq!(loaded.rom_end, 0);
+    assert_eq!(loaded.registers[0x0], 0x2A);
+    assert_eq!(loaded.pc(), processor.pc());
+    let mut expected = processor.clone();
+    expected.rom_end = 0;
+    assert_eq!(loaded.snapshot(), expected.snapshot());
+
+    // Without the end of the ROM, running on is still fine
+    loaded.cycle_n(10).unwrap();
+
+    // The current version keeps it
+    loaded.load_state_binary(&state).unwrap();
+    assert_eq!(loaded.rom_end, START_ADDRESS as usize + 6);
+}
+
+
+#[test]
+fn test_schip_drw_vf_rows() {
+    let mut processor = Chip8Builder::new().quirks(Quirks::schip()).hires(true).build();
+    processor.set_ram(&[0xFF; 0x200]).unwrap();
+    processor.i_register = 0x300;
+    processor.registers[0x1] = 56;
+
+    // A 16x16 sprite 8 rows from the bottom: the other 8 are clipped
+    processor.execute(0xD010).unwrap();
+    assert_eq!(processor.registers[0xF], 8);
+
+    // Drawn again, the 8 rows on the screen collide too
+    processor.execute(0xD010).unwrap();
+    assert_eq!(processor.registers[0xF], 16);
+
+    // In low resolution, VF is a flag again
+    processor.execute(0x00FE).unwrap();
+    processor.registers[0x1] = 24;
+    processor.execute(0xD010).unwrap();
+    assert_eq!(processor.registers[0xF], 0);
+
+    // And without the quirk, it always is
+    let mut processor = Chip8Builder::new()
+        .quirks(Quirks { schip_drw_vf_rows: false, ..Quirks::schip() })
+        .hires(true)
+        .build();
+    processor.set_ram(&[0xFF; 0x200]).unwrap();
+    processor.i_register = 0x300;
+    processor.registers[0x1] = 56;
+    processor.execute(0xD010).unwrap();
+    assert_eq!(processor.registers[0xF], 0);
+    processor.execute(0xD010).unwrap();
+    assert_eq!(processor.registers[0xF], 1);
+}
+
+#[test]
+fn test_hires_chip8() {
+    let mut rom = vec![0; 0xD0];
+    rom[..2].copy_from_slice(&[0x12, 0x60]); // JP 0x260, into the patch
+    rom[0xC0..0xC8].copy_from_slice(&[
+        0xA0, 0x00, // LD I, 0x000
+        0x6A, 0x3C, // LD VA, 60
+        0xD0, 0xA1, // DRW V0, VA, 1
+        0x02, 0x30, // Clear
+    ]);
+
+    let mut processor = Chip8Builder::new()
+        .quirks(Quirks { hires_chip8: true, ..Quirks::default() })
+        .build();
+    processor.load_rom(&rom).unwrap();
+
+    // The jump switches to 64x64 and goes to the program
+    processor.cycle().unwrap();
+    assert_eq!(processor.pc(), 0x2C0);
+    assert_eq!(processor.display_dimensions(), (DISPLAY_MEM_WIDTH, HIRES_CHIP8_DISPLAY_HEIGHT));
+    assert_eq!(processor.get_display().len(), 64 * 64);
+
+    // Row 60 is on the screen now, and 0230 clears it
+    processor.cycle_n(3).unwrap();
+    assert!(processor.get_pixel(0, 60));
+    let state = processor.save_state_binary();
+    processor.cycle().unwrap();
+    assert!(processor.get_display().iter().all(|pixel| !pixel));
+
+    // Save states remember the mode, but older versions didn't have it
+    let mut loaded = Chip8Processor::new();
+    loaded.load_state_binary(&state).unwrap();
+    assert_eq!(loaded.display_dimensions(), (64, 64));
+    assert!(loaded.get_pixel(0, 60));
+    let mut v2 = state.clone();
+    v2[4] = 2;
+    assert!(matches!(loaded.load_state_binary(&v2), Err(Chip8Error::InvalidState(_))));
+
+    // Only a jump from the very start counts, and only with the quirk
+    processor.execute(0x1260).unwrap();
+    assert_eq!(processor.pc(), 0x260);
+    let mut processor = Chip8Processor::new();
+    processor.load_rom(&rom).unwrap();
+    processor.cycle().unwrap();
+    assert_eq!(processor.pc(), 0x260);
+    assert_eq!(processor.display_dimensions(), (DISPLAY_MEM_WIDTH, DISPLAY_MEM_HEIGHT));
+    processor.set_pixel(0, 0, true);
+    processor.execute(0x0230).unwrap();
+    assert!(processor.get_pixel(0, 0));
+}
+
+
+#[test]
+fn test_opcode_fx29() {
+    let mut processor = Chip8Processor::new();
+
+    processor.registers[0x4] = 0x7;
+    processor.execute(0xF429).unwrap();
+    assert_eq!(processor.i_register, 7 * 5);
+
+    // Only the low nibble picks the digit, so we stay in the font
+    processor.registers[0x4] = 0x2A;
+    processor.execute(0xF429).unwrap();
+    assert_eq!(processor.i_register, 10 * 5);
+}
+
+
+#[test]
+fn test_opcode_fx30() {
+    let mut processor = Chip8Builder::new()
+        .quirks(Quirks { schip: true, ..Quirks::default() })
+        .build();
+
+    processor.registers[0x5] = 3;
+    processor.execute(0xF530).unwrap();
+    assert_eq!(processor.i_register, 80 + 3 * 10);
+    assert_eq!(processor.dump_mem(110..112), &[0x3C, 0x7E]);
+
+    // DXY0 draws 16 rows of two bytes each from I
+    let sprite = processor.dump_mem(110..142).to_vec();
+    processor.execute(0xD000).unwrap();
+    assert_eq!(processor.registers[0xF], 0);
+
+    for row in 0..16 {
+        for col in 0..16 {
+            let expected = sprite[row * 2 + col / 8] & (0x80 >> (col % 8)) != 0;
+            assert_eq!(processor.display.get(col, row), expected);
+        }
+    }
+
+    // Past 9, the XO-CHIP letters, not whatever follows the digits
+    processor.registers[0x5] = 0xA;
+    processor.execute(0xF530).unwrap();
+    assert_eq!(processor.i_register, 80 + 0xA * 10);
+    assert_eq!(processor.dump_mem(180..190), &[0x3C, 0x7E, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3]);
+    processor.registers[0x5] = 0xF;
+    processor.execute(0xF530).unwrap();
+    assert_eq!(processor.i_register, 80 + 0xF * 10);
+    assert_eq!(processor.dump_mem(230..240), &[0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFC, 0xC0, 0xC0, 0xC0, 0xC0]);
+
+    // Without SCHIP, FX30 doesn't exist, and DXY0 draws nothing
+    let mut processor = Chip8Processor::new();
+    processor.i_register = 110;
+    processor.execute(0xD000).unwrap();
+    assert!(processor.display.pixels().iter().all(|pixel| !pixel));
+}
+
+
+#[test]
+fn test_opcode_fx75_fx85() {
+    let mut processor = Chip8Builder::new()
+        .quirks(Quirks { schip: true, ..Quirks::default() })
+        .build();
+
+    processor.registers[..4].copy_from_slice(&[0x11, 0x22, 0x33, 0x44]);
+    processor.execute(0xF375).unwrap();
+
+    // The flags survive a reset, the registers don't
+    processor.reset();
+    assert_eq!(processor.registers, [0; 16]);
+    assert!(processor.quirks().schip);
+
+    processor.execute(0xF385).unwrap();
+    assert_eq!(&processor.registers[..5], &[0x11, 0x22, 0x33, 0x44, 0x00]);
+}
+
+#[test]
+fn test_reset() {
+    let mut processor = Chip8Processor::new();
+    processor.add_breakpoint(0x204);
+    processor.load_rom(&[0x60, 0x12, 0x00, 0xE0, 0x12, 0x04]).unwrap();
+    processor.run_until_breakpoint(1000).unwrap();
+
+    processor.reset();
+    assert_eq!(processor.pc(), START_ADDRESS);
+    assert_eq!(processor.read_mem(0x200), 0);
+    assert_eq!(processor.dump_mem(0..5), &[0xF0, 0x90, 0x90, 0x90, 0xF0]);
+
+    // Breakpoints are kept
+    processor.load_rom(&[0x60, 0x12, 0x00, 0xE0, 0x12, 0x04]).unwrap();
+    assert_eq!(processor.run_until_breakpoint(1000), Ok(Some(0x204)));
+}
+
+
+/// Plays back a list of keypad states, one per poll, then holds the last.
+struct ScriptedInput {
+    states: Vec<[bool; 16]>,
+    polls: usize,
+}
+
+impl InputSource for ScriptedInput {
+    fn poll(&mut self) -> [bool; 16] {
+        let index = self.polls.min(self.states.len() - 1);
+        self.polls += 1;
+        self.states[index]
+    }
+}
+
+#[test]
+fn test_opcode_fx0a_scripted() {
+    let mut pressed = [false; 16];
+    pressed[0x7] = true;
+    let mut input = ScriptedInput { states: vec![[false; 16], [false; 16], pressed], polls: 0 };
+
+    let mut processor = Chip8Processor::new();
+    processor.load_rom(&[0xF3, 0x0A]).unwrap();
+
+    // Nothing is pressed, so we keep waiting on the same instruction
+    processor.cycle_n_with_input(2, &mut input).unwrap();
+    assert_eq!(processor.pc(), START_ADDRESS);
+
+    processor.cycle_with_input(&mut input).unwrap();
+    assert_eq!(processor.pc(), START_ADDRESS + 2);
+    assert_eq!(processor.registers[0x3], 0x7);
+    assert_eq!(input.polls, 3);
+}
+
+
+#[test]
+fn test_cycle_with_keypad_snapshot() {
+    let mut processor = Chip8Processor::new();
+    // LD V1, 0x5; SKP V1; LD V2, 0x1; LD V3, 0x1
+    processor.load_rom(&[0x61, 0x05, 0xE1, 0x9E, 0x62, 0x01, 0x63, 0x01]).unwrap();
+    processor.cycle().unwrap();
+
+    // The key goes down and is checked in the same step
+    let mut keys = [false; 16];
+    keys[0x5] = true;
+    processor.cycle_with_input(&mut keys).unwrap();
+    assert_eq!(processor.pc(), START_ADDRESS + 6);
+    assert!(processor.keypad()[0x5]);
+}
+
+#[test]
+fn test_steps() {
+    let mut processor = Chip8Processor::new();
+    // LD V0, 0x2A; CALL 0x206; (0x204) JP 0x204; (0x206) ADD V0, 1; RET
+    processor.load_rom(&[0x60, 0x2A, 0x22, 0x06, 0x12, 0x04, 0x70, 0x01, 0x00, 0xEE]).unwrap();
+
+    let steps: Vec<_> = processor.steps().take(3).map(Result::unwrap).collect();
+    let opcodes: Vec<_> = steps.iter().map(|step| step.opcode).collect();
+    assert_eq!(opcodes, [0x602A, 0x2206, 0x7001]);
+    assert_eq!(steps[1].pc, 0x202);
+    assert_eq!(steps[1].instruction, Instruction::Call(0x206));
+    assert_eq!(steps[2].cycles, 10);
+    assert_eq!(processor.registers[0x0], 0x2B);
+
+    // The iterator stops at the first error
+    let mut processor = Chip8Processor::new();
+    processor.load_rom(&[0x00, 0xEE, 0x00, 0xEE]).unwrap();
+    let steps: Vec<_> = processor.steps().collect();
+    assert_eq!(steps, [Err(Chip8Error::StackUnderflow)]);
+
+    // ...and where the program ends
+    let mut processor = Chip8Builder::new().quirks(Quirks::schip()).build();
+    processor.load_rom(&[0x60, 0x01, 0x00, 0xFD, 0x60, 0x02]).unwrap();
+    assert_eq!(processor.steps().count(), 2);
+}
+
+#[test]
+fn test_opcode_bnnn() {
+    let mut processor = Chip8Processor::new();
+
+    processor.registers[0x0] = 0x10;
+    processor.execute(0xB300).unwrap();
+    assert_eq!(processor.pc(), 0x310);
+
+    // Past the end of the RAM, we wrap around
+    processor.registers[0x0] = 0xFF;
+    processor.execute(0xBFFF).unwrap();
+    assert_eq!(processor.pc(), 0x0FE);
+}
+
+
+#[test]
+fn test_opcode_8xy0() {
+    let mut processor = Chip8Processor::new();
+
+    processor.registers[0x2] = 0x42;
+    processor.execute(0x8120).unwrap();
+    assert_eq!(processor.registers[0x1], 0x42);
+    assert_eq!(processor.registers[0x2], 0x42);
+}
+
+#[test]
+fn test_opcode_8xy1_8xy2_8xy3() {
+    let mut processor = Chip8Processor::new();
+
+    processor.registers[0x0] = 0b1100;
+    processor.registers[0x1] = 0b1010;
+    processor.execute(0x8011).unwrap();
+    assert_eq!(processor.registers[0x0], 0b1110);
+
+    processor.registers[0x0] = 0b1100;
+    processor.execute(0x8012).unwrap();
+    assert_eq!(processor.registers[0x0], 0b1000);
+
+    processor.registers[0x0] = 0b1100;
+    processor.execute(0x8013).unwrap();
+    assert_eq!(processor.registers[0x0], 0b0110);
+
+    // VY is left alone
+    assert_eq!(processor.registers[0x1], 0b1010);
+}
+
+#[test]
+fn test_opcode_8xy4() {
+    let mut processor = Chip8Processor::new();
+
+    processor.registers[0x0] = 0x10;
+    processor.registers[0x1] = 0x20;
+    processor.execute(0x8014).unwrap();
+    assert_eq!(processor.registers[0x0], 0x30);
+    assert_eq!(processor.registers[0xF], 0);
+
+    // Carry
+    processor.registers[0x0] = 0xFF;
+    processor.registers[0x1] = 0x02;
+    processor.execute(0x8014).unwrap();
+    assert_eq!(processor.registers[0x0], 0x01);
+    assert_eq!(processor.registers[0xF], 1);
+
+    // When VF is the target, the flag wins over the result
+    processor.registers[0xF] = 0xFF;
+    processor.execute(0x8F14).unwrap();
+    assert_eq!(processor.registers[0xF], 1);
+}
+
+#[test]
+fn test_opcode_8xy5() {
+    let mut processor = Chip8Processor::new();
+
+    // No borrow sets VF
+    processor.registers[0x0] = 0x30;
+    processor.registers[0x1] = 0x10;
+    processor.execute(0x8015).unwrap();
+    assert_eq!(processor.registers[0x0], 0x20);
+    assert_eq!(processor.registers[0xF], 1);
+
+    // Borrow clears it
+    processor.registers[0x0] = 0x10;
+    processor.registers[0x1] = 0x30;
+    processor.execute(0x8015).unwrap();
+    assert_eq!(processor.registers[0x0], 0xE0);
+    assert_eq!(processor.registers[0xF], 0);
+
+    // Subtracting the same value is not a borrow
+    processor.registers[0x0] = 0x10;
+    processor.registers[0x1] = 0x10;
+    processor.execute(0x8015).unwrap();
+    assert_eq!(processor.registers[0x0], 0);
+    assert_eq!(processor.registers[0xF], 1);
+}
+
+#[test]
+fn test_opcode_8xy6_8xye() {
+    let mut processor = Chip8Processor::new();
+
+    processor.registers[0x0] = 0b0000_0101;
+    processor.execute(0x8016).unwrap();
+    assert_eq!(processor.registers[0x0], 0b0000_0010);
+    assert_eq!(processor.registers[0xF], 1);
+
+    processor.execute(0x8016).unwrap();
+    assert_eq!(processor.registers[0x0], 0b0000_0001);
+    assert_eq!(processor.registers[0xF], 0);
+
+    processor.registers[0x0] = 0b1000_0001;
+    processor.execute(0x801E).unwrap();
+    assert_eq!(processor.registers[0x0], 0b0000_0010);
+    assert_eq!(processor.registers[0xF], 1);
+
+    processor.execute(0x801E).unwrap();
+    assert_eq!(processor.registers[0x0], 0b0000_0100);
+    assert_eq!(processor.registers[0xF], 0);
+
+    // With the COSMAC VIP quirk, VY is shifted into VX
+    processor.set_quirks(Quirks { shift_uses_vy: true, ..Quirks::default() });
+    processor.registers[0x1] = 0b0000_0011;
+    processor.execute(0x8016).unwrap();
+    assert_eq!(processor.registers[0x0], 0b0000_0001);
+    assert_eq!(processor.registers[0x1], 0b0000_0011);
+    assert_eq!(processor.registers[0xF], 1);
+}
+
+#[test]
+fn test_opcode_9xy0() {
+    let mut processor = Chip8Processor::new();
+
+    processor.execute(0x9010).unwrap();
+    assert_eq!(processor.pc(), START_ADDRESS);
+
+    processor.registers[0x1] = 1;
+    processor.execute(0x9010).unwrap();
+    assert_eq!(processor.pc(), START_ADDRESS + 2);
+}
+
+#[test]
+fn test_opcode_annn() {
+    let mut processor = Chip8Processor::new();
+
+    processor.execute(0xA123).unwrap();
+    assert_eq!(processor.i_register, 0x123);
+}
+
+#[test]
+fn test_opcode_cxnn() {
+    let mut processor = Chip8Builder::new().seed(42).build();
+    let mut rng = StdRng::seed_from_u64(42);
+
+    processor.execute(0xC0FF).unwrap();
+    assert_eq!(processor.registers[0x0], rng.gen::<u8>());
+
+    // The random number is masked with NN
+    processor.execute(0xC10F).unwrap();
+    assert_eq!(processor.registers[0x1], rng.gen::<u8>() & 0x0F);
+
+    processor.execute(0xC200).unwrap();
+    assert_eq!(processor.registers[0x2], 0);
+}
+
+#[test]
+fn test_rng_constant() {
+    let mut processor = Chip8Builder::new().seed(42).build();
+    let mut rng = StdRng::seed_from_u64(42);
+
+    processor.set_rng_constant(Some(0xA5));
+    processor.execute(0xC0FF).unwrap();
+    assert_eq!(processor.registers[0x0], 0xA5);
+    processor.execute(0xC10F).unwrap();
+    assert_eq!(processor.registers[0x1], 0x05);
+
+    // The seeded RNG carries on where it was
+    processor.set_rng_constant(None);
+    processor.execute(0xC2FF).unwrap();
+    assert_eq!(processor.registers[0x2], rng.gen::<u8>());
+}
+
+#[test]
+fn test_opcode_ex9e_exa1() {
+    let mut processor = Chip8Processor::new();
+    processor.registers[0x0] = 0xA;
+
+    // Not pressed: EX9E does nothing, EXA1 skips
+    processor.execute(0xE09E).unwrap();
+    assert_eq!(processor.pc(), START_ADDRESS);
+    processor.execute(0xE0A1).unwrap();
+    assert_eq!(processor.pc(), START_ADDRESS + 2);
+
+    // Pressed: the other way around
+    processor.press_key(Chip8Key::KA);
+    processor.execute(0xE09E).unwrap();
+    assert_eq!(processor.pc(), START_ADDRESS + 4);
+    processor.execute(0xE0A1).unwrap();
+    assert_eq!(processor.pc(), START_ADDRESS + 4);
+}
+
+#[test]
+fn test_opcode_fx07_fx15_fx18() {
+    let mut processor = Chip8Processor::new();
+
+    processor.registers[0x0] = 10;
+    processor.execute(0xF015).unwrap();
+    processor.execute(0xF018).unwrap();
+    processor.tick_timers();
+
+    processor.execute(0xF107).unwrap();
+    assert_eq!(processor.registers[0x1], 9);
+    assert_eq!(processor.sound_timer, 9);
+}
+
+#[test]
+fn test_opcode_fx1e() {
+    let mut processor = Chip8Processor::new();
+
+    processor.i_register = 0x100;
+    processor.registers[0x3] = 0x20;
+    processor.execute(0xF31E).unwrap();
+    assert_eq!(processor.i_register, 0x120);
+}
+
+#[test]
+fn test_opcode_fx33() {
+    let mut processor = Chip8Processor::new();
+
+    processor.i_register = 0x300;
+    processor.registers[0x0] = 254;
+    processor.execute(0xF033).unwrap();
+    assert_eq!(processor.dump_mem(0x300..0x303), &[2, 5, 4]);
+
+    processor.registers[0x0] = 7;
+    processor.execute(0xF033).unwrap();
+    assert_eq!(processor.dump_mem(0x300..0x303), &[0, 0, 7]);
+}
+
+#[test]
+fn test_opcode_fx55_fx65() {
+    let mut processor = Chip8Processor::new();
+
+    // FX55 stores V0 to VX at I
+    processor.i_register = 0x300;
+    processor.registers[..4].copy_from_slice(&[1, 2, 3, 4]);
+    processor.execute(0xF255).unwrap();
+    assert_eq!(processor.dump_mem(0x300..0x304), &[1, 2, 3, 0]);
+
+    // FX65 reads them back
+    processor.registers = [0; 16];
+    processor.execute(0xF365).unwrap();
+    assert_eq!(&processor.registers[..4], &[1, 2, 3, 0]);
+}
+
+
+#[test]
+fn test_opcode_8xy7() {
+    let mut processor = Chip8Processor::new();
+
+    // VY - VX, with no borrow, sets VF
+    processor.registers[0x0] = 0x10;
+    processor.registers[0x1] = 0x30;
+    processor.execute(0x8017).unwrap();
+    assert_eq!(processor.registers[0x0], 0x20);
+    assert_eq!(processor.registers[0xF], 1);
+
+    // Borrow clears it
+    processor.registers[0x0] = 0x30;
+    processor.registers[0x1] = 0x10;
+    processor.execute(0x8017).unwrap();
+    assert_eq!(processor.registers[0x0], 0xE0);
+    assert_eq!(processor.registers[0xF], 0);
+    assert_eq!(processor.registers[0x1], 0x10);
+}
+
+
+#[test]
+fn test_logic_resets_vf() {
+    // By default, VF is left alone
+    let mut processor = Chip8Processor::new();
+    processor.registers[0xF] = 0x42;
+    processor.execute(0x8011).unwrap();
+    assert_eq!(processor.registers[0xF], 0x42);
+
+    // Like the COSMAC VIP, it is cleared
+    processor.set_quirks(Quirks { logic_resets_vf: true, ..Quirks::default() });
+    processor.registers[0x0] = 0b01;
+    processor.registers[0x1] = 0b10;
+    processor.execute(0x8011).unwrap();
+    assert_eq!(processor.registers[0x0], 0b11);
+    assert_eq!(processor.registers[0xF], 0);
+}
+
+
+#[test]
+fn test_display_mirror() {
+    let mut processor = Chip8Builder::new().display_mirror(true).build();
+    let mirror = DISPLAY_MIRROR_ADDRESS;
+
+    // Draw the "0" sprite at (8, 1)
+    processor.registers[0x0] = 8;
+    processor.registers[0x1] = 1;
+    processor.execute(0xD015).unwrap();
+
+    // Each row is 8 bytes, and the sprite starts on the second byte
+    for (row, sprite_byte) in [0xF0, 0x90, 0x90, 0x90, 0xF0].into_iter().enumerate() {
+        let addr = mirror + 8 * (row as u16 + 1);
+        assert_eq!(processor.dump_mem(addr..addr + 2), &[0x00, sprite_byte]);
+    }
+    assert_eq!(processor.dump_mem(mirror..mirror + 8), &[0; 8]);
+
+    // Clearing the screen clears the mirror
+    processor.execute(0x00E0).unwrap();
+    assert!(processor.dump_mem(mirror..0x1000).iter().all(|byte| *byte == 0));
+
+    // Off by default
+    let mut processor = Chip8Processor::new();
+    processor.execute(0xD015).unwrap();
+    assert!(processor.dump_mem(mirror..0x1000).iter().all(|byte| *byte == 0));
+}
+
+
+#[test]
+fn test_set_font() {
+    let mut processor = Chip8Processor::new();
+
+    // A font where every digit is a 5-row bar, one pixel wider per digit
+    let mut font = [[0; 5]; 16];
+    for (digit, sprite) in font.iter_mut().enumerate() {
+        *sprite = [!(0xFFu16 >> (digit % 8 + 1)) as u8; 5];
+    }
+    processor.set_font(&font);
+
+    processor.registers[0x0] = 0x3;
+    processor.execute(0xF029).unwrap();
+    processor.execute(0xD115).unwrap();
+
+    let expected_row = "####....".to_string() + &".".repeat(DISPLAY_MEM_WIDTH - 8) + "\n";
+    assert!(processor.display_ascii().starts_with(&expected_row.repeat(5)));
+
+    // Back to the built-in font after a reset
+    processor.reset();
+    assert_eq!(&processor.ram[..80], &INTERPRETER_SPRITES);
+}
+
+
+#[test]
+fn test_last_collisions() {
+    let mut processor = Chip8Processor::new();
+    processor.set_collision_tracking(true);
+
+    // The "1" sprite, then the "7" sprite on top of it
+    processor.registers[0x0] = 1;
+    processor.execute(0xF029).unwrap();
+    processor.execute(0xD115).unwrap();
+    assert!(processor.last_collisions().is_empty());
+
+    processor.registers[0x0] = 7;
+    processor.execute(0xF029).unwrap();
+    processor.execute(0xD115).unwrap();
+    assert_eq!(processor.registers[0xF], 1);
+
+    // 1: ..#. / .##. / ..#. / ..#. / .###
+    // 7: #### / ...# / ..#. / .#.. / .#..
+    let at = |x: usize, y: usize| x + DISPLAY_MEM_WIDTH * y;
+    assert_eq!(processor.last_collisions(), &[at(2, 0), at(2, 2), at(1, 4)]);
+
+    // Not tracked by default
+    let mut processor = Chip8Processor::new();
+    processor.execute(0xD115).unwrap();
+    processor.execute(0xD115).unwrap();
+    assert_eq!(processor.registers[0xF], 1);
+    assert!(processor.last_collisions().is_empty());
+}
+
+
+/// Draw a sprite one pixel at a time, the way DXYN used to before the
+/// display was packed, to check the packed version against.
+fn draw_reference(display: &mut [bool], width: usize, height: usize, wrapping: bool, (x, y): (usize, usize), sprite: &[u8], row_bytes: usize) -> (bool, Vec<usize>) {
+    let mut collisions = Vec::new();
+
+    for (y_line, row) in sprite.chunks(row_bytes).enumerate() {
+        for x_line in 0..row_bytes * 8 {
+            if row[x_line / 8] & (0x80 >> (x_line % 8)) == 0 {
+                continue;
+            }
+            let (x, y) = (x % width + x_line, y % height + y_line);
+            if !wrapping && (x >= width || y >= height) {
+                continue;
+            }
+            let position = x % width + width * (y % height);
+            if display[position] {
+                collisions.push(position);
+            }
+            display[position] ^= true;
+        }
+    }
+
+    (!collisions.is_empty(), collisions)
+}
+
+#[test]
+fn test_packed_display_matches_reference() {
+    let mut rng = StdRng::seed_from_u64(42);
+
+    for (hires, wrapping) in [(false, false), (false, true), (true, false), (true, true)] {
+        let mut processor = Chip8Builder::new()
+            .quirks(Quirks { schip: true, sprite_wrapping: wrapping, ..Quirks::default() })
+            .build();
+        processor.set_hires(hires);
+        processor.set_collision_tracking(true);
+        let (width, height) = processor.display_dimensions();
+        let mut expected = vec![false; width * height];
+
+        for _ in 0..200 {
+            // Random sprites, 8 or 16 pixels wide, anywhere on the screen
+            let n: u8 = rng.gen_range(0..16);
+            let row_bytes = if n == 0 { 2 } else { 1 };
+            let rows = if n == 0 { 16 } else { n as usize };
+            let mut sprite = vec![0; rows * row_bytes];
+            rng.fill(&mut sprite[..]);
+
+            for (offset, byte) in sprite.iter().enumerate() {
+                processor.write_mem(0x300 + offset as u16, *byte).unwrap();
+            }
+            processor.i_register = 0x300;
+            processor.registers[0x0] = rng.gen();
+            processor.registers[0x1] = rng.gen();
+            processor.execute(0xD010 | n as u16).unwrap();
+
+            let (x, y) = (processor.registers[0x0] as usize, processor.registers[0x1] as usize);
+            let (flipped, mut collisions) = draw_reference(&mut expected, width, height, wrapping, (x, y), &sprite, row_bytes);
+
+            assert_eq!(processor.get_display(), &expected[..]);
+            assert_eq!(processor.registers[0xF], flipped as u8);
+            let mut tracked = processor.last_collisions().to_vec();
+            tracked.sort_unstable();
+            collisions.sort_unstable();
+            assert_eq!(tracked, collisions);
+        }
+
+        // The packed rows agree with the bools, too
+        for y in 0..height {
+            for x in 0..width {
+                assert_eq!(processor.display.row(y) & (1 << (127 - x)) != 0, expected[x + width * y]);
+            }
+        }
+        assert_eq!(processor.display.row(0) & !processor.display.row_mask(), 0);
+
+        processor.execute(0x00E0).unwrap();
+        assert!(processor.get_display().iter().all(|pixel| !pixel));
+        assert!((0..height).all(|y| processor.display.row(y) == 0));
+    }
+}
+
+#[test]
+fn test_display_mirror_from_packed_rows() {
+    let mut processor = Chip8Processor::new();
+    processor.set_display_mirror(true);
+
+    processor.set_pixel(0, 0, true);
+    processor.set_pixel(63, 0, true);
+    processor.set_pixel(9, 1, true);
+
+    let mirror = processor.dump_mem(DISPLAY_MIRROR_ADDRESS..DISPLAY_MIRROR_ADDRESS + 16).to_vec();
+    assert_eq!(&mirror[..8], &[0x80, 0, 0, 0, 0, 0, 0, 0x01]);
+    assert_eq!(&mirror[8..16], &[0, 0x40, 0, 0, 0, 0, 0, 0]);
+}
+
+
+#[test]
+fn test_timer_mode() {
+    // Set the delay timer to V0, then spin
+    let rom = [0xF0, 0x15, 0x12, 0x02];
+
+    let mut processor = Chip8Processor::new();
+    processor.registers[0x0] = 10;
+    processor.load_rom(&rom).unwrap();
+    processor.cycle_n(20).unwrap();
+    assert_eq!(processor.delay_timer, 10);
+
+    // Ticks on cycles 5, 10, 15 and 20
+    let mut processor = Chip8Builder::new().timer_mode(TimerMode::PerCycles(5)).build();
+    processor.registers[0x0] = 10;
+    processor.load_rom(&rom).unwrap();
+    processor.cycle_n(20).unwrap();
+    assert_eq!(processor.delay_timer, 6);
+
+    // Back to manual, the timer stays put
+    processor.set_timer_mode(TimerMode::Manual);
+    processor.cycle_n(20).unwrap();
+    assert_eq!(processor.delay_timer, 6);
+}
+
+
+#[test]
+fn test_replay() {
+    let rom = asm::assemble("
+            LD V0, 0
+        loop:
+            RND V1, 0xFF
+            ADD V0, V1
+            LD F, V0
+            DRW V0, V1, 5
+            JP loop
+    ").unwrap();
+
+    let mut processor = Chip8Builder::new().seed(7).build();
+    processor.load_rom(&rom).unwrap();
+    processor.set_recording(true);
+    processor.cycle_n(50).unwrap();
+    assert_eq!(processor.replay_log().len(), 50);
+    assert_eq!(processor.replay_log()[..2], [(0x200, 0x6000), (0x202, 0xC1FF)]);
+
+    let log = processor.take_replay_log();
+    assert!(processor.replay_log().is_empty());
+
+    let mut replayed = Chip8Builder::new().seed(7).build();
+    replayed.load_rom(&rom).unwrap();
+    replayed.replay(&log).unwrap();
+
+    assert_eq!(replayed.snapshot(), processor.snapshot());
+}
+
+
+#[test]
+fn test_clone() {
+    // Keep drawing random sprites at random places
+    let rom = asm::assemble("
+        loop:
+            RND V0, 0x3F
+            RND V1, 0x1F
+            RND V2, 0x0F
+            LD F, V2
+            DRW V0, V1, 5
+            JP loop
+    ").unwrap();
+
+    let mut processor = Chip8Builder::new().seed(1234).build();
+    processor.load_rom(&rom).unwrap();
+    processor.cycle_n(30).unwrap();
+
+    // The clone carries on exactly like the original, RNG included
+    let mut clone = processor.clone();
+    for _ in 0..10 {
+        processor.cycle_n(6).unwrap();
+        clone.cycle_n(6).unwrap();
+        assert_eq!(clone, processor);
+    }
+}
+
+
+#[test]
+fn test_cycle_cost() {
+    let mut processor = Chip8Processor::new();
+    processor.load_rom(&[
+        0x60, 0x05, // LD V0, 5
+        0x80, 0x14, // ADD V0, V1
+        0xD0, 0x15, // DRW V0, V1, 5
+        0x00, 0xE0, // CLS
+        0x12, 0x00, // JP 0x200
+    ]).unwrap();
+
+    assert_eq!(processor.cycle(), Ok(6));
+    assert_eq!(processor.cycle(), Ok(44));
+    // Drawing costs more the taller the sprite
+    assert_eq!(processor.cycle(), Ok(68 + 46 * 5));
+    assert_eq!(processor.cycle(), Ok(3078));
+    assert_eq!(processor.cycle(), Ok(12));
+
+    // One time around the loop is 3438 cycles, so a 4000 budget goes
+    // around once, then stops after the CLS that goes over it
+    assert_eq!(processor.run_budget(4000), Ok(3438 + 6 + 44 + 298 + 3078));
+    assert_eq!(processor.pc(), 0x208);
+
+    // Idling until the next frame is free
+    processor.set_quirks(Quirks { display_wait: true, ..Quirks::default() });
+    processor.load_rom_at(0x200, &[0xD0, 0x15]).unwrap();
+    processor.cycle().unwrap();
+    assert_eq!(processor.cycle(), Ok(0));
+    assert_eq!(processor.run_budget(100), Ok(0));
+}
+
+
+#[test]
+fn test_unknown_opcode() {
+    let mut processor = Chip8Processor::new();
+    processor.load_rom(&[0x51, 0x23]).unwrap();
+
+    assert_eq!(processor.cycle(), Err(Chip8Error::UnknownOpcode(0x5123)));
+    // Nothing else happened, and we could carry on past it
+    assert_eq!(processor.pc(), START_ADDRESS + 2);
+    assert_eq!(processor.registers, [0; 16]);
+
+    assert_eq!(processor.execute(0xE000), Err(Chip8Error::UnknownOpcode(0xE000)));
+    assert_eq!(processor.execute(0xF0FF), Err(Chip8Error::UnknownOpcode(0xF0FF)));
+    // XO-CHIP opcodes are unknown unless the quirk is on
+    assert_eq!(processor.execute(0xF002), Err(Chip8Error::UnknownOpcode(0xF002)));
+}
+
+
+#[test]
+fn test_opcode_5xyn_9xyn_invalid() {
+    let mut processor = Chip8Processor::new();
+
+    // Only a trailing 0 makes these a skip
+    processor.execute(0x5010).unwrap();
+    assert_eq!(processor.pc(), START_ADDRESS + 2);
+    processor.execute(0x9010).unwrap();
+    assert_eq!(processor.pc(), START_ADDRESS + 2);
+
+    assert_eq!(processor.execute(0x5011), Err(Chip8Error::UnknownOpcode(0x5011)));
+    assert_eq!(processor.execute(0x9015), Err(Chip8Error::UnknownOpcode(0x9015)));
+    assert_eq!(processor.pc(), START_ADDRESS + 2);
+
+    // Running into one stops the program, instead of taking the emulator down
+    processor.load_rom(&[0x60, 0x01, 0x91, 0x25, 0x60, 0x02]).unwrap();
+    assert_eq!(processor.run_for(10), Err(Chip8Error::UnknownOpcode(0x9125)));
+    assert_eq!(processor.registers[0x0], 1);
+}
+
+
+#[test]
+fn test_emulator() {
+    // Set both timers to 100, draw, then count cycles in V1 (by twos)
+    let rom = asm::assemble("
+            LD V0, 100
+            LD DT, V0
+            LD ST, V0
+            DRW V2, V2, 5
+        loop:
+            ADD V1, 2
+            JP loop
+    ").unwrap();
+
+    let mut processor = Chip8Processor::new();
+    processor.load_rom(&rom).unwrap();
+    let mut emulator = Emulator::new(processor, 120);
+
+    // At 120Hz, a frame is two cycles, so the beep and the draw both
+    // come in the second
+    let frame = Duration::from_secs(1) / 60;
+    let output = emulator.run_frame(frame, [false; 16]).unwrap();
+    assert_eq!(output, FrameOutput { beeping: false, display_changed: false });
+    let output = emulator.run_frame(frame, [false; 16]).unwrap();
+    assert_eq!(output, FrameOutput { beeping: true, display_changed: true });
+    let output = emulator.run_frame(frame, [false; 16]).unwrap();
+    assert!(!output.display_changed);
+
+    // One second in total: 120 cycles and 60 timer ticks
+    for _ in 3..60 {
+        emulator.run_frame(frame, [false; 16]).unwrap();
+    }
+    assert_eq!(emulator.processor().delay_timer, 40);
+    assert_eq!(emulator.processor().sound_timer, 41);
+    // 4 cycles of setup, then 58 ADDs out of the remaining 116 cycles
+    assert_eq!(emulator.processor().registers[0x1], 2 * 58);
+
+    // The keys go straight to the keypad
+    let mut keys = [false; 16];
+    keys[0x3] = true;
+    emulator.run_frame(frame, keys).unwrap();
+    assert_eq!(emulator.processor().keypad(), &keys);
+}
+
+#[test]
+fn test_cycle_scheduler() {
+    // Frames from 1 to 40ms long, like a busy front-end's
+    let mut rng = StdRng::seed_from_u64(42);
+    let mut scheduler = CycleScheduler::new(700);
+    let mut elapsed = Duration::ZERO;
+    let mut cycles = 0;
+
+    for _ in 0..10_000 {
+        let dt = Duration::from_micros(rng.gen_range(1_000..40_000));
+        let frame_cycles = scheduler.cycles_for(dt);
+
+        // No frame is more than a cycle off its fair share...
+        let fair = dt.as_secs_f64() * 700.0;
+        assert!((frame_cycles as f64 - fair).abs() <= 1.0, "{} cycles for {:?}", frame_cycles, dt);
+
+        elapsed += dt;
+        cycles += frame_cycles;
+        // ...and the running total never drifts more than half a cycle
+        let expected = elapsed.as_secs_f64() * 700.0;
+        assert!((cycles as f64 - expected).abs() <= 0.5, "{} cycles in {:?}", cycles, elapsed);
+    }
+
+    // A 60th of a second is 10 cycles at 600Hz, every time, even though
+    // the `Duration` is a little short of it
+    let mut scheduler = CycleScheduler::new(600);
+    let frame = Duration::from_secs(1) / 60;
+    assert!((0..1000).all(|_| scheduler.cycles_for(frame) == 10));
+
+    // Paused, nothing runs, and the carry is kept for later
+    let mut scheduler = CycleScheduler::new(100);
+    assert_eq!(scheduler.cycles_for(Duration::from_millis(4)), 0);
+    scheduler.set_clock_hz(0);
+    assert_eq!(scheduler.cycles_for(Duration::from_secs(1)), 0);
+    scheduler.set_clock_hz(100);
+    assert_eq!(scheduler.cycles_for(Duration::from_millis(2)), 1);
+}
+
+
+#[test]
+fn test_opcode_fx0a_press_edge() {
+    let mut processor = Chip8Processor::new();
+    processor.load_rom(&[0xF3, 0x0A, 0xF4, 0x0A]).unwrap();
+
+    // A key held from before doesn't count
+    processor.press_key(Chip8Key::K5);
+    processor.cycle_n(3).unwrap();
+    assert_eq!(processor.pc(), START_ADDRESS);
+
+    // It has to go up and down again
+    processor.release_key(Chip8Key::K5);
+    processor.cycle().unwrap();
+    processor.press_key(Chip8Key::K5);
+    processor.cycle().unwrap();
+    assert_eq!(processor.pc(), START_ADDRESS + 2);
+    assert_eq!(processor.registers[0x3], 0x5);
+
+    // Still holding it doesn't answer the next FX0A
+    processor.cycle_n(3).unwrap();
+    assert_eq!(processor.pc(), START_ADDRESS + 2);
+
+    // Another key does
+    processor.press_key(Chip8Key::K9);
+    processor.cycle().unwrap();
+    assert_eq!(processor.pc(), START_ADDRESS + 4);
+    assert_eq!(processor.registers[0x4], 0x9);
+
+    // At the very top of the XO-CHIP RAM, waiting wraps back from 0
+    let mut processor = Chip8Builder::new().quirks(Quirks::xo_chip()).build();
+    processor.write_mem(0xFFFE, 0xF2).unwrap();
+    processor.write_mem(0xFFFF, 0x0A).unwrap();
+    processor.program_counter = 0xFFFE;
+    processor.cycle_n(2).unwrap();
+    assert_eq!(processor.pc(), 0xFFFE);
+
+    processor.press_key(Chip8Key::K1);
+    processor.cycle().unwrap();
+    assert_eq!(processor.pc(), 0x0000);
+    assert_eq!(processor.registers[0x2], 0x1);
+}
+
+
+#[test]
+fn test_quirk_presets() {
+    let vip = Quirks::cosmac_vip();
+    assert!(vip.shift_uses_vy);
+    assert!(vip.logic_resets_vf);
+    assert!(vip.display_wait);
+    assert!(!vip.sprite_wrapping);
+    assert!(!vip.schip && !vip.xo_chip);
+
+    let schip = Quirks::schip();
+    assert!(schip.schip);
+    assert!(!schip.shift_uses_vy);
+    assert!(!schip.logic_resets_vf);
+    assert!(schip.schip_lores_scroll_half);
+
+    let xo_chip = Quirks::xo_chip();
+    assert!(xo_chip.xo_chip && xo_chip.schip);
+    assert!(xo_chip.shift_uses_vy);
+    assert!(xo_chip.sprite_wrapping);
+
+    assert_eq!(Quirks::modern(), Quirks::default());
+
+    assert_eq!(Quirks::preset("VIP"), Some(vip));
+    assert_eq!(Quirks::preset("xo-chip"), Some(xo_chip));
+    assert_eq!(Quirks::preset("chip-48"), None);
+}
+
+
+#[test]
+fn test_get_set_pixel() {
+    let mut processor = Chip8Processor::new();
+
+    processor.set_pixel(3, 2, true);
+    assert!(processor.get_pixel(3, 2));
+    assert!(processor.get_display()[3 + 2 * DISPLAY_MEM_WIDTH]);
+    assert!(!processor.get_pixel(2, 3));
+
+    processor.set_pixel(3, 2, false);
+    assert!(!processor.get_pixel(3, 2));
+
+    // Off the screen, nothing happens
+    processor.set_pixel(DISPLAY_MEM_WIDTH, 0, true);
+    processor.set_pixel(0, DISPLAY_MEM_HEIGHT, true);
+    assert!(processor.get_display().iter().all(|pixel| !pixel));
+    assert!(!processor.get_pixel(DISPLAY_MEM_WIDTH, 0));
+    assert!(!processor.get_pixel(0, DISPLAY_MEM_HEIGHT));
+
+    // The bounds follow the resolution
+    processor.set_hires(true);
+    processor.set_pixel(100, 50, true);
+    assert!(processor.get_pixel(100, 50));
+    assert!(!processor.get_pixel(HIRES_DISPLAY_WIDTH, 50));
+}
+
+
+#[cfg(feature = "std")]
+#[test]
+fn test_timer_thread() {
+    use std::sync::{Arc, Mutex};
+    use std::time::Instant;
+
+    use crate::timer_thread::TickSchedule;
+
+    let ms = Duration::from_millis;
+    let start = Instant::now();
+    let mut schedule = TickSchedule::new(ms(16), start);
+
+    // Early, we sleep until the tick is due
+    assert_eq!(schedule.wait(start + ms(4)), Some(ms(12)));
+    assert_eq!(schedule.wait(start + ms(16)), None);
+    // The next one is due 16 ms after the last was due, not after it ran
+    assert_eq!(schedule.wait(start + ms(20)), Some(ms(12)));
+
+    // Behind, we tick back to back until we caught up
+    assert_eq!(schedule.wait(start + ms(70)), None);
+    assert_eq!(schedule.wait(start + ms(70)), None);
+    assert_eq!(schedule.wait(start + ms(70)), None);
+    assert_eq!(schedule.wait(start + ms(70)), Some(ms(10)));
+
+    // Stopping the thread lets go of the processor
+    let processor = Arc::new(Mutex::new(Chip8Processor::new()));
+    let timers = TimerThread::spawn(Arc::clone(&processor));
+    timers.stop();
+    assert_eq!(Arc::strong_count(&processor), 1);
+}
+
+
+#[test]
+fn test_undo() {
+    let mut processor = Chip8Processor::new();
+    // LD V0, 0x7B; LD I, 0x300; LD B, V0
+    processor.load_rom(&[0x60, 0x7B, 0xA3, 0x00, 0xF0, 0x33]).unwrap();
+    processor.set_undo_history(true);
+    let start = processor.snapshot();
+
+    processor.cycle_n(3).unwrap();
+    assert_eq!(&processor.ram[0x300..0x303], &[1, 2, 3]);
+
+    assert!(processor.undo());
+    assert_eq!(&processor.ram[0x300..0x303], &[0, 0, 0]);
+    assert_eq!(processor.pc(), START_ADDRESS + 4);
+    assert!(processor.undo());
+    assert!(processor.undo());
+    assert_eq!(processor.snapshot(), start);
+    assert!(!processor.undo());
+
+    // The history only goes so far back
+    processor.load_rom(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+    processor.cycle_n(300).unwrap();
+    let mut undone = 0;
+    while processor.undo() {
+        undone += 1;
+    }
+    assert_eq!(undone, 256);
+
+    // Without the history, there is nothing to undo
+    processor.set_undo_history(false);
+    processor.cycle().unwrap();
+    assert!(!processor.undo());
+}
+
+
+#[test]
+fn test_fetch_odd_length_rom() {
+    let mut processor = Chip8Processor::new();
+    // LD V0, 1; then a lone byte
+    processor.load_rom(&[0x60, 0x01, 0x70]).unwrap();
+
+    processor.cycle().unwrap();
+    assert_eq!(processor.cycle(), Err(Chip8Error::OutOfBoundsMemory(0x202)));
+    assert_eq!(processor.pc(), 0x202);
+
+    // Even-length ROMs run on into the RAM after them, like before
+    processor.load_rom(&[0x60, 0x01]).unwrap();
+    processor.cycle_n(2).unwrap();
+    assert_eq!(processor.pc(), 0x204);
+
+    // And nothing can be fetched from the last byte of the RAM
+    processor.program_counter = 0x0FFF;
+    assert_eq!(processor.cycle(), Err(Chip8Error::OutOfBoundsMemory(0x0FFF)));
+}
+
+#[test]
+fn test_strict_alignment() {
+    // JP 0x205, to a LD V0, 0x07 that starts on an odd address
+    let rom = [0x12, 0x05, 0x00, 0x00, 0x00, 0x60, 0x07, 0x00];
+    let mut processor = Chip8Processor::new();
+    processor.load_rom(&rom).unwrap();
+
+    // By default, we run whatever is there
+    processor.cycle_n(2).unwrap();
+    assert_eq!(processor.registers[0x0], 0x07);
+    assert_eq!(processor.pc(), 0x207);
+
+    processor.load_rom(&rom).unwrap();
+    processor.set_strict_alignment(true);
+    processor.cycle().unwrap();
+    assert_eq!(processor.cycle(), Err(Chip8Error::MisalignedPc));
+    assert_eq!(processor.pc(), 0x205);
+
+    // It sticks around after a reset
+    processor.reset();
+    processor.load_rom(&rom).unwrap();
+    processor.cycle().unwrap();
+    assert_eq!(processor.cycle(), Err(Chip8Error::MisalignedPc));
+}
+
+
+#[test]
+fn test_registers_dump() {
+    let mut processor = Chip8Processor::new();
+    processor.registers[0x0] = 0x2A;
+    processor.registers[0xF] = 0x01;
+    processor.i_register = 0x300;
+    processor.delay_timer = 0x3C;
+    processor.execute(0x2400).unwrap();
+
+    assert_eq!(
+        processor.registers_dump(),
+        "V0=2a V1=00 V2=00 V3=00 V4=00 V5=00 V6=00 V7=00\n\
+         V8=00 V9=00 VA=00 VB=00 VC=00 VD=00 VE=00 VF=01\n\
+         I=0300 PC=0400 SP=01 DT=3c ST=00\n"
+    );
+}
+
+
+#[test]
+fn test_sound_state_hook() {
+    use std::sync::{Arc, Mutex};
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let mut processor = Chip8Processor::new();
+    let recorded = Arc::clone(&events);
+    processor.on_sound_state_change(move |beeping| recorded.lock().unwrap().push(beeping));
+
+    processor.registers[0x2] = 3;
+    processor.execute(0xF218).unwrap();
+    // Setting it again while it runs is not a new edge
+    processor.execute(0xF218).unwrap();
+    for _ in 0..5 {
+        processor.tick_timers();
+    }
+
+    assert_eq!(*events.lock().unwrap(), vec![true, false]);
+
+    processor.clear_sound_hook();
+    processor.execute(0xF218).unwrap();
+    assert_eq!(events.lock().unwrap().len(), 2);
+}
+
+
+#[test]
+fn test_is_waiting_for_key() {
+    let mut processor = Chip8Processor::new();
+    processor.load_rom(&[0xF0, 0x0A, 0x12, 0x02]).unwrap();
+    assert!(!processor.is_waiting_for_key());
+
+    processor.cycle_n(3).unwrap();
+    assert!(processor.is_waiting_for_key());
+
+    processor.press_key(Chip8Key::K1);
+    processor.cycle().unwrap();
+    assert!(!processor.is_waiting_for_key());
+
+    // Looping on a jump is not waiting
+    processor.cycle_n(3).unwrap();
+    assert!(!processor.is_waiting_for_key());
+}
+
+
+#[test]
+fn test_decode() {
+    assert_eq!(decode(0x00E0), Instruction::Cls);
+    assert_eq!(decode(0x00EE), Instruction::Ret);
+    assert_eq!(decode(0x0123), Instruction::Sys(0x123));
+    assert_eq!(decode(0x1ABC), Instruction::Jp(0xABC));
+    assert_eq!(decode(0x6A12), Instruction::LdVxByte(0xA, 0x12));
+    assert_eq!(decode(0x8126), Instruction::Shr(0x1, 0x2));
+    assert_eq!(decode(0xD125), Instruction::Drw(0x1, 0x2, 5));
+    assert_eq!(decode(0xE3A1), Instruction::Sknp(0x3));
+    assert_eq!(decode(0xF465), Instruction::LdVxI(0x4));
+
+    // The extensions decode whatever the quirks
+    assert_eq!(decode(0x00FF), Instruction::High);
+    assert_eq!(decode(0x00FD), Instruction::Exit);
+    assert_eq!(decode(0xF230), Instruction::LdHfVx(0x2));
+    assert_eq!(decode(0xF000), Instruction::LdILong);
+    assert_eq!(decode(0xF301), Instruction::Plane(0x3));
+
+    assert_eq!(decode(0x5121), Instruction::Unknown(0x5121));
+    assert_eq!(decode(0xFFFF), Instruction::Unknown(0xFFFF));
+}
+
+
+#[test]
+fn test_ram_size_bounds() {
+    let mut processor = Chip8Processor::new();
+    assert_eq!(processor.ram.len(), RAM_SIZE);
+
+    assert_eq!(processor.write_mem(MAX_ADDR, 0xAB), Ok(()));
+    assert_eq!(processor.read_mem(MAX_ADDR), 0xAB);
+    assert_eq!(processor.write_mem(RAM_SIZE as u16, 1), Err(Chip8Error::OutOfBoundsMemory(RAM_SIZE as u16)));
+    assert_eq!(processor.read_mem(RAM_SIZE as u16), 0);
+
+    // Instructions going past the end are errors, not panics
+    processor.i_register = MAX_ADDR - 1;
+    assert_eq!(processor.execute(0xF255), Err(Chip8Error::OutOfBoundsMemory(RAM_SIZE as u16)));
+    assert_eq!(processor.execute(0xF265), Err(Chip8Error::OutOfBoundsMemory(RAM_SIZE as u16)));
+    assert_eq!(processor.execute(0xF033), Err(Chip8Error::OutOfBoundsMemory(RAM_SIZE as u16)));
+    // But the last bytes are still usable
+    assert_eq!(processor.execute(0xF155), Ok(()));
+    assert_eq!(processor.execute(0xD002), Ok(()));
+}
+
+#[test]
+fn test_sprite_rows_past_ram_end() {
+    let mut processor = Chip8Processor::new();
+    processor.write_mem(MAX_ADDR - 1, 0xF0).unwrap();
+    processor.write_mem(MAX_ADDR, 0xCC).unwrap();
+    processor.ram[0] = 0xAA;
+    processor.ram[12] = 0x81;
+
+    // The first two rows are at the end of the RAM, the other 13 come from
+    // its start
+    processor.i_register = MAX_ADDR - 1;
+    assert_eq!(processor.execute(0xD00F), Ok(()));
+    let row = |processor: &Chip8Processor, y: usize| {
+        (0..8).fold(0u8, |row, x| (row << 1) | processor.display.get(x, y) as u8)
+    };
+    assert_eq!(row(&processor, 0), 0xF0);
+    assert_eq!(row(&processor, 1), 0xCC);
+    assert_eq!(row(&processor, 2), 0xAA);
+    assert_eq!(row(&processor, 14), 0x81);
+
+    // Strictly, the sprite does not fit, and nothing is drawn
+    processor.set_quirks(Quirks { sprite_address_wrap: false, ..Quirks::default() });
+    assert_eq!(processor.execute(0xD00F), Err(Chip8Error::OutOfBoundsMemory(RAM_SIZE as u16)));
+    assert_eq!(row(&processor, 0), 0xF0);
+}
+
+
+#[test]
+fn test_key_latch() {
+    let mut processor = Chip8Processor::new();
+    processor.registers[0x0] = 0x7;
+
+    // Pressed and released before the program looked
+    processor.press_key(Chip8Key::K7);
+    processor.release_key(Chip8Key::K7);
+    processor.execute(0xE09E).unwrap();
+    assert_eq!(processor.pc(), START_ADDRESS + 2);
+    processor.execute(0xE0A1).unwrap();
+    assert_eq!(processor.pc(), START_ADDRESS + 2);
+
+    // The next frame doesn't see it any more
+    processor.end_frame();
+    processor.execute(0xE09E).unwrap();
+    assert_eq!(processor.pc(), START_ADDRESS + 2);
+
+    // FX0A catches a quick press while it waits, too
+    processor.load_rom(&[0xF1, 0x0A]).unwrap();
+    processor.cycle().unwrap();
+    let mut keys = [false; 16];
+    keys[0x3] = true;
+    processor.set_keypad(keys);
+    processor.set_keypad([false; 16]);
+    processor.cycle().unwrap();
+    assert_eq!(processor.registers[0x1], 0x3);
+    assert_eq!(processor.pc(), START_ADDRESS + 2);
+}
+
+/// A processor that runs every extension, so no known opcode is refused.
+fn processor_with_extensions() -> Chip8Processor {
+    Chip8Builder::new()
+        .quirks(Quirks { schip: true, xo_chip: true, ..Quirks::default() })
+        .build()
+}
+
+#[test]
+fn test_dispatch_routes_every_opcode() {
+    let handlers: [(&str, execute::Handler); 8] = [
+        ("system", Chip8Processor::exec_system),
+        ("flow", Chip8Processor::exec_flow),
+        ("skip", Chip8Processor::exec_skip),
+        ("load", Chip8Processor::exec_load),
+        ("alu", Chip8Processor::exec_alu),
+        ("draw", Chip8Processor::exec_draw),
+        ("keys", Chip8Processor::exec_keys),
+        ("misc", Chip8Processor::exec_misc),
+    ];
+    let template = processor_with_extensions();
+    let accepts = |handler: execute::Handler, opcode: u16| {
+        let mut processor = template.clone();
+        !matches!(handler(&mut processor, opcode), Err(Chip8Error::UnknownOpcode(_)))
+    };
+
+    for opcode in 0..=u16::MAX {
+        let known = !matches!(decode(opcode), Instruction::Unknown(_));
+
+        // The table sends every known opcode to a handler that runs it...
+        let routed = execute::DISPATCH[(opcode >> 12) as usize];
+        assert_eq!(accepts(routed, opcode), known, "{:#06x}", opcode);
+
+        // ...and no other handler would run it too
+        let takers: Vec<&str> = handlers
+            .iter()
+            .filter(|(_, handler)| accepts(*handler, opcode))
+            .map(|(name, _)| *name)
+            .collect();
+        assert_eq!(takers.len(), known as usize, "{:#06x} runs in {:?}", opcode, takers);
+    }
+}
+
+#[test]
+fn test_dispatch_handlers_run_their_family() {
+    let mut processor = processor_with_extensions();
+
+    processor.registers[0x1] = 0xF0;
+    processor.registers[0x2] = 0x20;
+    processor.exec_alu(0x8124).unwrap();
+    assert_eq!(processor.registers[0x1], 0x10);
+    assert_eq!(processor.registers[0xF], 1);
+
+    processor.exec_load(0x6A42).unwrap();
+    assert_eq!(processor.registers[0xA], 0x42);
+    processor.exec_flow(0x1300).unwrap();
+    assert_eq!(processor.program_counter, 0x300);
+    processor.exec_skip(0x3A42).unwrap();
+    assert_eq!(processor.program_counter, 0x302);
+
+    // Each handler refuses the others' opcodes, even the ones sharing
+    // a mnemonic (9XY0 is a skip, 8XY0 is a load)
+    assert_eq!(processor.exec_alu(0x6A42), Err(Chip8Error::UnknownOpcode(0x6A42)));
+    assert_eq!(processor.exec_load(0x8120), Err(Chip8Error::UnknownOpcode(0x8120)));
+    assert_eq!(processor.exec_misc(0xE19E), Err(Chip8Error::UnknownOpcode(0xE19E)));
+    assert_eq!(processor.exec_keys(0xF155), Err(Chip8Error::UnknownOpcode(0xF155)));
+    assert_eq!(processor.registers[0xA], 0x42);
+}
+
+#[cfg(feature = "poison")]
+#[test]
+fn test_uninitialized_reads() {
+    use std::sync::{Arc, Mutex};
+
+    let rom = asm::assemble("
+            LD V0, 5
+            ADD V0, V1
+            ADD V0, V1
+            LD [I], V0
+            LD I, 0x300
+            LD V2, [I]
+            LD V3, [I]
+    ").unwrap();
+
+    let warnings = Arc::new(Mutex::new(Vec::new()));
+    let mut processor = Chip8Processor::new();
+    let recorded = Arc::clone(&warnings);
+    processor.on_uninitialized_read(move |read| recorded.lock().unwrap().push(read));
+    processor.load_rom(&rom).unwrap();
+    processor.cycle_n(7).unwrap();
+
+    // V1 and I were never set, and neither was 0x300 to 0x303. Each is
+    // only reported the first time it is read.
+    let read = |pc, opcode, location| UninitializedRead { pc, opcode, location };
+    assert_eq!(*warnings.lock().unwrap(), [
+        read(0x202, 0x8014, Location::Register(0x1)),
+        read(0x206, 0xF055, Location::I),
+        read(0x20A, 0xF265, Location::Ram(0x300)),
+        read(0x20A, 0xF265, Location::Ram(0x301)),
+        read(0x20A, 0xF265, Location::Ram(0x302)),
+        read(0x20C, 0xF365, Location::Ram(0x303)),
+    ]);
+
+    // The ROM, the fonts and what the program wrote are fine to read
+    warnings.lock().unwrap().clear();
+    processor.registers[0x0] = 0;
+    processor.execute(0xA200).unwrap(); // LD I, 0x200
+    processor.load_rom(&[0xD0, 0x05]).unwrap(); // DRW V0, V0, 5
+    processor.cycle().unwrap();
+    assert!(warnings.lock().unwrap().is_empty());
+
+    // Off again, nothing is reported
+    processor.clear_poison();
+    processor.load_rom(&[0x80, 0xE4]).unwrap(); // ADD V0, VE
+    processor.cycle().unwrap();
+    assert!(warnings.lock().unwrap().is_empty());
+}
@@ -0,0 +1,11 @@
+/// What makes the delay and sound timers count down.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum TimerMode {
+    /// Only `tick_timers` and `advance_timers` do, so the front-end keeps
+    /// the timers in step with the real 60Hz.
+    #[default]
+    Manual,
+    /// The timers tick once every this many cycles, for headless drivers
+    /// with no clock of their own. Zero never ticks.
+    PerCycles(usize),
+}
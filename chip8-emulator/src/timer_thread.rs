@@ -0,0 +1,93 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::{Chip8Processor, TIMER_PERIOD};
+
+/// A thread that ticks the timers of a shared processor at 60Hz, so the
+/// front-end only has to run cycles.
+///
+/// The processor is only locked for the tick itself. Ticks are scheduled
+/// against the time the thread started, so they don't drift, and if the
+/// thread falls behind (say, the lock was busy) it catches up.
+///
+/// The thread stops when `stop` is called or the `TimerThread` is dropped.
+pub struct TimerThread {
+    running: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl TimerThread {
+    /// Start ticking the timers of `processor`.
+    pub fn spawn(processor: Arc<Mutex<Chip8Processor>>) -> Self {
+        let running = Arc::new(AtomicBool::new(true));
+        let flag = Arc::clone(&running);
+
+        let handle = thread::spawn(move || {
+            let mut schedule = TickSchedule::new(TIMER_PERIOD, Instant::now());
+
+            while flag.load(Ordering::Relaxed) {
+                if let Some(wait) = schedule.wait(Instant::now()) {
+                    thread::sleep(wait);
+                    continue;
+                }
+
+                // A poisoned lock means the other side panicked, so there
+                // is nothing left to tick.
+                match processor.lock() {
+                    Ok(mut processor) => processor.tick_timers(),
+                    Err(_) => return,
+                }
+            }
+        });
+
+        Self { running, handle: Some(handle) }
+    }
+
+    /// Stop the thread, and wait for it to finish.
+    pub fn stop(mut self) {
+        self.join();
+    }
+
+    fn join(&mut self) {
+        self.running.store(false, Ordering::Relaxed);
+        if let Some(handle) = self.handle.take() {
+            // The thread never panics while holding the lock, so there is
+            // nothing to do if it panicked anyway
+            let _ = handle.join();
+        }
+    }
+}
+
+impl Drop for TimerThread {
+    fn drop(&mut self) {
+        self.join();
+    }
+}
+
+/// When the ticks of a `TimerThread` are due: every `period` after it
+/// started, however late the last one ran. Kept apart from the thread so
+/// it can be tested without waiting for real time to pass.
+pub(crate) struct TickSchedule {
+    period: Duration,
+    next_tick: Instant,
+}
+
+impl TickSchedule {
+    /// A schedule whose first tick is due a period after `now`.
+    pub(crate) fn new(period: Duration, now: Instant) -> Self {
+        Self { period, next_tick: now + period }
+    }
+
+    /// How long to sleep at `now` until the next tick is due, or `None` if
+    /// it is due already. Then it counts as done, and the caller ticks.
+    pub(crate) fn wait(&mut self, now: Instant) -> Option<Duration> {
+        if now < self.next_tick {
+            return Some(self.next_tick - now);
+        }
+
+        self.next_tick += self.period;
+        None
+    }
+}
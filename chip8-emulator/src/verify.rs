@@ -0,0 +1,71 @@
+// Looking over a ROM for obvious mistakes, without running it.
+//
+// ROMs mix code and data, and we can't tell which is which without
+// running them, so every word is read as if it were an instruction. The
+// warnings are hints to look closer, not proof that something is wrong.
+use std::fmt;
+
+use crate::{decode, Instruction, MAX_ADDR, START_ADDRESS};
+
+/// Something `verify_rom` found suspicious. Addresses are where the ROM
+/// lands in RAM, from 0x200.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RomWarning {
+    /// The ROM has an odd number of bytes, so its last instruction is cut
+    /// short (or it isn't a CHIP-8 ROM at all).
+    OddLength(usize),
+    /// A word that isn't any instruction, which is fine if it is data.
+    UnknownOpcode { address: u16, opcode: u16 },
+    /// A jump or call below 0x200, into the fonts and the space the
+    /// interpreter kept for itself.
+    ReservedTarget { address: u16, opcode: u16, target: u16 },
+    /// The ROM is this many bytes long, more than fits between 0x200 and
+    /// the end of the 4 KiB RAM. Only XO-CHIP has room for it. Nothing
+    /// past the end is checked.
+    TooLong(usize),
+}
+
+impl fmt::Display for RomWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RomWarning::OddLength(len) => write!(f, "the ROM is {} bytes long, an odd number", len),
+            RomWarning::UnknownOpcode { address, opcode } =>
+                write!(f, "{:#05x}: unknown opcode {:#06x}", address, opcode),
+            RomWarning::ReservedTarget { address, opcode, target } =>
+                write!(f, "{:#05x}: {:#06x} goes to {:#05x}, below the program", address, opcode, target),
+            RomWarning::TooLong(len) => write!(f, "the ROM is {} bytes long, too big for 4 KiB of RAM", len),
+        }
+    }
+}
+
+/// Look over `rom` for unknown opcodes, jumps and calls into the reserved
+/// space below 0x200, and a length that can't hold whole instructions or
+/// doesn't fit in the RAM.
+pub fn verify_rom(rom: &[u8]) -> Vec<RomWarning> {
+    let mut warnings = Vec::new();
+
+    if !rom.len().is_multiple_of(2) {
+        warnings.push(RomWarning::OddLength(rom.len()));
+    }
+
+    for (offset, word) in rom.chunks_exact(2).enumerate() {
+        // In usize, as a ROM of 64 KiB or more would wrap around in u16
+        let address = START_ADDRESS as usize + offset * 2;
+        if address > MAX_ADDR as usize {
+            warnings.push(RomWarning::TooLong(rom.len()));
+            break;
+        }
+        let address = address as u16;
+        let opcode = u16::from_be_bytes([word[0], word[1]]);
+
+        match decode(opcode) {
+            Instruction::Unknown(_) => warnings.push(RomWarning::UnknownOpcode { address, opcode }),
+            Instruction::Jp(target) | Instruction::Call(target) if target < START_ADDRESS => {
+                warnings.push(RomWarning::ReservedTarget { address, opcode, target });
+            },
+            _ => (),
+        }
+    }
+
+    warnings
+}
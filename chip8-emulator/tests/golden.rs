@@ -0,0 +1,124 @@
+// Golden-image tests: run a ROM from `tests/roms/` headless for a fixed
+// number of frames, and compare the screen with the picture in
+// `tests/golden/`.
+//
+// The pictures are `display_ascii` dumps. To (re)make them after a change
+// that is meant to move pixels, run the tests with `CHIP8_BLESS=1` and look
+// at the diff before committing it.
+//
+// ROMs ending in `.asm` are put together with `asm::assemble` first, so the
+// test programs we wrote ourselves are kept as source. Their pictures are
+// named after the file without the extension.
+use std::path::{Path, PathBuf};
+
+use chip8_emulator::*;
+
+// Like the front-ends: a few instructions, then a timer tick.
+const CYCLES_PER_FRAME: usize = 10;
+const SEED: u64 = 0xC8;
+
+fn test_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests")
+}
+
+/// Run `rom` for `frames` frames with `quirks`, and check the screen against
+/// `golden/<rom>.txt`.
+fn check_golden(rom: &str, quirks: Quirks, frames: usize) {
+    let path = test_dir().join("roms").join(rom);
+    let (name, bytes) = match rom.strip_suffix(".asm") {
+        Some(name) => {
+            let source = std::fs::read_to_string(&path).unwrap();
+            let bytes = asm::assemble(&source).unwrap_or_else(|error| panic!("{}: {}", rom, error));
+            (name, bytes)
+        }
+        None => (rom, std::fs::read(&path).unwrap()),
+    };
+    let mut processor = Chip8Builder::new().quirks(quirks).seed(SEED).build();
+    processor.load_rom(&bytes).unwrap();
+
+    for _ in 0..frames {
+        processor.cycle_n(CYCLES_PER_FRAME).unwrap();
+        processor.tick_timers();
+    }
+
+    let actual = processor.display_ascii();
+    let golden = test_dir().join("golden").join(format!("{}.txt", name));
+
+    if std::env::var_os("CHIP8_BLESS").is_some() {
+        std::fs::write(&golden, &actual).unwrap();
+        return;
+    }
+
+    let expected = std::fs::read_to_string(&golden)
+        .unwrap_or_else(|error| panic!("{}: {} (run with CHIP8_BLESS=1 to make it)", golden.display(), error));
+
+    if let Some(diff) = ascii_diff(&expected, &actual) {
+        panic!("{} does not match {}:\n{}", rom, golden.display(), diff);
+    }
+}
+
+/// The rows that differ between two `display_ascii` pictures, with a `^`
+/// under every pixel that changed. `None` if they are the same.
+fn ascii_diff(expected: &str, actual: &str) -> Option<String> {
+    if expected == actual {
+        return None;
+    }
+
+    let expected: Vec<&str> = expected.lines().collect();
+    let actual: Vec<&str> = actual.lines().collect();
+    let mut diff = String::new();
+
+    if expected.len() != actual.len() {
+        diff.push_str(&format!("expected {} rows, got {}\n", expected.len(), actual.len()));
+    }
+
+    for (row, (want, got)) in expected.iter().zip(&actual).enumerate() {
+        if want == got {
+            continue;
+        }
+
+        let markers: String = want
+            .chars()
+            .zip(got.chars())
+            .map(|(a, b)| if a == b { ' ' } else { '^' })
+            .collect();
+        diff.push_str(&format!("row {:2} expected {}\n", row, want));
+        diff.push_str(&format!("       got      {}\n", got));
+        diff.push_str(&format!("                {}\n", markers));
+    }
+
+    Some(diff)
+}
+
+#[test]
+fn golden_maze() {
+    // MAZE picks every tile with CXNN, so this also checks the seeded RNG
+    check_golden("MAZE", Quirks::modern(), 100);
+}
+
+#[test]
+fn golden_brix() {
+    check_golden("BRIX", Quirks::cosmac_vip(), 300);
+}
+
+#[test]
+fn golden_opcodes() {
+    // Every check draws a tick when it passes and a cross when it doesn't
+    check_golden("opcodes.asm", Quirks::modern(), 50);
+}
+
+#[test]
+fn golden_flags() {
+    // Needs the VIP's shifts and logic VF reset, and with display_wait it
+    // only gets one mark a frame
+    check_golden("flags.asm", Quirks::cosmac_vip(), 100);
+}
+
+#[test]
+fn test_ascii_diff() {
+    assert_eq!(ascii_diff("#.\n..\n", "#.\n..\n"), None);
+    assert_eq!(
+        ascii_diff("#.\n..\n", "#.\n.#\n").unwrap(),
+        "row  1 expected ..\n       got      .#\n                 ^\n"
+    );
+}
@@ -0,0 +1,185 @@
+; Flags test, in the spirit of Timendus': the 8XYN instructions have to
+; get both the result and VF right, in that order, including when VF is
+; one of the operands. Each check draws a tick when it passes and a cross
+; when it fails, two per instruction: the result, then VF.
+;
+; This expects the COSMAC VIP quirks: 8XY6 and 8XYE shift VY, and 8XY1,
+; 8XY2 and 8XY3 clear VF.
+;
+; VA holds the result, VB what it should be, VC the flag right after the
+; instruction, VD and VE are where the next mark goes.
+        CLS
+        LD VD, 0
+        LD VE, 0
+
+; 8XY4, without and with a carry
+        LD V1, 0x10
+        LD V2, 0x20
+        ADD V1, V2
+        LD VC, VF
+        LD VA, V1
+        LD VB, 0x30
+        CALL check
+        LD VA, VC
+        LD VB, 0
+        CALL check
+        LD V1, 0xF0
+        LD V2, 0x20
+        ADD V1, V2
+        LD VC, VF
+        LD VA, V1
+        LD VB, 0x10
+        CALL check
+        LD VA, VC
+        LD VB, 1
+        CALL check
+
+; 8XY5, without and with a borrow
+        LD V1, 0x30
+        LD V2, 0x10
+        SUB V1, V2
+        LD VC, VF
+        LD VA, V1
+        LD VB, 0x20
+        CALL check
+        LD VA, VC
+        LD VB, 1
+        CALL check
+        LD V1, 0x10
+        LD V2, 0x30
+        SUB V1, V2
+        LD VC, VF
+        LD VA, V1
+        LD VB, 0xE0
+        CALL check
+        LD VA, VC
+        LD VB, 0
+        CALL check
+
+; 8XY7, without and with a borrow
+        LD V1, 0x10
+        LD V2, 0x30
+        SUBN V1, V2
+        LD VC, VF
+        LD VA, V1
+        LD VB, 0x20
+        CALL check
+        LD VA, VC
+        LD VB, 1
+        CALL check
+        LD V1, 0x30
+        LD V2, 0x10
+        SUBN V1, V2
+        LD VC, VF
+        LD VA, V1
+        LD VB, 0xE0
+        CALL check
+        LD VA, VC
+        LD VB, 0
+        CALL check
+
+; 8XY6 and 8XYE, shifting VY into VX
+        LD V1, 0xFF
+        LD V2, 0x05
+        SHR V1, V2
+        LD VC, VF
+        LD VA, V1
+        LD VB, 0x02
+        CALL check
+        LD VA, VC
+        LD VB, 1
+        CALL check
+        LD V1, 0x00
+        LD V2, 0x81
+        SHL V1, V2
+        LD VC, VF
+        LD VA, V1
+        LD VB, 0x02
+        CALL check
+        LD VA, VC
+        LD VB, 1
+        CALL check
+
+; 8XY1, 8XY2 and 8XY3 clear VF
+        LD VF, 1
+        LD V1, 0x0F
+        LD V2, 0xF0
+        OR V1, V2
+        LD VC, VF
+        LD VA, V1
+        LD VB, 0xFF
+        CALL check
+        LD VA, VC
+        LD VB, 0
+        CALL check
+        LD VF, 1
+        LD V1, 0x3C
+        LD V2, 0x0F
+        AND V1, V2
+        LD VC, VF
+        LD VA, V1
+        LD VB, 0x0C
+        CALL check
+        LD VA, VC
+        LD VB, 0
+        CALL check
+        LD VF, 1
+        LD V1, 0x3C
+        LD V2, 0x0F
+        XOR V1, V2
+        LD VC, VF
+        LD VA, V1
+        LD VB, 0x33
+        CALL check
+        LD VA, VC
+        LD VB, 0
+        CALL check
+
+; VF as an operand: the flag is written last, so it wins as VX...
+        LD VF, 0xF0
+        LD V2, 0x20
+        ADD VF, V2
+        LD VA, VF
+        LD VB, 1
+        CALL check
+        LD VF, 0x10
+        LD V2, 0x30
+        SUB VF, V2
+        LD VA, VF
+        LD VB, 0
+        CALL check
+        LD V2, 0x04
+        SHR VF, V2
+        LD VA, VF
+        LD VB, 0
+        CALL check
+
+; ...and is read before it is written as VY
+        LD V1, 0xF0
+        LD VF, 0x20
+        ADD V1, VF
+        LD VC, VF
+        LD VA, V1
+        LD VB, 0x10
+        CALL check
+        LD VA, VC
+        LD VB, 1
+        CALL check
+
+end:    JP end
+
+; Draw the mark for VA == VB, and move on to the next spot
+check:  LD I, pass
+        SNE VA, VB
+        JP mark
+        LD I, fail
+mark:   DRW VD, VE, 4
+        ADD VD, 5
+        SE VD, 60
+        RET
+        LD VD, 0
+        ADD VE, 5
+        RET
+
+pass:   DB 0x10, 0x20, 0xA0, 0x40
+fail:   DB 0x90, 0x60, 0x60, 0x90
@@ -0,0 +1,194 @@
+; Opcode test, in the spirit of corax89's: every instruction is run once
+; and its result compared with what it should be. Each check draws a tick
+; when it passes and a cross when it fails, left to right, top to bottom.
+;
+; Nothing here depends on the quirks, so it runs with any preset.
+;
+; VA holds the result, VB what it should be, VD and VE are where the next
+; mark goes.
+        CLS
+        LD VD, 0
+        LD VE, 0
+
+; 3XNN, skipping and not
+        LD V1, 5
+        LD VA, 1
+        SE V1, 5
+        LD VA, 0
+        LD VB, 1
+        CALL check
+        LD VA, 1
+        SE V1, 6
+        LD VA, 0
+        LD VB, 0
+        CALL check
+
+; 4XNN, skipping and not
+        LD VA, 1
+        SNE V1, 6
+        LD VA, 0
+        LD VB, 1
+        CALL check
+        LD VA, 1
+        SNE V1, 5
+        LD VA, 0
+        LD VB, 0
+        CALL check
+
+; 5XY0 and 9XY0
+        LD V2, 5
+        LD VA, 1
+        SE V1, V2
+        LD VA, 0
+        LD VB, 1
+        CALL check
+        LD V2, 6
+        LD VA, 1
+        SNE V1, V2
+        LD VA, 0
+        LD VB, 1
+        CALL check
+
+; 1NNN
+        LD VA, 1
+        JP jumped
+        LD VA, 0
+jumped: LD VB, 1
+        CALL check
+
+; 2NNN and 00EE
+        LD VA, 0
+        CALL subroutine
+        LD VB, 0x55
+        CALL check
+
+; BNNN, into the second entry of a table
+        LD V0, 4
+        JP V0, table
+table:  LD VA, 0
+        JP tabled
+        LD VA, 1
+tabled: LD VB, 1
+        CALL check
+
+; 7XNN, wrapping around
+        LD VA, 0xF0
+        ADD VA, 0x20
+        LD VB, 0x10
+        CALL check
+
+; 8XY0 to 8XY7, 8XY6 and 8XYE
+        LD V1, 0x42
+        LD VA, V1
+        LD VB, 0x42
+        CALL check
+        LD VA, 0x0F
+        LD V1, 0xF0
+        OR VA, V1
+        LD VB, 0xFF
+        CALL check
+        LD VA, 0x3C
+        LD V1, 0x0F
+        AND VA, V1
+        LD VB, 0x0C
+        CALL check
+        LD VA, 0x3C
+        LD V1, 0x0F
+        XOR VA, V1
+        LD VB, 0x33
+        CALL check
+        LD VA, 0x12
+        LD V1, 0x34
+        ADD VA, V1
+        LD VB, 0x46
+        CALL check
+        LD VA, 0x34
+        LD V1, 0x12
+        SUB VA, V1
+        LD VB, 0x22
+        CALL check
+        LD VA, 0x12
+        LD V1, 0x34
+        SUBN VA, V1
+        LD VB, 0x22
+        CALL check
+        LD VA, 0x84
+        SHR VA, VA
+        LD VB, 0x42
+        CALL check
+        LD VA, 0x21
+        SHL VA, VA
+        LD VB, 0x42
+        CALL check
+
+; ANNN, FX55 and FX65
+        LD I, scratch
+        LD V0, 0x11
+        LD V1, 0x22
+        LD [I], V1
+        LD V0, 0
+        LD V1, 0
+        LD I, scratch
+        LD V1, [I]
+        LD VA, V0
+        LD VB, 0x11
+        CALL check
+        LD VA, V1
+        LD VB, 0x22
+        CALL check
+
+; FX1E
+        LD I, scratch
+        LD V0, 1
+        ADD I, V0
+        LD V0, [I]
+        LD VA, V0
+        LD VB, 0x22
+        CALL check
+
+; FX33
+        LD V0, 137
+        LD I, scratch
+        LD B, V0
+        LD V2, [I]
+        LD VA, V0
+        LD VB, 1
+        CALL check
+        LD VA, V1
+        LD VB, 3
+        CALL check
+        LD VA, V2
+        LD VB, 7
+        CALL check
+
+; FX29, the top row of the "A"
+        LD V0, 0xA
+        LD F, V0
+        LD V0, [I]
+        LD VA, V0
+        LD VB, 0xF0
+        CALL check
+
+end:    JP end
+
+subroutine:
+        LD VA, 0x55
+        RET
+
+; Draw the mark for VA == VB, and move on to the next spot
+check:  LD I, pass
+        SNE VA, VB
+        JP mark
+        LD I, fail
+mark:   DRW VD, VE, 4
+        ADD VD, 5
+        SE VD, 60
+        RET
+        LD VD, 0
+        ADD VE, 5
+        RET
+
+pass:   DB 0x10, 0x20, 0xA0, 0x40
+fail:   DB 0x90, 0x60, 0x60, 0x90
+scratch:
+        DB 0, 0, 0, 0
@@ -0,0 +1,156 @@
+// The beep, played through SDL while the sound timer is running.
+//
+// What the beep sounds like is up to a `ToneGenerator`, which only has to
+// say how loud the wave is at any point of its period, so it can be tested
+// without opening an audio device.
+//
+// XO-CHIP programs can load their own sound with F002 and FX3A: a loop of
+// 128 one-bit samples, played at a rate set by the pitch. Once a program
+// has loaded one, the beeper plays that instead of the tone.
+use std::f32::consts::TAU;
+
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+use sdl2::AudioSubsystem;
+
+const TONE_HZ: f32 = 440.0;
+const VOLUME: f32 = 0.1;
+const PATTERN_BITS: f32 = 128.0;
+
+/// The shape of one period of the beep.
+pub trait ToneGenerator: Send {
+    /// The level of the wave, between -1 and 1, at `t` (from 0 to 1)
+    /// through its period.
+    fn sample(&mut self, t: f32) -> f32;
+}
+
+/// A plain square wave, the classic beep.
+pub struct Square;
+
+impl ToneGenerator for Square {
+    fn sample(&mut self, t: f32) -> f32 {
+        if t < 0.5 { 1.0 } else { -1.0 }
+    }
+}
+
+/// A sine wave, softer on the ears.
+pub struct Sine;
+
+impl ToneGenerator for Sine {
+    fn sample(&mut self, t: f32) -> f32 {
+        (t * TAU).sin()
+    }
+}
+
+/// An XO-CHIP audio pattern: 128 samples, one per bit, most significant
+/// bit first. One period of the wave is the whole pattern.
+pub struct Pattern(pub [u8; 16]);
+
+impl ToneGenerator for Pattern {
+    fn sample(&mut self, t: f32) -> f32 {
+        let bit = ((t * PATTERN_BITS) as usize).min(127);
+        if self.0[bit / 8] & (0x80 >> (bit % 8)) != 0 { 1.0 } else { -1.0 }
+    }
+}
+
+/// The waveforms `--waveform` can pick.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Waveform {
+    #[default]
+    Square,
+    Sine,
+}
+
+impl Waveform {
+    /// Understand the value of `--waveform`.
+    pub fn parse(name: &str) -> Result<Self, String> {
+        match name {
+            "square" => Ok(Waveform::Square),
+            "sine" => Ok(Waveform::Sine),
+            _ => Err(format!("Unknown waveform: {} (expected square or sine)", name)),
+        }
+    }
+
+    /// A generator playing this waveform.
+    pub fn generator(self) -> Box<dyn ToneGenerator> {
+        match self {
+            Waveform::Square => Box::new(Square),
+            Waveform::Sine => Box::new(Sine),
+        }
+    }
+}
+
+/// Plays `generator`, `phase_increment` periods per sample.
+pub struct Tone {
+    generator: Box<dyn ToneGenerator>,
+    phase_increment: f32,
+    phase: f32,
+}
+
+impl AudioCallback for Tone {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        for sample in out.iter_mut() {
+            *sample = self.generator.sample(self.phase) * VOLUME;
+            self.phase = (self.phase + self.phase_increment) % 1.0;
+        }
+    }
+}
+
+/// Starts and stops the beep.
+pub struct Beeper {
+    device: AudioDevice<Tone>,
+    waveform: Waveform, // What to go back to when there's no pattern
+    freq: f32, // Samples per second of the audio device
+    pattern: Option<([u8; 16], f32)>, // The XO-CHIP pattern and rate playing, if any
+}
+
+impl Beeper {
+    pub fn new(audio: &AudioSubsystem, waveform: Waveform) -> Result<Self, String> {
+        let desired = AudioSpecDesired {
+            freq: Some(44_100),
+            channels: Some(1),
+            samples: None,
+        };
+
+        let device = audio.open_playback(None, &desired, |spec| Tone {
+            generator: waveform.generator(),
+            phase_increment: TONE_HZ / spec.freq as f32,
+            phase: 0.0,
+        })?;
+        let freq = device.spec().freq as f32;
+
+        Ok(Self { device, waveform, freq, pattern: None })
+    }
+
+    /// Play the XO-CHIP `pattern` at `rate` bits a second, or the usual tone
+    /// if `None`. Cheap to call every frame: the callback is only touched
+    /// when something changed.
+    pub fn set_pattern(&mut self, pattern: Option<([u8; 16], f32)>) {
+        if pattern == self.pattern {
+            return;
+        }
+        self.pattern = pattern;
+
+        let mut tone = self.device.lock();
+        match pattern {
+            Some((bits, rate)) => {
+                tone.generator = Box::new(Pattern(bits));
+                tone.phase_increment = rate / PATTERN_BITS / self.freq;
+            }
+            None => {
+                tone.generator = self.waveform.generator();
+                tone.phase_increment = TONE_HZ / self.freq;
+            }
+        }
+    }
+
+    /// Play the beep if `beeping`, otherwise keep quiet.
+    pub fn set_beeping(&mut self, beeping: bool) {
+        if beeping {
+            self.device.resume();
+        } else {
+            self.device.pause();
+        }
+    }
+}
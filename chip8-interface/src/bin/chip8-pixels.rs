@@ -0,0 +1,140 @@
+// A front-end on `pixels` and `winit`, for where SDL is hard to install.
+// Build it with `cargo run --bin chip8-pixels --no-default-features --features pixels`.
+use std::env;
+use std::time::{Duration, Instant};
+
+use chip8_emulator::*;
+use chip8_interface::frame::framebuffer_to_rgba;
+use chip8_interface::keymap::char_to_chip8_key;
+use chip8_interface::{CYCLES_PER_FRAME, WINDOW_HEIGHT, WINDOW_WIDTH};
+use pixels::{Pixels, SurfaceTexture};
+use winit::dpi::LogicalSize;
+use winit::event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent};
+use winit::event_loop::EventLoop;
+use winit::window::WindowBuilder;
+
+const FRAME_TIME: Duration = Duration::from_millis(16);
+
+fn main() {
+    let args: Vec<_> = env::args().collect();
+
+    if args.len() != 2 {
+        println!("Invalid number of args\nUsage: chip8-pixels <path>");
+        return ;
+    }
+
+    let mut processor = Chip8Processor::new();
+
+    if let Err(error) = processor.load_rom_from_path(&args[1]) {
+        println!("Unable to load ROM: {}", error);
+        return ;
+    }
+
+    let event_loop = EventLoop::new();
+    let window = WindowBuilder::new()
+        .with_title("Chip8 Emulator")
+        .with_inner_size(LogicalSize::new(WINDOW_WIDTH, WINDOW_HEIGHT))
+        .build(&event_loop)
+        .unwrap();
+
+    let window_size = window.inner_size();
+    let surface = SurfaceTexture::new(window_size.width, window_size.height, &window);
+    // `pixels` scales the buffer up by whole numbers, so pixels stay sharp
+    let (mut width, mut height) = processor.display_dimensions();
+    let mut pixels = Pixels::new(width as u32, height as u32, surface).unwrap();
+
+    let mut emulator = Emulator::new(processor, (CYCLES_PER_FRAME * 60) as u32);
+
+    let mut keypad = [false; 16];
+    let mut last_frame = Instant::now();
+
+    event_loop.run(move |event, _, control_flow| {
+        match event {
+            Event::WindowEvent { event: WindowEvent::CloseRequested, .. } => control_flow.set_exit(),
+            Event::WindowEvent { event: WindowEvent::Resized(size), .. } => {
+                if let Err(error) = pixels.resize_surface(size.width, size.height) {
+                    println!("Unable to resize: {}", error);
+                    control_flow.set_exit();
+                }
+            },
+            Event::WindowEvent {
+                event: WindowEvent::KeyboardInput {
+                    input: KeyboardInput { virtual_keycode: Some(key), state, .. },
+                    ..
+                },
+                ..
+            } => {
+                if key == VirtualKeyCode::Escape {
+                    control_flow.set_exit();
+                } else if let Some(chip_key) = virtual_key_to_chip8_key(key) {
+                    keypad[chip_key.index()] = state == ElementState::Pressed;
+                }
+            },
+            Event::MainEventsCleared => {
+                let now = Instant::now();
+                if now - last_frame < FRAME_TIME {
+                    control_flow.set_wait_until(last_frame + FRAME_TIME);
+                    return;
+                }
+
+                match emulator.run_frame(now - last_frame, keypad) {
+                    Ok(output) if output.display_changed => window.request_redraw(),
+                    Ok(_) => (),
+                    Err(error) => {
+                        println!("The emulator stopped: {}", error);
+                        control_flow.set_exit();
+                        return;
+                    },
+                }
+                last_frame = now;
+
+                control_flow.set_wait_until(last_frame + FRAME_TIME);
+            },
+            Event::RedrawRequested(_) => {
+                let processor = emulator.processor();
+
+                // SCHIP can change the resolution under us
+                if processor.display_dimensions() != (width, height) {
+                    (width, height) = processor.display_dimensions();
+                    if let Err(error) = pixels.resize_buffer(width as u32, height as u32) {
+                        println!("Unable to resize: {}", error);
+                        control_flow.set_exit();
+                        return;
+                    }
+                }
+
+                framebuffer_to_rgba(processor.get_display(), pixels.frame_mut());
+                if let Err(error) = pixels.render() {
+                    println!("Unable to draw: {}", error);
+                    control_flow.set_exit();
+                }
+            },
+            _ => (),
+        }
+    });
+}
+
+/// Find the character on the key, to look it up in the shared layout.
+fn virtual_key_to_chip8_key(key: VirtualKeyCode) -> Option<Chip8Key> {
+    let c = match key {
+        VirtualKeyCode::Key1 => '1',
+        VirtualKeyCode::Key2 => '2',
+        VirtualKeyCode::Key3 => '3',
+        VirtualKeyCode::Key4 => '4',
+        VirtualKeyCode::Q => 'q',
+        VirtualKeyCode::W => 'w',
+        VirtualKeyCode::E => 'e',
+        VirtualKeyCode::R => 'r',
+        VirtualKeyCode::A => 'a',
+        VirtualKeyCode::S => 's',
+        VirtualKeyCode::D => 'd',
+        VirtualKeyCode::F => 'f',
+        VirtualKeyCode::Z => 'z',
+        VirtualKeyCode::X => 'x',
+        VirtualKeyCode::C => 'c',
+        VirtualKeyCode::V => 'v',
+        _ => return None,
+    };
+
+    char_to_chip8_key(c)
+}
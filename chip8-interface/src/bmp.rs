@@ -0,0 +1,64 @@
+// Screenshots as 1-bit BMP files, for bug reports. The format is simple
+// enough to write by hand, so we don't need an image crate for it.
+//
+// The file is a 14-byte file header, a 40-byte BITMAPINFOHEADER, a
+// two-color palette and the pixels, one bit each. Rows go from the bottom
+// of the picture up, and each one is padded to a multiple of 4 bytes.
+use std::path::Path;
+
+use chip8_emulator::Chip8Processor;
+
+const HEADERS_SIZE: usize = 14 + 40;
+// Black for unlit pixels, white for lit ones, as BGRA
+const PALETTE: [u8; 8] = [0, 0, 0, 0, 255, 255, 255, 0];
+// 72 DPI, in pixels per meter
+const PIXELS_PER_METER: u32 = 2835;
+
+/// Encode `pixels`, `width` to a row, as a black and white BMP file.
+pub fn encode_bmp(pixels: &[bool], width: usize, height: usize) -> Vec<u8> {
+    let row_size = width.div_ceil(32) * 4;
+    let data_offset = HEADERS_SIZE + PALETTE.len();
+    let file_size = data_offset + row_size * height;
+
+    let mut bmp = Vec::with_capacity(file_size);
+    let put_u16 = |bmp: &mut Vec<u8>, value: u16| bmp.extend_from_slice(&value.to_le_bytes());
+    let put_u32 = |bmp: &mut Vec<u8>, value: u32| bmp.extend_from_slice(&value.to_le_bytes());
+
+    // File header
+    bmp.extend_from_slice(b"BM");
+    put_u32(&mut bmp, file_size as u32);
+    put_u32(&mut bmp, 0); // Reserved
+    put_u32(&mut bmp, data_offset as u32);
+
+    // BITMAPINFOHEADER
+    put_u32(&mut bmp, 40);
+    put_u32(&mut bmp, width as u32);
+    put_u32(&mut bmp, height as u32); // Positive, so bottom-up
+    put_u16(&mut bmp, 1); // One color plane
+    put_u16(&mut bmp, 1); // One bit per pixel
+    put_u32(&mut bmp, 0); // No compression
+    put_u32(&mut bmp, (row_size * height) as u32);
+    put_u32(&mut bmp, PIXELS_PER_METER);
+    put_u32(&mut bmp, PIXELS_PER_METER);
+    put_u32(&mut bmp, 2); // Colors in the palette
+    put_u32(&mut bmp, 0); // All of them matter
+
+    bmp.extend_from_slice(&PALETTE);
+
+    for row in pixels.chunks_exact(width).take(height).rev() {
+        let mut packed = vec![0u8; row_size];
+        for (x, _) in row.iter().enumerate().filter(|(_, on)| **on) {
+            packed[x / 8] |= 0x80 >> (x % 8);
+        }
+        bmp.extend_from_slice(&packed);
+    }
+
+    bmp
+}
+
+/// Save the screen of `processor` to `path`, as a BMP file.
+pub fn save_bmp(processor: &Chip8Processor, path: &Path) -> Result<(), String> {
+    let (width, height) = processor.display_dimensions();
+    std::fs::write(path, encode_bmp(processor.get_display(), width, height))
+        .map_err(|error| format!("unable to write {}: {}", path.display(), error))
+}
@@ -0,0 +1,228 @@
+// The `chip8.toml` config file, and the command line flags that override it.
+//
+// A config looks like this, and every part of it is optional:
+//
+//  scale = 10
+//  speed = 12
+//  turbo = 4
+//  ghosting = 3
+//
+//  [theme]
+//  foreground = [255, 176, 0]
+//  background = [40, 20, 0]
+//
+//  [quirks]
+//  shift_uses_vy = true
+//
+//  [keymap]
+//  u = 0xC
+//
+//  [gamepad]
+//  a = 0x6
+use std::collections::HashMap;
+use std::io;
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+use chip8_emulator::{Chip8Key, Quirks};
+use serde::Deserialize;
+
+use crate::frame::Theme;
+use crate::keymap::{button_name_to_chip8_key, char_to_chip8_key, chip8_key_to_char, GAMEPAD_BUTTONS};
+use crate::{CYCLES_PER_FRAME, SCALE, TURBO_FACTOR};
+
+/// The file we look for in the working directory when `--config` isn't given.
+pub const CONFIG_FILE: &str = "chip8.toml";
+
+const SCALE_RANGE: RangeInclusive<u32> = 1..=64;
+const SPEED_RANGE: RangeInclusive<usize> = 1..=1000;
+const TURBO_RANGE: RangeInclusive<usize> = 1..=100;
+const GHOSTING_RANGE: RangeInclusive<u8> = 0..=60;
+
+/// Everything the interface can be told, from the config file and the flags.
+#[derive(Deserialize, Clone, PartialEq, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// How many window pixels each CHIP-8 pixel takes.
+    pub scale: u32,
+    /// How many instructions we run each 60th of a second, whatever the
+    /// frame rate of the screen.
+    pub speed: usize,
+    /// How much faster we run while the turbo key is held.
+    pub turbo: usize,
+    /// Over how many frames pixels fade out once they go dark, or 0 for
+    /// at once.
+    pub ghosting: u8,
+    pub theme: Theme,
+    pub quirks: Quirks,
+    /// Extra keys, from the character on the keyboard to the keypad key
+    /// (0x0 to 0xF). The usual layout still works for the other keys.
+    pub keymap: HashMap<String, u8>,
+    /// Controller buttons, by their SDL name (like "a" or "dpup"), to the
+    /// keypad key. The usual layout still works for the other buttons.
+    pub gamepad: HashMap<String, u8>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            scale: SCALE,
+            speed: CYCLES_PER_FRAME,
+            turbo: TURBO_FACTOR,
+            ghosting: 0,
+            theme: Theme::default(),
+            quirks: Quirks::default(),
+            keymap: HashMap::new(),
+            gamepad: HashMap::new(),
+        }
+    }
+}
+
+impl Config {
+    /// Parse a config file, checking that every value makes sense.
+    pub fn from_toml(text: &str) -> Result<Config, String> {
+        let mut config: Config = toml::from_str(text).map_err(|error| error.to_string())?;
+
+        // Keys are looked up in lowercase, like the usual layout
+        let mut keymap = HashMap::new();
+        for (key, value) in config.keymap {
+            let mut chars = key.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) if value < 16 => {
+                    keymap.insert(c.to_lowercase().collect(), value);
+                },
+                (Some(_), None) => return Err(format!("keymap: {:#x} is not a keypad key", value)),
+                _ => return Err(format!("keymap: \"{}\" is not a single key", key)),
+            }
+        }
+        config.keymap = keymap;
+
+        let mut gamepad = HashMap::new();
+        for (button, value) in config.gamepad {
+            let name = button.to_lowercase();
+            if !GAMEPAD_BUTTONS.contains(&name.as_str()) {
+                return Err(format!("gamepad: \"{}\" is not a controller button", button));
+            }
+            if value >= 16 {
+                return Err(format!("gamepad: {:#x} is not a keypad key", value));
+            }
+            gamepad.insert(name, value);
+        }
+        config.gamepad = gamepad;
+
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// The keypad key under the character `c`, looking at our keymap before
+    /// the usual layout.
+    pub fn key(&self, c: char) -> Option<Chip8Key> {
+        let lower: String = c.to_lowercase().collect();
+        match self.keymap.get(&lower) {
+            Some(index) => Chip8Key::from_index(*index),
+            None => char_to_chip8_key(c),
+        }
+    }
+
+    /// The keypad key the controller button `name` presses, looking at our
+    /// gamepad bindings before the usual layout.
+    pub fn button(&self, name: &str) -> Option<Chip8Key> {
+        match self.gamepad.get(name) {
+            Some(index) => Chip8Key::from_index(*index),
+            None => button_name_to_chip8_key(name),
+        }
+    }
+
+    /// What to write on each key of an on-screen keypad, by keypad index:
+    /// every keyboard key that presses it, uppercase and separated by `/`,
+    /// like "Q/U". The usual key comes first, unless the keymap took it
+    /// for another one.
+    pub fn key_labels(&self) -> [String; 16] {
+        let mut labels: [Vec<String>; 16] = Default::default();
+
+        for (index, labels) in labels.iter_mut().enumerate() {
+            let key = Chip8Key::from_index(index as u8).unwrap();
+            let usual = chip8_key_to_char(key);
+            if self.key(usual) == Some(key) {
+                labels.push(usual.to_uppercase().collect());
+            }
+        }
+
+        let mut extra: Vec<_> = self.keymap.iter().collect();
+        extra.sort();
+        for (c, index) in extra {
+            let label = c.to_uppercase();
+            if !labels[*index as usize].contains(&label) {
+                labels[*index as usize].push(label);
+            }
+        }
+
+        labels.map(|labels| labels.join("/"))
+    }
+
+    /// Use the `--scale=`, `--speed=`, `--turbo=`, `--platform=`,
+    /// `--palette=` and `--ghosting=` flags in `args` over whatever the
+    /// file said.
+    fn apply_args(&mut self, args: &[String]) -> Result<(), String> {
+        for arg in args {
+            if let Some(value) = arg.strip_prefix("--scale=") {
+                self.scale = value.parse().map_err(|_| format!("Invalid scale: {}", value))?;
+            } else if let Some(value) = arg.strip_prefix("--speed=") {
+                self.speed = value.parse().map_err(|_| format!("Invalid speed: {}", value))?;
+            } else if let Some(value) = arg.strip_prefix("--turbo=") {
+                self.turbo = value.parse().map_err(|_| format!("Invalid turbo factor: {}", value))?;
+            } else if let Some(value) = arg.strip_prefix("--platform=") {
+                self.quirks = Quirks::preset(value).ok_or_else(|| format!("Unknown platform: {}", value))?;
+            } else if let Some(value) = arg.strip_prefix("--ghosting=") {
+                self.ghosting = value.parse().map_err(|_| format!("Invalid ghosting: {}", value))?;
+            } else if let Some(value) = arg.strip_prefix("--palette=") {
+                self.theme = self.theme.with_palette(value)?;
+            }
+        }
+
+        self.validate()
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        check("scale", self.scale, SCALE_RANGE)?;
+        check("speed", self.speed, SPEED_RANGE)?;
+        check("turbo", self.turbo, TURBO_RANGE)?;
+        check("ghosting", self.ghosting, GHOSTING_RANGE)
+    }
+}
+
+fn check<T: PartialOrd + std::fmt::Display>(name: &str, value: T, range: RangeInclusive<T>) -> Result<(), String> {
+    if range.contains(&value) {
+        Ok(())
+    } else {
+        Err(format!(
+            "{} must be between {} and {}, not {}",
+            name, range.start(), range.end(), value
+        ))
+    }
+}
+
+/// Build the config from the command line `args`.
+///
+/// The file comes from `--config=<path>`, or else `chip8.toml` in `dir`. Not
+/// having a `chip8.toml` is fine, but a file asked for with `--config` has
+/// to be there. Flags on the command line win over the file.
+pub fn load_config(args: &[String], dir: &Path) -> Result<Config, String> {
+    let explicit = args.iter().find_map(|arg| arg.strip_prefix("--config="));
+    let path = match explicit {
+        Some(path) => Path::new(path).to_path_buf(),
+        None => dir.join(CONFIG_FILE),
+    };
+
+    let mut config = match std::fs::read_to_string(&path) {
+        Ok(text) => Config::from_toml(&text)
+            .map_err(|error| format!("{}: {}", path.display(), error))?,
+        Err(error) if error.kind() == io::ErrorKind::NotFound && explicit.is_none() => {
+            Config::default()
+        },
+        Err(error) => return Err(format!("{}: {}", path.display(), error)),
+    };
+
+    config.apply_args(args)?;
+    Ok(config)
+}
@@ -0,0 +1,131 @@
+// Turning the CHIP-8 screen into colors, for the front-ends that draw
+// through a texture.
+use serde::Deserialize;
+
+/// The color of unlit pixels, as RGBA.
+pub const BACKGROUND: [u8; 4] = [0, 0, 0, 255];
+/// The color of lit pixels, as RGBA.
+pub const FOREGROUND: [u8; 4] = [255, 255, 255, 255];
+
+/// The colors of the screen, as RGB. The default is white on black.
+///
+/// XO-CHIP has two planes, so four colors: the background where neither
+/// is on, the foreground for the first plane, and two more for the second
+/// plane and for both.
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Theme {
+    pub foreground: [u8; 3],
+    pub background: [u8; 3],
+    pub second_plane: [u8; 3],
+    pub both_planes: [u8; 3],
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        let [fr, fg, fb, _] = FOREGROUND;
+        let [br, bg, bb, _] = BACKGROUND;
+        Self {
+            foreground: [fr, fg, fb],
+            background: [br, bg, bb],
+            second_plane: [255, 0, 0],
+            both_planes: [0, 0, 255],
+        }
+    }
+}
+
+impl Theme {
+    /// Fill `frame` with the RGBA colors of `pixels`, four bytes per pixel.
+    pub fn paint(&self, pixels: &[bool], frame: &mut [u8]) {
+        for (pixel, color) in pixels.iter().zip(frame.chunks_exact_mut(4)) {
+            let [r, g, b] = if *pixel { self.foreground } else { self.background };
+            color.copy_from_slice(&[r, g, b, 255]);
+        }
+    }
+
+    /// The color for an XO-CHIP color index, from 0 to 3 (see
+    /// `Chip8Processor::get_display_colors`).
+    pub fn color(&self, index: u8) -> [u8; 3] {
+        match index & 0b11 {
+            0 => self.background,
+            1 => self.foreground,
+            2 => self.second_plane,
+            _ => self.both_planes,
+        }
+    }
+
+    /// This theme with the colors of a `--palette`: comma-separated
+    /// `RRGGBB` hex colors, like `000000,ffffff,ff0000,0000ff`.
+    ///
+    /// Two colors are the background and the foreground, like the classic
+    /// screen has, and leave the XO-CHIP ones alone. Four are all of them,
+    /// in color index order.
+    pub fn with_palette(self, palette: &str) -> Result<Theme, String> {
+        let colors = palette.split(',')
+            .map(|text| parse_color(text.trim()))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        match colors.as_slice() {
+            [background, foreground] => Ok(Theme { background: *background, foreground: *foreground, ..self }),
+            [background, foreground, second_plane, both_planes] => Ok(Theme {
+                background: *background,
+                foreground: *foreground,
+                second_plane: *second_plane,
+                both_planes: *both_planes,
+            }),
+            _ => Err(format!("A palette has 2 or 4 colors, not {}", colors.len())),
+        }
+    }
+
+    /// Like `paint`, but with a brightness per pixel (see `decay`), from the
+    /// background at 0 to the foreground at 255.
+    pub fn paint_faded(&self, brightness: &[u8], frame: &mut [u8]) {
+        for (level, color) in brightness.iter().zip(frame.chunks_exact_mut(4)) {
+            let blend = |channel: usize| {
+                let (from, to) = (self.background[channel] as u32, self.foreground[channel] as u32);
+                ((from * (255 - *level as u32) + to * *level as u32) / 255) as u8
+            };
+            color.copy_from_slice(&[blend(0), blend(1), blend(2), 255]);
+        }
+    }
+
+    /// Like `paint`, but with a color index per pixel instead of a bool.
+    pub fn paint_colors(&self, colors: &[u8], frame: &mut [u8]) {
+        for (index, color) in colors.iter().zip(frame.chunks_exact_mut(4)) {
+            let [r, g, b] = self.color(*index);
+            color.copy_from_slice(&[r, g, b, 255]);
+        }
+    }
+}
+
+/// Phosphor persistence, for `--ghosting`: update the `brightness` of
+/// every pixel for a new frame. Lit pixels are at full brightness (255),
+/// and pixels that went dark fade out over `frames` frames instead of
+/// snapping to the background, so sprites that flicker don't blink.
+///
+/// With 0 frames, pixels go dark at once, like without ghosting.
+pub fn decay(brightness: &mut [u8], pixels: &[bool], frames: u8) {
+    // Round up, so the last step always reaches 0
+    let step = if frames == 0 { 255 } else { 255u8.div_ceil(frames) };
+
+    for (level, on) in brightness.iter_mut().zip(pixels) {
+        *level = if *on { 255 } else { level.saturating_sub(step) };
+    }
+}
+
+/// Understand a `RRGGBB` hex color, with or without a leading `#`.
+fn parse_color(text: &str) -> Result<[u8; 3], String> {
+    let hex = text.strip_prefix('#').unwrap_or(text);
+    let invalid = || format!("Not a RRGGBB color: {}", text);
+    if hex.len() != 6 || !hex.is_ascii() {
+        return Err(invalid());
+    }
+
+    let channel = |at: usize| u8::from_str_radix(&hex[at..at + 2], 16).map_err(|_| invalid());
+    Ok([channel(0)?, channel(2)?, channel(4)?])
+}
+
+/// Fill `frame` with the RGBA colors of `pixels` in the default theme.
+pub fn framebuffer_to_rgba(pixels: &[bool], frame: &mut [u8]) {
+    Theme::default().paint(pixels, frame);
+}
@@ -0,0 +1,84 @@
+// Game controller support, so the keypad can be played from the couch.
+use chip8_emulator::*;
+use chip8_interface::config::Config;
+use sdl2::controller::{Button, GameController};
+use sdl2::event::Event;
+use sdl2::GameControllerSubsystem;
+
+use crate::SdlKeypad;
+
+/// Keeps the one controller we listen to open, and swaps it when
+/// controllers are plugged in and out.
+pub struct Gamepad {
+    subsystem: GameControllerSubsystem,
+    controller: Option<GameController>,
+}
+
+impl Gamepad {
+    /// Start listening, opening the first controller that is connected, if any.
+    pub fn new(subsystem: GameControllerSubsystem) -> Self {
+        let controller = subsystem
+            .num_joysticks()
+            .ok()
+            .and_then(|count| {
+                (0..count)
+                    .filter(|index| subsystem.is_game_controller(*index))
+                    .find_map(|index| subsystem.open(index).ok())
+            });
+
+        Self { subsystem, controller }
+    }
+
+    /// React to the controller events, pressing and releasing keys on
+    /// `keypad` as the bindings of `config` say.
+    pub fn handle_event(&mut self, event: &Event, keypad: &mut SdlKeypad, config: &Config) {
+        match event {
+            Event::ControllerDeviceAdded { which, .. } if self.controller.is_none() => {
+                self.controller = self.subsystem.open(*which).ok();
+            },
+            Event::ControllerDeviceRemoved { which, .. }
+                if self.controller.as_ref().map(|c| c.instance_id()) == Some(*which) => {
+                self.controller = None;
+            },
+            Event::ControllerButtonDown { button, .. } => {
+                if let Some(key) = button_to_chip8_key(*button, config) {
+                    keypad.press(key);
+                }
+            },
+            Event::ControllerButtonUp { button, .. } => {
+                if let Some(key) = button_to_chip8_key(*button, config) {
+                    keypad.release(key);
+                }
+            },
+            _ => (),
+        }
+    }
+}
+
+/// The keypad key `button` presses, following the gamepad bindings of
+/// `config`.
+pub fn button_to_chip8_key(button: Button, config: &Config) -> Option<Chip8Key> {
+    config.button(button_name(button))
+}
+
+/// The name SDL gives `button`, which the config uses too. We don't ask
+/// SDL, so this works without a controller subsystem.
+fn button_name(button: Button) -> &'static str {
+    match button {
+        Button::A => "a",
+        Button::B => "b",
+        Button::X => "x",
+        Button::Y => "y",
+        Button::Back => "back",
+        Button::Guide => "guide",
+        Button::Start => "start",
+        Button::LeftStick => "leftstick",
+        Button::RightStick => "rightstick",
+        Button::LeftShoulder => "leftshoulder",
+        Button::RightShoulder => "rightshoulder",
+        Button::DPadUp => "dpup",
+        Button::DPadDown => "dpdown",
+        Button::DPadLeft => "dpleft",
+        Button::DPadRight => "dpright",
+    }
+}
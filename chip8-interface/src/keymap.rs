@@ -0,0 +1,62 @@
+// The keyboard layout: the left side of a QWERTY keyboard stands in for
+// the hex keypad, in the same positions.
+//
+//  1 2 3 C        1 2 3 4
+//  4 5 6 D   <-   Q W E R
+//  7 8 9 E        A S D F
+//  A 0 B F        Z X C V
+//
+// Controllers have a usual layout of their own, see `GAMEPAD_LAYOUT`.
+use chip8_emulator::Chip8Key;
+
+/// The character of every keypad key, row by row as on the keypad.
+const LAYOUT: [(char, Chip8Key); 16] = [
+    ('1', Chip8Key::K1), ('2', Chip8Key::K2), ('3', Chip8Key::K3), ('4', Chip8Key::KC),
+    ('q', Chip8Key::K4), ('w', Chip8Key::K5), ('e', Chip8Key::K6), ('r', Chip8Key::KD),
+    ('a', Chip8Key::K7), ('s', Chip8Key::K8), ('d', Chip8Key::K9), ('f', Chip8Key::KE),
+    ('z', Chip8Key::KA), ('x', Chip8Key::K0), ('c', Chip8Key::KB), ('v', Chip8Key::KF),
+];
+
+/// The keypad key under the character `c`, if there is one.
+pub fn char_to_chip8_key(c: char) -> Option<Chip8Key> {
+    let c = c.to_ascii_lowercase();
+    LAYOUT.iter().find(|(key_char, _)| *key_char == c).map(|(_, key)| *key)
+}
+
+/// The character on the keyboard for `key` in the usual layout, in
+/// lowercase. The inverse of `char_to_chip8_key`.
+pub fn chip8_key_to_char(key: Chip8Key) -> char {
+    LAYOUT.iter().find(|(_, layout_key)| *layout_key == key).map(|(c, _)| *c).unwrap()
+}
+
+/// What to write on `key` in an on-screen keypad, for the usual layout,
+/// like "Q". `Config::key_labels` knows about the keymap of the config too.
+pub fn chip8_key_to_display_name(key: Chip8Key) -> String {
+    chip8_key_to_char(key).to_uppercase().collect()
+}
+
+/// The controller buttons, by the names SDL gives them, as the `[gamepad]`
+/// part of the config calls them.
+pub const GAMEPAD_BUTTONS: [&str; 15] = [
+    "a", "b", "x", "y", "back", "guide", "start", "leftstick", "rightstick",
+    "leftshoulder", "rightshoulder", "dpup", "dpdown", "dpleft", "dpright",
+];
+
+/// The usual controller layout: the D-pad goes on 2/4/6/8, the keys most
+/// games move with, and the face buttons on the usual "action" keys.
+const GAMEPAD_LAYOUT: [(&str, Chip8Key); 10] = [
+    ("dpup", Chip8Key::K2), ("dpleft", Chip8Key::K4), ("dpright", Chip8Key::K6), ("dpdown", Chip8Key::K8),
+    ("a", Chip8Key::K5), ("b", Chip8Key::KA), ("x", Chip8Key::K0), ("y", Chip8Key::KB),
+    ("start", Chip8Key::K1), ("back", Chip8Key::KF),
+];
+
+/// The keypad key the controller button `name` presses in the usual
+/// layout, if any.
+pub fn button_name_to_chip8_key(name: &str) -> Option<Chip8Key> {
+    GAMEPAD_LAYOUT.iter().find(|(button, _)| *button == name).map(|(_, key)| *key)
+}
+
+/// The display names of the usual layout, by keypad index.
+pub fn default_key_labels() -> [String; 16] {
+    std::array::from_fn(|i| chip8_key_to_display_name(Chip8Key::from_index(i as u8).unwrap()))
+}
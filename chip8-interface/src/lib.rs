@@ -0,0 +1,21 @@
+// The pieces every front-end shares: the window size, the speed, the
+// keyboard layout, the config file, how a frame becomes colors, save
+// states, screenshots, logging and frame pacing.
+use chip8_emulator::{DISPLAY_MEM_HEIGHT, DISPLAY_MEM_WIDTH};
+
+pub mod bmp;
+pub mod config;
+pub mod frame;
+pub mod keymap;
+pub mod logging;
+pub mod pacing;
+#[cfg(feature = "remote")]
+pub mod remote;
+pub mod state;
+
+pub const SCALE: u32 = 15;
+pub const WINDOW_WIDTH: u32 = (DISPLAY_MEM_WIDTH as u32) * SCALE;
+pub const WINDOW_HEIGHT: u32 = (DISPLAY_MEM_HEIGHT as u32) * SCALE;
+pub const CYCLES_PER_FRAME: usize = 10;
+// How much faster we run while the turbo key is held.
+pub const TURBO_FACTOR: usize = 5;
@@ -0,0 +1,72 @@
+// Logging for the front-ends, on stderr, for diagnosing ROMs in the field.
+//
+// The core doesn't log: it returns errors, and we turn them into log
+// entries here. `--log-level=debug` also logs every frame, and
+// `--log-level=trace` every instruction, from the replay log.
+use chip8_emulator::{asm, Chip8Error};
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+/// What we log when `--log-level` isn't given: only what goes wrong.
+pub const DEFAULT_LOG_LEVEL: LevelFilter = LevelFilter::Warn;
+
+/// Understand a `--log-level`: off, error, warn, info, debug or trace.
+pub fn parse_log_level(text: &str) -> Result<LevelFilter, String> {
+    text.parse().map_err(|_| format!(
+        "Unknown log level: {} (use off, error, warn, info, debug or trace)",
+        text
+    ))
+}
+
+/// How bad an error of the core is. An unknown opcode is usually the
+/// program running into its data, or needing a platform we weren't told
+/// about, so it is only a warning.
+pub fn error_level(error: &Chip8Error) -> Level {
+    match error {
+        Chip8Error::UnknownOpcode(_) => Level::Warn,
+        _ => Level::Error,
+    }
+}
+
+/// Log `error`, which stopped the program.
+pub fn log_error(error: &Chip8Error) {
+    log::log!(error_level(error), "The emulator stopped: {}", error);
+}
+
+/// Describe the instruction `opcode` that ran at `pc`, for the trace.
+pub fn trace_line(pc: u16, opcode: u16) -> String {
+    format!("{:#05x}: {:04x}  {}", pc, opcode, asm::disassemble(opcode))
+}
+
+/// Log the instructions in `log`, as `take_replay_log` hands them over.
+pub fn trace_instructions(log: &[(u16, u16)]) {
+    for &(pc, opcode) in log {
+        log::trace!("{}", trace_line(pc, opcode));
+    }
+}
+
+/// Writes every entry to stderr, like `[WARN] message`.
+struct StderrLogger;
+
+impl Log for StderrLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!("[{}] {}", record.level(), record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: StderrLogger = StderrLogger;
+
+/// Log to stderr from now on, up to `level`. Only the first call does
+/// anything.
+pub fn init(level: LevelFilter) {
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(level);
+    }
+}
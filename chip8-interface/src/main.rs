@@ -1,28 +1,113 @@
 use std::env;
-use std::fs::File;
-use std::io::Read;
+use std::path::Path;
 
 use chip8_emulator::*;
-use sdl2;
+use chip8_interface::config::{load_config, Config};
+use chip8_interface::logging::{self, log_error, parse_log_level, trace_instructions, DEFAULT_LOG_LEVEL};
+use chip8_interface::pacing::{FrameLimiter, FRAME_TIME};
+use chip8_interface::state::dump_state;
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
-use sdl2::pixels::Color;
-use sdl2::rect::Rect;
-use sdl2::render::Canvas;
-use sdl2::video::Window;
 use std::thread::sleep;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-const SCALE: u32 = 15;
-const WINDOW_WIDTH: u32 = (DISPLAY_MEM_WIDTH as u32) * SCALE;
-const WINDOW_HEIGHT: u32 = (DISPLAY_MEM_HEIGHT as u32) * SCALE;
-const CYCLES_PER_FRAME: usize = 10;
+mod audio;
+mod gamepad;
+mod monitor;
+mod screen;
+mod terminal;
+
+use audio::{Beeper, Waveform};
+use gamepad::Gamepad;
+use monitor::{parse_breakpoints, Monitor};
+use screen::SdlScreen;
+
+// The longest a frame counts for, however long it really took.
+const MAX_FRAME_DELTA: Duration = Duration::from_nanos(4 * 1_000_000_000 / 60);
 
 fn main() {
     let args: Vec<_> = env::args().collect();
 
-    if args.len() != 2 { // Remember that the first item is the path to the binary
-        println!("Invalid number of args\nUsage: cargo run <path>");
+    // Flags can go anywhere, everything else is the path to the ROM.
+    // Remember that the first item is the path to the binary
+    let use_terminal = args.iter().any(|arg| arg == "--terminal");
+    let legacy_render = args.iter().any(|arg| arg == "--legacy-render");
+    let mute_turbo = args.iter().any(|arg| arg == "--mute-turbo");
+    let use_monitor = args.iter().any(|arg| arg == "--monitor");
+    let dump_path = args.iter().find_map(|arg| arg.strip_prefix("--dump-state-on-exit="));
+    let pause_on_start = args.iter().any(|arg| arg == "--pause-on-start");
+    let breakpoints = args.iter().find_map(|arg| arg.strip_prefix("--break="));
+    let no_vsync = args.iter().any(|arg| arg == "--no-vsync");
+    let paths: Vec<_> = args.iter().skip(1).filter(|arg| !arg.starts_with("--")).collect();
+
+    if paths.len() != 1 {
+        println!(
+            "Invalid number of args\nUsage: cargo run [--terminal] [--legacy-render] \
+            [--config=<path>] [--platform=<cosmac-vip|schip|xo-chip|modern>] \
+            [--scale=<n>] [--speed=<n>] [--turbo=<factor>] [--mute-turbo] [--monitor] \
+            [--remote=<address>] [--dump-state-on-exit=<path>] [--pause-on-start] \
+            [--break=<addr>,<addr>...] [--palette=<RRGGBB>,<RRGGBB>[,<RRGGBB>,<RRGGBB>]] \
+            [--log-level=<off|error|warn|info|debug|trace>] [--no-vsync] [--ghosting=<frames>] \
+            [--waveform=<square|sine>] <path>"
+        );
+        return ;
+    }
+
+    let log_level = match args.iter().find_map(|arg| arg.strip_prefix("--log-level=")) {
+        Some(level) => match parse_log_level(level) {
+            Ok(level) => level,
+            Err(error) => {
+                println!("{}", error);
+                return ;
+            },
+        },
+        None => DEFAULT_LOG_LEVEL,
+    };
+    logging::init(log_level);
+
+    let waveform = match args.iter().find_map(|arg| arg.strip_prefix("--waveform=")) {
+        Some(name) => match Waveform::parse(name) {
+            Ok(waveform) => waveform,
+            Err(error) => {
+                println!("{}", error);
+                return ;
+            },
+        },
+        None => Waveform::default(),
+    };
+
+    // The config file sits next to where we are run from
+    let config = match load_config(&args, Path::new(".")) {
+        Ok(config) => config,
+        Err(error) => {
+            println!("Invalid config: {}", error);
+            return ;
+        },
+    };
+
+    let mut processor = Chip8Processor::new();
+    processor.set_quirks(config.quirks);
+
+    if let Err(error) = processor.load_rom_from_path(paths[0]) {
+        println!("Unable to load ROM: {}", error);
+        return ;
+    }
+
+    if let Some(list) = breakpoints {
+        match parse_breakpoints(list, processor.ram_size()) {
+            Ok(addrs) => addrs.into_iter().for_each(|addr| processor.add_breakpoint(addr)),
+            Err(error) => {
+                println!("Invalid --break: {}", error);
+                return ;
+            },
+        }
+    }
+
+    if use_terminal {
+        if let Err(error) = terminal::run(&mut processor, &config) {
+            log::error!("Terminal error: {}", error);
+        }
+        dump_on_exit(&processor, dump_path);
         return ;
     }
 
@@ -30,26 +115,58 @@ fn main() {
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
 
+    let window_width = DISPLAY_MEM_WIDTH as u32 * config.scale;
+    let window_height = DISPLAY_MEM_HEIGHT as u32 * config.scale;
     let window = video_subsystem
-        .window("Chip8 Emulator", WINDOW_WIDTH, WINDOW_HEIGHT)
+        .window("Chip8 Emulator", window_width, window_height)
         .position_centered()
+        .resizable()
         .opengl()
         .build()
         .unwrap();
-    
-    let mut canvas = window.into_canvas().present_vsync().build().unwrap();
+
+    // Scale the screen texture up without blurring the pixels
+    sdl2::hint::set("SDL_RENDER_SCALE_QUALITY", "nearest");
+
+    // Without vsync, the frame limiter keeps us at 60 frames per second
+    let canvas = window.into_canvas();
+    let canvas = if no_vsync { canvas } else { canvas.present_vsync() };
+    let mut canvas = canvas.build().unwrap();
     canvas.clear();
     canvas.present();
+    let texture_creator = canvas.texture_creator();
+    let mut screen = SdlScreen::new(canvas, &texture_creator, config.theme, legacy_render, config.ghosting);
 
     let mut event_pump = sdl_context.event_pump().unwrap();
+    let mut keypad = SdlKeypad::default();
+    // Like the sound, controllers are a bonus: the keyboard still works
+    let mut gamepad = sdl_context.game_controller().ok().map(Gamepad::new);
+    // No sound is better than no emulator, so we carry on without audio
+    let mut beeper = sdl_context.audio().and_then(|audio| Beeper::new(&audio, waveform)).ok();
 
-    let mut processor = Chip8Processor::new();
-
-    let mut rom = File::open(&args[1]).expect("Unable to open file.");
-    let mut buffer = Vec::new();
-    rom.read_to_end(&mut buffer).unwrap();
+    let mut last_frame = Instant::now();
+    let mut limiter = FrameLimiter::new(FRAME_TIME, last_frame);
+    // How many cycles each frame gets, from how long it actually lasted
+    let mut scheduler = CycleScheduler::new(0);
+    let mut turbo = false;
+    // Breakpoints need the monitor to stop at them, and to carry on after
+    let paused = use_monitor || pause_on_start;
+    let mut monitor = (paused || breakpoints.is_some()).then(|| Monitor::spawn(paused));
+    #[cfg(feature = "remote")]
+    let remote = match args.iter().find_map(|arg| arg.strip_prefix("--remote=")) {
+        Some(addr) => match chip8_interface::remote::RemoteInput::listen(addr) {
+            Ok(remote) => Some(remote),
+            Err(error) => {
+                println!("Unable to listen on {}: {}", addr, error);
+                return ;
+            },
+        },
+        None => None,
+    };
 
-    processor.load_rom(&buffer);
+    // The trace comes from the instructions the processor records for us
+    let trace = log::log_enabled!(log::Level::Trace);
+    processor.set_recording(trace);
 
     // This is a loop label that we can use to break out of tiered loops.
     'gameloop: loop {
@@ -58,74 +175,157 @@ fn main() {
                 Event::Quit { .. } | Event::KeyDown { keycode: Some(Keycode::Escape), .. } => {
                     break 'gameloop;
                 },
-                Event::KeyDown { keycode: Some(key), .. } => {
-                    if let Some(chip_key) = key_to_chip8_key(key) {
+                Event::KeyDown { keycode: Some(Keycode::Tab), .. } => turbo = true,
+                Event::KeyUp { keycode: Some(Keycode::Tab), .. } => turbo = false,
+                // Key repeat would look like the key going down again and
+                // again, so we only listen to the first press
+                Event::KeyDown { keycode: Some(key), repeat: false, .. } => {
+                    if let Some(chip_key) = key_to_chip8_key(key, &config) {
+                        keypad.press(chip_key);
+                        // The key might be released before the cycles get to
+                        // poll it, so we have the processor latch the press
                         processor.press_key(chip_key);
                     }
                 },
                 Event::KeyUp { keycode: Some(key), .. } => {
-                    if let Some(chip_key) = key_to_chip8_key(key) {
-                        processor.release_key(chip_key);
+                    if let Some(chip_key) = key_to_chip8_key(key, &config) {
+                        keypad.release(chip_key);
                     }
                 }
 
-                _ => ()
+                other => {
+                    if let Some(gamepad) = &mut gamepad {
+                        gamepad.handle_event(&other, &mut keypad, &config);
+                    }
+                },
             }
         }
 
-        for _ in 0..CYCLES_PER_FRAME {
-            processor.cycle();
+        #[cfg(feature = "remote")]
+        if let Some(remote) = &remote {
+            keypad.remote = remote.keys();
+        }
+
+        // The frame rate depends on the monitor, so both the cycles and
+        // the timers go by how much time actually passed. A stall (the
+        // window was dragged) only counts for a few frames, instead of
+        // running everything we missed at once. While the debugger holds
+        // the program, no time passes at all.
+        let now = Instant::now();
+        let dt = if monitor.as_ref().is_some_and(Monitor::is_paused) {
+            Duration::ZERO
+        } else {
+            (now - last_frame).min(MAX_FRAME_DELTA)
+        };
+        last_frame = now;
+
+        // The speed is set in cycles per 60th of a second
+        let per_frame = effective_cycles_per_frame(config.speed, turbo, config.turbo);
+        scheduler.set_clock_hz(u32::try_from(per_frame.saturating_mul(60)).unwrap_or(u32::MAX));
+        let cycles = scheduler.cycles_for(dt);
+        let result = match &mut monitor {
+            Some(monitor) => monitor.run_frame(&mut processor, cycles, &mut keypad),
+            None => processor.cycle_n_with_input(cycles, &mut keypad),
+        };
+        if trace {
+            trace_instructions(&processor.take_replay_log());
+        }
+        if let Err(error) = result {
+            log_error(&error);
+            break 'gameloop;
+        }
+        log::debug!(
+            "Frame: {} cycles, PC {:#05x}, delay {}, sound {}, {}",
+            cycles,
+            processor.pc(),
+            processor.delay_timer(),
+            processor.sound_timer(),
+            if processor.is_beeping() { "beeping" } else { "silent" },
+        );
+        if processor.is_halted() {
+            println!("The program ended.");
+            break 'gameloop;
+        }
+        processor.end_frame();
+        processor.advance_timers(dt);
+
+        if let Some(beeper) = &mut beeper {
+            // An all-zero pattern is silence: programs that never loaded
+            // one with F002 expect the plain beep
+            let pattern = processor.audio_pattern();
+            let loaded = processor.quirks().xo_chip && pattern.iter().any(|&bits| bits != 0);
+            beeper.set_pattern(loaded.then(|| (*pattern, processor.audio_sample_rate())));
+            beeper.set_beeping(processor.is_beeping() && !(turbo && mute_turbo));
+        }
+        processor.present(&mut screen);
+
+        // With vsync, presenting the frame already waited for the monitor
+        if no_vsync {
+            sleep(limiter.wait(Instant::now()));
         }
-        processor.tick_timers();
-        draw_screen(&processor, &mut canvas);
-        
-        sleep(Duration::from_millis(16));
     }
 
+    dump_on_exit(&processor, dump_path);
+}
+
+/// Save the final state to `path`, if we were asked to, so it can be
+/// looked at after a crash.
+fn dump_on_exit(processor: &Chip8Processor, path: Option<&str>) {
+    if let Some(path) = path {
+        match dump_state(processor, Path::new(path)) {
+            Ok(()) => println!("Saved the final state to {}", path),
+            Err(error) => println!("Unable to save the final state: {}", error),
+        }
+    }
 }
 
 
-fn draw_screen(processor: &Chip8Processor, canvas: &mut Canvas<Window>) {
-    // Clear the canvas
-    canvas.set_draw_color(Color::RGB(0, 0, 0));
-    canvas.clear();
+/// How many cycles to run this frame. Turbo multiplies whatever the
+/// normal speed is.
+fn effective_cycles_per_frame(cycles_per_frame: usize, turbo: bool, turbo_factor: usize) -> usize {
+    if turbo {
+        cycles_per_frame.saturating_mul(turbo_factor)
+    } else {
+        cycles_per_frame
+    }
+}
+
+/// The keys held down on the keyboard and the controller, as the SDL
+/// events tell us about them, and the ones held down remotely.
+#[derive(Default)]
+struct SdlKeypad {
+    keys: [bool; 16],
+    remote: [bool; 16],
+}
 
-    let screen_buffer = processor.get_display();
+impl SdlKeypad {
+    fn press(&mut self, key: Chip8Key) {
+        self.keys[key.index()] = true;
+    }
 
-    canvas.set_draw_color(Color::RGB(255, 255, 255));
-    for (i, pixel) in screen_buffer.iter().enumerate() {
-        if *pixel {
-            // Make the 1D array 2D. We get the coordinates of the pixel we are
-            // iterating upon.
-            let x = (i % DISPLAY_MEM_WIDTH) as u32;
-            let y = (i / DISPLAY_MEM_WIDTH) as u32;
+    fn release(&mut self, key: Chip8Key) {
+        self.keys[key.index()] = false;
+    }
+}
 
-            let rectangle = Rect::new((x * SCALE) as i32, (y * SCALE) as i32, SCALE, SCALE);
-            canvas.fill_rect(rectangle).unwrap();
+impl InputSource for SdlKeypad {
+    fn poll(&mut self) -> [bool; 16] {
+        let mut keys = self.keys;
+        for (key, remote) in keys.iter_mut().zip(self.remote) {
+            *key |= remote;
         }
+        keys
     }
+}
 
-    canvas.present();
+/// SDL keycodes for printable keys are their character, so we can look
+/// them up in the keymap of the config.
+fn key_to_chip8_key(key: Keycode, config: &Config) -> Option<Chip8Key> {
+    u32::try_from(key as i32)
+        .ok()
+        .and_then(char::from_u32)
+        .and_then(|c| config.key(c))
 }
 
-fn key_to_chip8_key(key: Keycode) -> Option<Chip8Key> {
-    match key {
-        Keycode::Num1 => Some(Chip8Key::K1),
-        Keycode::Num2 => Some(Chip8Key::K2),
-        Keycode::Num3 => Some(Chip8Key::K3),
-        Keycode::Num4 => Some(Chip8Key::KC),
-        Keycode::Q => Some(Chip8Key::K4),
-        Keycode::W => Some(Chip8Key::K5),
-        Keycode::E => Some(Chip8Key::K6),
-        Keycode::R => Some(Chip8Key::KD),
-        Keycode::A => Some(Chip8Key::K7),
-        Keycode::S => Some(Chip8Key::K8),
-        Keycode::D => Some(Chip8Key::K9),
-        Keycode::F => Some(Chip8Key::KE),
-        Keycode::Z => Some(Chip8Key::KA),
-        Keycode::X => Some(Chip8Key::K0),
-        Keycode::C => Some(Chip8Key::KB),
-        Keycode::V => Some(Chip8Key::KF),
-        _ => None,
-    }
-}
\ No newline at end of file
+#[cfg(test)]
+mod tests;
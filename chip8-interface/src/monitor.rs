@@ -0,0 +1,184 @@
+// A line-based debugger on stdin. The game keeps drawing in the window,
+// while the commands come in from a thread of their own.
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+
+use chip8_emulator::*;
+use chip8_interface::bmp::save_bmp;
+
+/// Everything the monitor understands.
+#[derive(PartialEq, Eq, Debug)]
+pub enum Command {
+    /// Run this many instructions.
+    Step(usize),
+    /// Show the registers.
+    Regs,
+    /// Show the 16 bytes of memory from this address.
+    Mem(u16),
+    /// Stop when the program counter gets here.
+    Break(u16),
+    /// Run until the next breakpoint.
+    Continue,
+    /// Show the 8 instructions from this address.
+    Disasm(u16),
+    /// Save the screen to this path, as a BMP file.
+    Bmp(String),
+}
+
+/// Understand a line typed at the prompt. Addresses are in hex, with or
+/// without a leading `0x`, counts are in decimal.
+pub fn parse_command(line: &str) -> Result<Command, String> {
+    let words: Vec<_> = line.split_whitespace().collect();
+
+    let command = match words.as_slice() {
+        ["step"] => Command::Step(1),
+        ["step", count] => Command::Step(
+            count.parse().map_err(|_| format!("Not a count: {}", count))?
+        ),
+        ["regs"] => Command::Regs,
+        ["mem", addr] => Command::Mem(parse_address(addr)?),
+        ["break", addr] => Command::Break(parse_address(addr)?),
+        ["continue"] => Command::Continue,
+        ["disasm", addr] => Command::Disasm(parse_address(addr)?),
+        ["bmp", path] => Command::Bmp(path.to_string()),
+        _ => return Err(format!(
+            "Unknown command: {}\nCommands: step [N], regs, mem ADDR, break ADDR, continue, disasm ADDR, bmp PATH",
+            line.trim()
+        )),
+    };
+
+    Ok(command)
+}
+
+fn parse_address(text: &str) -> Result<u16, String> {
+    let hex = text.strip_prefix("0x").unwrap_or(text);
+    u16::from_str_radix(hex, 16).map_err(|_| format!("Not an address: {}", text))
+}
+
+/// Understand a comma-separated list of breakpoints, like `0x2A0,0x2B4`,
+/// for `--break`. Instructions are 2 bytes long and start at even
+/// addresses in the RAM, so anything else could never be reached.
+pub fn parse_breakpoints(list: &str, ram_size: usize) -> Result<Vec<u16>, String> {
+    list.split(',')
+        .map(|text| {
+            let addr = parse_address(text.trim())?;
+            if addr as usize + 1 >= ram_size {
+                return Err(format!("Breakpoint {:#05x} is outside of the RAM", addr));
+            }
+            if addr % 2 != 0 {
+                return Err(format!("Breakpoint {:#05x} is not on an instruction, it is odd", addr));
+            }
+            Ok(addr)
+        })
+        .collect()
+}
+
+/// Runs the processor under the commands typed on stdin.
+pub struct Monitor {
+    lines: Receiver<String>,
+    paused: bool,
+}
+
+impl Monitor {
+    /// Start reading commands. If `paused`, the program waits for them
+    /// before it starts.
+    pub fn spawn(paused: bool) -> Self {
+        let (sender, lines) = mpsc::channel();
+
+        // Reading stdin blocks, so it cannot happen on the game loop
+        thread::spawn(move || {
+            for line in io::stdin().lock().lines().map_while(Result::ok) {
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        prompt();
+        Self { lines, paused }
+    }
+
+    /// Whether we are waiting for commands, so time should stand still.
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    /// Carry out the commands typed since the last frame, then run `cycles`
+    /// cycles unless we are paused, stopping early on a breakpoint.
+    pub fn run_frame(&mut self, processor: &mut Chip8Processor, cycles: usize, input: &mut impl InputSource) -> Result<(), Chip8Error> {
+        while let Ok(line) = self.lines.try_recv() {
+            match parse_command(&line) {
+                Ok(command) => self.execute(command, processor, input)?,
+                Err(error) => println!("{}", error),
+            }
+            prompt();
+        }
+
+        if self.paused {
+            return Ok(());
+        }
+
+        for _ in 0..cycles {
+            processor.cycle_with_input(input)?;
+
+            if processor.breakpoints().contains(&processor.pc()) {
+                println!("Breakpoint at {:#05x}", processor.pc());
+                self.paused = true;
+                prompt();
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn execute(&mut self, command: Command, processor: &mut Chip8Processor, input: &mut impl InputSource) -> Result<(), Chip8Error> {
+        match command {
+            Command::Step(count) => {
+                for _ in 0..count {
+                    processor.cycle_with_input(input)?;
+                }
+                print_next(processor);
+            },
+            Command::Regs => print!("{}", processor.registers_dump()),
+            Command::Mem(addr) => {
+                let end = addr.saturating_add(16);
+                let bytes: Vec<_> = processor.dump_mem(addr..end)
+                    .iter()
+                    .map(|byte| format!("{:02x}", byte))
+                    .collect();
+                println!("{:#05x}: {}", addr, bytes.join(" "));
+            },
+            Command::Break(addr) => processor.add_breakpoint(addr),
+            Command::Continue => self.paused = false,
+            Command::Disasm(addr) => {
+                for offset in 0..8 {
+                    let at = addr.wrapping_add(offset * 2);
+                    let opcode = u16::from_be_bytes([processor.read_mem(at), processor.read_mem(at.wrapping_add(1))]);
+                    println!("{:#05x}: {:04x}  {}", at, opcode, asm::disassemble(opcode));
+                }
+            },
+            Command::Bmp(path) => match save_bmp(processor, Path::new(&path)) {
+                Ok(()) => println!("Saved the screen to {}", path),
+                Err(error) => println!("Unable to save the screen: {}", error),
+            },
+        }
+
+        Ok(())
+    }
+}
+
+/// Show where we stopped, and what runs next.
+fn print_next(processor: &Chip8Processor) {
+    let pc = processor.pc();
+    let opcode = u16::from_be_bytes([processor.read_mem(pc), processor.read_mem(pc.wrapping_add(1))]);
+    println!("{:#05x}: {}", pc, asm::disassemble(opcode));
+}
+
+fn prompt() {
+    print!("> ");
+    // There is no newline, so we have to flush ourselves
+    io::stdout().flush().ok();
+}
@@ -0,0 +1,40 @@
+// Keeping the front-ends at 60 frames per second without vsync, which
+// ties the speed to the refresh rate of the monitor (or doesn't hold
+// anything back at all, when it is turned off).
+use std::time::{Duration, Instant};
+
+/// How long a frame lasts at 60 frames per second.
+pub const FRAME_TIME: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+/// Tells the game loop how long to sleep to keep a steady frame rate.
+///
+/// Frames are due at fixed times, not a fixed time after the last one
+/// ended, so the time spent running a frame does not add up into drift.
+/// When we fall more than a frame behind (the window was dragged, the
+/// machine was asleep), we start over from now instead of rushing through
+/// the frames we missed.
+pub struct FrameLimiter {
+    frame_time: Duration,
+    next_frame: Instant,
+}
+
+impl FrameLimiter {
+    /// A limiter whose first frame starts at `now`.
+    pub fn new(frame_time: Duration, now: Instant) -> Self {
+        Self { frame_time, next_frame: now + frame_time }
+    }
+
+    /// How long to sleep at `now`, after running a frame, until the next
+    /// one is due.
+    pub fn wait(&mut self, now: Instant) -> Duration {
+        let wait = self.next_frame.saturating_duration_since(now);
+
+        if now > self.next_frame + self.frame_time {
+            self.next_frame = now + self.frame_time;
+        } else {
+            self.next_frame += self.frame_time;
+        }
+
+        wait
+    }
+}
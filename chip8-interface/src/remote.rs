@@ -0,0 +1,73 @@
+// Remote input over TCP, e.g. for a phone used as a controller.
+//
+// The protocol is one byte per key change: the low nibble is the keypad
+// key, the high bit is set for a press and clear for a release. The three
+// bits in between must be zero, so stray text is not mistaken for keys.
+//
+//  1000 0101  -> key 5 pressed
+//  0000 0101  -> key 5 released
+use std::io::{self, Read};
+use std::net::{TcpListener, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// A single key change, as sent over the wire.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct KeyUpdate {
+    pub key: usize,
+    pub pressed: bool,
+}
+
+/// Understand one byte of the protocol, or `None` if it isn't one.
+pub fn parse_update(byte: u8) -> Option<KeyUpdate> {
+    if byte & 0b0111_0000 != 0 {
+        return None;
+    }
+
+    Some(KeyUpdate { key: (byte & 0x0F) as usize, pressed: byte & 0x80 != 0 })
+}
+
+/// Apply every update in `bytes` to `keys`, skipping the bytes that
+/// aren't updates.
+pub fn apply_updates(keys: &mut [bool; 16], bytes: &[u8]) {
+    for update in bytes.iter().filter_map(|byte| parse_update(*byte)) {
+        keys[update.key] = update.pressed;
+    }
+}
+
+/// A TCP listener that keeps track of the keys its clients hold down.
+///
+/// Clients are served one at a time, and their keys are released when
+/// they disconnect.
+pub struct RemoteInput {
+    keys: Arc<Mutex<[bool; 16]>>,
+}
+
+impl RemoteInput {
+    /// Start listening on `addr`, in the background.
+    pub fn listen(addr: impl ToSocketAddrs) -> io::Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        let keys = Arc::new(Mutex::new([false; 16]));
+        let shared = Arc::clone(&keys);
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else { continue };
+                let mut buffer = [0; 64];
+
+                while let Ok(read @ 1..) = stream.read(&mut buffer) {
+                    apply_updates(&mut shared.lock().unwrap(), &buffer[..read]);
+                }
+
+                *shared.lock().unwrap() = [false; 16];
+            }
+        });
+
+        Ok(Self { keys })
+    }
+
+    /// The keys held down by the remote, by keypad index.
+    pub fn keys(&self) -> [bool; 16] {
+        *self.keys.lock().unwrap()
+    }
+}
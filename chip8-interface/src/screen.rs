@@ -0,0 +1,198 @@
+// Drawing the CHIP-8 screen on the SDL window.
+use chip8_emulator::DisplaySink;
+use chip8_interface::frame::{decay, Theme};
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::rect::Rect;
+use sdl2::render::{Canvas, Texture, TextureCreator};
+use sdl2::video::{Window, WindowContext};
+
+/// Draws the CHIP-8 screen on an SDL window.
+///
+/// Each frame is uploaded to a texture, then stretched by the largest whole
+/// scale that fits the window, so the pixels stay square and sharp however
+/// the window is resized. With `legacy_render`, every pixel is drawn as its
+/// own rectangle instead, like we used to.
+///
+/// With `ghosting`, pixels fade out over that many frames when they go
+/// dark, see `frame::decay`. XO-CHIP frames are drawn as they are.
+pub struct SdlScreen<'a> {
+    canvas: Canvas<Window>,
+    creator: &'a TextureCreator<WindowContext>,
+    texture: Option<Texture<'a>>,
+    rgba: Vec<u8>,
+    theme: Theme,
+    legacy_render: bool,
+    ghosting: u8,
+    brightness: Vec<u8>, // How lit each pixel is, for the ghosting
+}
+
+impl<'a> SdlScreen<'a> {
+    pub fn new(
+        canvas: Canvas<Window>,
+        creator: &'a TextureCreator<WindowContext>,
+        theme: Theme,
+        legacy_render: bool,
+        ghosting: u8,
+    ) -> Self {
+        Self {
+            canvas,
+            creator,
+            texture: None,
+            rgba: Vec::new(),
+            theme,
+            legacy_render,
+            ghosting,
+            brightness: Vec::new(),
+        }
+    }
+
+    fn background(&self) -> Color {
+        let [r, g, b] = self.theme.background;
+        Color::RGB(r, g, b)
+    }
+
+    fn present_texture(&mut self, frame: Frame, width: usize, height: usize) {
+        let background = self.background();
+
+        // The texture has to be as big as the screen, which SCHIP can resize
+        let stale = !self.texture.as_ref().is_some_and(|texture| {
+            let query = texture.query();
+            (query.width as usize, query.height as usize) == (width, height)
+        });
+        if stale {
+            let texture = self.creator
+                .create_texture_streaming(PixelFormatEnum::RGBA32, width as u32, height as u32)
+                .unwrap();
+            self.texture = Some(texture);
+        }
+        let texture = self.texture.as_mut().unwrap();
+
+        self.rgba.resize(width * height * 4, 0);
+        upload_frame(texture, frame, width, &self.theme, &mut self.rgba);
+
+        let (window_width, window_height) = self.canvas.output_size().unwrap();
+        let target = integer_scale_rect(window_width, window_height, width, height);
+
+        self.canvas.set_draw_color(background);
+        self.canvas.clear();
+        self.canvas.copy(texture, None, target).unwrap();
+        self.canvas.present();
+    }
+
+    fn present_rects(&mut self, frame: Frame, width: usize) {
+        let background = self.background();
+        let theme = self.theme;
+        let canvas = &mut self.canvas;
+
+        // Clear the canvas
+        canvas.set_draw_color(background);
+        canvas.clear();
+
+        // In high resolution mode the pixels are smaller, so the window
+        // keeps the same size.
+        let scale = canvas.output_size().unwrap().0 / width as u32;
+
+        for i in 0..frame.len() {
+            let color = frame.color(i, &theme);
+            if color != theme.background {
+                // Make the 1D array 2D. We get the coordinates of the pixel we are
+                // iterating upon.
+                let x = (i % width) as u32;
+                let y = (i / width) as u32;
+
+                let [r, g, b] = color;
+                canvas.set_draw_color(Color::RGB(r, g, b));
+                let rectangle = Rect::new((x * scale) as i32, (y * scale) as i32, scale, scale);
+                canvas.fill_rect(rectangle).unwrap();
+            }
+        }
+
+        canvas.present();
+    }
+
+    fn present_frame(&mut self, frame: Frame, width: usize, height: usize) {
+        if self.legacy_render {
+            self.present_rects(frame, width);
+        } else {
+            self.present_texture(frame, width, height);
+        }
+    }
+}
+
+/// A frame as the processor hands it over: two colors, or the four of
+/// XO-CHIP. With ghosting, the two colors fade into each other.
+#[derive(Clone, Copy)]
+enum Frame<'f> {
+    Pixels(&'f [bool]),
+    Colors(&'f [u8]),
+    Faded(&'f [u8]),
+}
+
+impl Frame<'_> {
+    fn len(&self) -> usize {
+        match self {
+            Frame::Pixels(pixels) => pixels.len(),
+            Frame::Colors(colors) => colors.len(),
+            Frame::Faded(brightness) => brightness.len(),
+        }
+    }
+
+    /// The color of pixel `i`.
+    fn color(&self, i: usize, theme: &Theme) -> [u8; 3] {
+        match self {
+            Frame::Pixels(pixels) => theme.color(pixels[i] as u8),
+            Frame::Colors(colors) => theme.color(colors[i]),
+            Frame::Faded(brightness) => {
+                let mut rgba = [0; 4];
+                theme.paint_faded(&brightness[i..i + 1], &mut rgba);
+                [rgba[0], rgba[1], rgba[2]]
+            },
+        }
+    }
+}
+
+impl DisplaySink for SdlScreen<'_> {
+    fn present(&mut self, pixels: &[bool], width: usize, height: usize) {
+        if self.ghosting == 0 {
+            self.present_frame(Frame::Pixels(pixels), width, height);
+            return;
+        }
+
+        // A new resolution starts from a blank screen
+        let mut brightness = std::mem::take(&mut self.brightness);
+        if brightness.len() != pixels.len() {
+            brightness = vec![0; pixels.len()];
+        }
+        decay(&mut brightness, pixels, self.ghosting);
+        self.present_frame(Frame::Faded(&brightness), width, height);
+        self.brightness = brightness;
+    }
+
+    fn present_colors(&mut self, colors: &[u8], width: usize, height: usize) {
+        self.present_frame(Frame::Colors(colors), width, height);
+    }
+}
+
+/// Copy a frame into `texture`, which has to be RGBA32 and `width` pixels
+/// wide, going through `rgba` (four bytes per pixel).
+fn upload_frame(texture: &mut Texture, frame: Frame, width: usize, theme: &Theme, rgba: &mut [u8]) {
+    match frame {
+        Frame::Pixels(pixels) => theme.paint(pixels, rgba),
+        Frame::Colors(colors) => theme.paint_colors(colors, rgba),
+        Frame::Faded(brightness) => theme.paint_faded(brightness, rgba),
+    }
+    texture.update(None, rgba, width * 4).unwrap();
+}
+
+/// Where to draw a `width` x `height` image on the window: scaled by the
+/// largest whole number that fits (but at least 1), and centered.
+pub fn integer_scale_rect(window_width: u32, window_height: u32, width: usize, height: usize) -> Rect {
+    let (width, height) = (width as u32, height as u32);
+    let scale = (window_width / width).min(window_height / height).max(1);
+
+    let (scaled_width, scaled_height) = (width * scale, height * scale);
+    let x = (window_width as i32 - scaled_width as i32) / 2;
+    let y = (window_height as i32 - scaled_height as i32) / 2;
+
+    Rect::new(x, y, scaled_width, scaled_height)
+}
@@ -0,0 +1,11 @@
+// Save states on disk, to look at the machine after the fact.
+use std::path::Path;
+
+use chip8_emulator::Chip8Processor;
+
+/// Write the state of `processor` to `path`, in the format
+/// `Chip8Processor::load_state_binary` reads back.
+pub fn dump_state(processor: &Chip8Processor, path: &Path) -> Result<(), String> {
+    std::fs::write(path, processor.save_state_binary())
+        .map_err(|error| format!("unable to write {}: {}", path.display(), error))
+}
@@ -0,0 +1,100 @@
+// A front-end that draws in the terminal, for playing over SSH.
+use std::io::{self, Write};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use chip8_emulator::*;
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind};
+use crossterm::terminal::{self, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+use crossterm::style::Print;
+
+use chip8_interface::config::Config;
+
+// Terminals only tell us when a key goes down, never when it comes back up,
+// so we keep every key pressed for a few frames after we see it.
+const KEY_HOLD_FRAMES: u8 = 6;
+
+/// Run the processor in the terminal until Escape is pressed.
+pub fn run(processor: &mut Chip8Processor, config: &Config) -> io::Result<()> {
+    let mut stdout = io::stdout();
+
+    terminal::enable_raw_mode()?;
+    execute!(stdout, EnterAlternateScreen, Hide)?;
+
+    let result = game_loop(processor, config, &mut stdout);
+
+    // Whatever happened, give the user their terminal back
+    execute!(stdout, Show, LeaveAlternateScreen)?;
+    terminal::disable_raw_mode()?;
+
+    result
+}
+
+fn game_loop(processor: &mut Chip8Processor, config: &Config, stdout: &mut io::Stdout) -> io::Result<()> {
+    // How many more frames each key stays pressed for
+    let mut held = [0u8; 16];
+    let mut last_frame = Instant::now();
+
+    loop {
+        while event::poll(Duration::ZERO)? {
+            if let Event::Key(KeyEvent { code, kind: KeyEventKind::Press, .. }) = event::read()? {
+                match code {
+                    KeyCode::Esc => return Ok(()),
+                    KeyCode::Char(c) => {
+                        if let Some(key) = config.key(c) {
+                            processor.press_key(key);
+                            held[key.index()] = KEY_HOLD_FRAMES;
+                        }
+                    },
+                    _ => (),
+                }
+            }
+        }
+
+        for (index, frames) in held.iter_mut().enumerate() {
+            if *frames > 0 {
+                *frames -= 1;
+                if *frames == 0 {
+                    processor.release_key(Chip8Key::from_index(index as u8).unwrap());
+                }
+            }
+        }
+
+        if let Err(error) = processor.cycle_n(config.speed) {
+            return Err(io::Error::other(error));
+        }
+        if processor.is_halted() {
+            return Ok(());
+        }
+        processor.end_frame();
+
+        let now = Instant::now();
+        processor.advance_timers(now - last_frame);
+        last_frame = now;
+
+        let (width, height) = processor.display_dimensions();
+        let frame = framebuffer_to_string(processor.get_display(), width, height);
+        // Raw mode does not return the cursor on newlines, so we place
+        // every line ourselves.
+        for (row, line) in frame.lines().enumerate() {
+            queue!(stdout, MoveTo(0, row as u16), Print(line))?;
+        }
+        stdout.flush()?;
+
+        sleep(Duration::from_millis(16));
+    }
+}
+
+/// Draw the screen as text, one line per row, with a block for each lit pixel.
+pub fn framebuffer_to_string(pixels: &[bool], width: usize, height: usize) -> String {
+    let mut frame = String::with_capacity((width + 1) * height * 3);
+
+    for row in pixels.chunks(width).take(height) {
+        frame.extend(row.iter().map(|pixel| if *pixel { '█' } else { ' ' }));
+        frame.push('\n');
+    }
+
+    frame
+}
@@ -0,0 +1,466 @@
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use chip8_emulator::{Chip8Error, Chip8Key, Chip8Processor, Quirks};
+use chip8_interface::bmp::*;
+use chip8_interface::config::*;
+use chip8_interface::frame::*;
+use chip8_interface::keymap::*;
+use chip8_interface::logging::*;
+use chip8_interface::pacing::*;
+use chip8_interface::state::*;
+use sdl2::controller::Button;
+use sdl2::keyboard::Keycode;
+use sdl2::rect::Rect;
+
+use crate::audio::*;
+use crate::gamepad::*;
+use crate::monitor::*;
+use crate::screen::*;
+use crate::terminal::*;
+use crate::{effective_cycles_per_frame, key_to_chip8_key};
+
+#[test]
+fn test_framebuffer_to_string() {
+    let pixels = [
+        true, false, false, true,
+        false, true, true, false,
+    ];
+
+    assert_eq!(framebuffer_to_string(&pixels, 4, 2), "█  █\n ██ \n");
+}
+
+#[test]
+fn test_char_to_chip8_key() {
+    assert_eq!(char_to_chip8_key('4'), Some(Chip8Key::KC));
+    assert_eq!(char_to_chip8_key('V'), Some(Chip8Key::KF));
+    assert_eq!(char_to_chip8_key('p'), None);
+}
+
+#[test]
+fn test_key_to_chip8_key() {
+    let config = Config::default();
+    assert_eq!(key_to_chip8_key(Keycode::Num1, &config), Some(Chip8Key::K1));
+    assert_eq!(key_to_chip8_key(Keycode::Num4, &config), Some(Chip8Key::KC));
+    assert_eq!(key_to_chip8_key(Keycode::Q, &config), Some(Chip8Key::K4));
+    assert_eq!(key_to_chip8_key(Keycode::V, &config), Some(Chip8Key::KF));
+    assert_eq!(key_to_chip8_key(Keycode::P, &config), None);
+    assert_eq!(key_to_chip8_key(Keycode::Escape, &config), None);
+}
+
+#[test]
+fn test_framebuffer_to_rgba() {
+    let mut frame = [0; 8];
+    framebuffer_to_rgba(&[true, false], &mut frame);
+
+    assert_eq!(&frame[..4], &FOREGROUND);
+    assert_eq!(&frame[4..], &BACKGROUND);
+}
+
+#[test]
+fn test_theme_paint_colors() {
+    let theme = Theme::default();
+    let mut frame = [0; 16];
+    theme.paint_colors(&[0, 1, 2, 3], &mut frame);
+
+    assert_eq!(&frame[..4], &BACKGROUND);
+    assert_eq!(&frame[4..8], &FOREGROUND);
+    assert_eq!(&frame[8..12], &[255, 0, 0, 255]);
+    assert_eq!(&frame[12..], &[0, 0, 255, 255]);
+}
+
+
+#[test]
+fn test_button_to_chip8_key() {
+    let config = Config::default();
+    assert_eq!(button_to_chip8_key(Button::DPadUp, &config), Some(Chip8Key::K2));
+    assert_eq!(button_to_chip8_key(Button::DPadLeft, &config), Some(Chip8Key::K4));
+    assert_eq!(button_to_chip8_key(Button::DPadRight, &config), Some(Chip8Key::K6));
+    assert_eq!(button_to_chip8_key(Button::DPadDown, &config), Some(Chip8Key::K8));
+    assert_eq!(button_to_chip8_key(Button::A, &config), Some(Chip8Key::K5));
+    assert_eq!(button_to_chip8_key(Button::Guide, &config), None);
+
+    // The config can move buttons, and bind the ones that do nothing
+    let config = Config::from_toml("[gamepad]\nA = 0x6\nguide = 0x0\n").unwrap();
+    assert_eq!(button_to_chip8_key(Button::A, &config), Some(Chip8Key::K6));
+    assert_eq!(button_to_chip8_key(Button::Guide, &config), Some(Chip8Key::K0));
+    assert_eq!(button_to_chip8_key(Button::B, &config), Some(Chip8Key::KA));
+
+    assert!(Config::from_toml("[gamepad]\nturbo = 0x1\n").is_err());
+    assert!(Config::from_toml("[gamepad]\na = 0x10\n").is_err());
+}
+
+
+#[test]
+fn test_integer_scale_rect() {
+    assert_eq!(integer_scale_rect(640, 320, 64, 32), Rect::new(0, 0, 640, 320));
+    // The scale is the largest that fits both ways, and we center
+    assert_eq!(integer_scale_rect(1000, 400, 64, 32), Rect::new(116, 8, 768, 384));
+    assert_eq!(integer_scale_rect(960, 480, 128, 64), Rect::new(32, 16, 896, 448));
+    // Tiny windows still get the whole image
+    assert_eq!(integer_scale_rect(10, 10, 64, 32), Rect::new(-27, -11, 64, 32));
+}
+
+
+#[test]
+fn test_effective_cycles_per_frame() {
+    assert_eq!(effective_cycles_per_frame(10, false, 5), 10);
+    assert_eq!(effective_cycles_per_frame(10, true, 5), 50);
+    // Turbo stacks on top of the normal speed
+    assert_eq!(effective_cycles_per_frame(20, true, 5), 100);
+    assert_eq!(effective_cycles_per_frame(usize::MAX, true, 5), usize::MAX);
+}
+
+
+#[test]
+fn test_parse_command() {
+    assert_eq!(parse_command("step"), Ok(Command::Step(1)));
+    assert_eq!(parse_command("  step 10 "), Ok(Command::Step(10)));
+    assert_eq!(parse_command("regs"), Ok(Command::Regs));
+    assert_eq!(parse_command("mem 0x200"), Ok(Command::Mem(0x200)));
+    assert_eq!(parse_command("break 20A"), Ok(Command::Break(0x20A)));
+    assert_eq!(parse_command("continue"), Ok(Command::Continue));
+    assert_eq!(parse_command("disasm 0x300"), Ok(Command::Disasm(0x300)));
+    assert_eq!(parse_command("bmp shot.bmp"), Ok(Command::Bmp("shot.bmp".to_string())));
+
+    assert!(parse_command("step many").is_err());
+    assert!(parse_command("mem zz").is_err());
+    assert!(parse_command("regs 1").is_err());
+    assert!(parse_command("").is_err());
+}
+
+#[test]
+fn test_parse_breakpoints() {
+    assert_eq!(parse_breakpoints("0x2A0,0x2B4", 4096), Ok(vec![0x2A0, 0x2B4]));
+    assert_eq!(parse_breakpoints("200", 4096), Ok(vec![0x200]));
+    assert_eq!(parse_breakpoints(" 0x2a0 , 2b4 ", 4096), Ok(vec![0x2A0, 0x2B4]));
+    // The last instruction in the RAM is the last one that can be reached
+    assert_eq!(parse_breakpoints("0xFFE", 4096), Ok(vec![0xFFE]));
+    assert_eq!(parse_breakpoints("0x1000", 0x10000), Ok(vec![0x1000]));
+
+    // Odd addresses and addresses past the RAM can never be reached
+    assert!(parse_breakpoints("0x2A1", 4096).unwrap_err().contains("odd"));
+    assert!(parse_breakpoints("0x1000", 4096).unwrap_err().contains("outside"));
+    assert!(parse_breakpoints("0xFFF", 4096).is_err());
+
+    // Malformed lists
+    assert!(parse_breakpoints("", 4096).is_err());
+    assert!(parse_breakpoints("0x2A0,", 4096).is_err());
+    assert!(parse_breakpoints("0x2A0;0x2B4", 4096).is_err());
+    assert!(parse_breakpoints("0xZZ", 4096).is_err());
+    assert!(parse_breakpoints("0x10000", 0x10000).is_err());
+}
+
+/// A fresh, empty directory in the temporary folder, for config files.
+fn config_dir(name: &str) -> std::path::PathBuf {
+    let dir = std::env::temp_dir().join(name);
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn args(args: &[&str]) -> Vec<String> {
+    args.iter().map(|arg| arg.to_string()).collect()
+}
+
+#[test]
+fn test_load_config_missing_file() {
+    let dir = config_dir("chip8_test_config_missing");
+
+    assert_eq!(load_config(&args(&["chip8", "rom.ch8"]), &dir), Ok(Config::default()));
+
+    // Asking for a file that isn't there is a mistake, though
+    let missing = format!("--config={}", dir.join("nope.toml").display());
+    assert!(load_config(&args(&["chip8", &missing, "rom.ch8"]), &dir).is_err());
+}
+
+#[test]
+fn test_load_config_partial() {
+    let dir = config_dir("chip8_test_config_partial");
+    std::fs::write(
+        dir.join(CONFIG_FILE),
+        "speed = 20\n[theme]\nforeground = [255, 176, 0]\n[quirks]\nshift_uses_vy = true\n[keymap]\nU = 0xC\n",
+    ).unwrap();
+
+    let config = load_config(&args(&["chip8", "rom.ch8"]), &dir).unwrap();
+    assert_eq!(config.speed, 20);
+    assert_eq!(config.scale, Config::default().scale);
+    assert_eq!(config.theme.foreground, [255, 176, 0]);
+    assert_eq!(config.theme.background, Theme::default().background);
+    assert_eq!(config.quirks, Quirks { shift_uses_vy: true, ..Quirks::default() });
+    assert_eq!(config.key('u'), Some(Chip8Key::KC));
+    assert_eq!(config.key('q'), Some(Chip8Key::K4));
+}
+
+#[test]
+fn test_load_config_cli_override() {
+    let dir = config_dir("chip8_test_config_cli");
+    let path = dir.join("custom.toml");
+    std::fs::write(&path, "scale = 8\nspeed = 20\nturbo = 3\n").unwrap();
+    let flag = format!("--config={}", path.display());
+
+    let config = load_config(&args(&["chip8", &flag, "--speed=7", "rom.ch8"]), Path::new(".")).unwrap();
+    assert_eq!(config.scale, 8);
+    assert_eq!(config.speed, 7);
+    assert_eq!(config.turbo, 3);
+
+    assert!(load_config(&args(&["chip8", &flag, "--turbo=0"]), &dir).is_err());
+    assert!(load_config(&args(&["chip8", &flag, "--scale=big"]), &dir).is_err());
+
+    // A platform replaces the quirks of the file
+    std::fs::write(&path, "[quirks]\nsprite_wrapping = false\n").unwrap();
+    let config = load_config(&args(&["chip8", &flag, "--platform=schip"]), &dir).unwrap();
+    assert_eq!(config.quirks, Quirks::schip());
+    assert!(load_config(&args(&["chip8", &flag, "--platform=eti-660"]), &dir).is_err());
+}
+
+#[test]
+fn test_ghosting_decay() {
+    let mut brightness = [0, 255, 255, 100];
+
+    // Lit pixels are at full brightness, the rest fade over 3 frames
+    decay(&mut brightness, &[true, false, true, false], 3);
+    assert_eq!(brightness, [255, 170, 255, 15]);
+    decay(&mut brightness, &[false; 4], 3);
+    assert_eq!(brightness, [170, 85, 170, 0]);
+    decay(&mut brightness, &[false; 4], 3);
+    decay(&mut brightness, &[false; 4], 3);
+    assert_eq!(brightness, [0; 4]);
+
+    // Without ghosting, dark is dark at once
+    let mut brightness = [255, 255];
+    decay(&mut brightness, &[true, false], 0);
+    assert_eq!(brightness, [255, 0]);
+
+    // Halfway is halfway between the colors
+    let mut frame = [0; 12];
+    Theme::default().paint_faded(&[0, 255, 51], &mut frame);
+    assert_eq!(&frame[..4], &BACKGROUND);
+    assert_eq!(&frame[4..8], &FOREGROUND);
+    assert_eq!(&frame[8..], &[51, 51, 51, 255]);
+
+    let config = load_config(&args(&["chip8", "--ghosting=4"]), Path::new("/nonexistent")).unwrap();
+    assert_eq!(config.ghosting, 4);
+    assert!(load_config(&args(&["chip8", "--ghosting=100"]), Path::new("/nonexistent")).is_err());
+}
+
+#[test]
+fn test_theme_with_palette() {
+    let default = Theme::default();
+    assert_eq!(default.color(2), [255, 0, 0]);
+    assert_eq!(default.color(3), [0, 0, 255]);
+
+    // Two colors are the classic pair, and keep the XO-CHIP ones
+    let theme = default.with_palette("281400,ffb000").unwrap();
+    assert_eq!(theme.background, [0x28, 0x14, 0x00]);
+    assert_eq!(theme.foreground, [0xFF, 0xB0, 0x00]);
+    assert_eq!(theme.second_plane, default.second_plane);
+    assert_eq!(theme.both_planes, default.both_planes);
+
+    // Four are every color index, in order
+    let theme = default.with_palette("#000000, 111111, 222222, 333333").unwrap();
+    assert_eq!((0..4).map(|index| theme.color(index)).collect::<Vec<_>>(), [
+        [0x00; 3], [0x11; 3], [0x22; 3], [0x33; 3],
+    ]);
+
+    assert!(default.with_palette("000000").is_err());
+    assert!(default.with_palette("000000,ffffff,ff0000").is_err());
+    assert!(default.with_palette("000000,fffff").is_err());
+    assert!(default.with_palette("000000,gggggg").is_err());
+    assert!(default.with_palette("000000,ffffé").is_err());
+
+    // From the command line, over the file
+    let config = load_config(&args(&["chip8", "--palette=ffffff,000000", "rom.ch8"]), Path::new("/nonexistent")).unwrap();
+    assert_eq!(config.theme.background, [0xFF; 3]);
+    assert!(load_config(&args(&["chip8", "--palette=red,blue"]), Path::new("/nonexistent")).is_err());
+}
+
+#[test]
+fn test_log_levels() {
+    assert_eq!(parse_log_level("debug"), Ok(log::LevelFilter::Debug));
+    assert_eq!(parse_log_level("WARN"), Ok(log::LevelFilter::Warn));
+    assert_eq!(parse_log_level("off"), Ok(log::LevelFilter::Off));
+    assert!(parse_log_level("loud").is_err());
+
+    // A program running into its data is worth a look, not a crash report
+    assert_eq!(error_level(&Chip8Error::UnknownOpcode(0xFFFF)), log::Level::Warn);
+    assert_eq!(error_level(&Chip8Error::StackOverflow), log::Level::Error);
+
+    assert_eq!(trace_line(0x200, 0x6A2F), "0x200: 6a2f  LD VA, 0x2f");
+}
+
+#[test]
+fn test_frame_limiter() {
+    let start = Instant::now();
+    let ms = Duration::from_millis;
+    let mut limiter = FrameLimiter::new(ms(16), start);
+
+    // A quick frame sleeps for the rest of it
+    assert_eq!(limiter.wait(start + ms(4)), ms(12));
+    // The next one is due 16 ms after the last was, whenever we woke up
+    assert_eq!(limiter.wait(start + ms(20)), ms(12));
+    // A slow frame doesn't sleep, and the next one makes up for it
+    assert_eq!(limiter.wait(start + ms(50)), ms(0));
+    assert_eq!(limiter.wait(start + ms(60)), ms(4));
+
+    // Far behind, we start over instead of running frames back to back
+    assert_eq!(limiter.wait(start + ms(500)), ms(0));
+    assert_eq!(limiter.wait(start + ms(510)), ms(6));
+}
+
+#[test]
+fn test_config_validation() {
+    assert!(Config::from_toml("scale = 0").is_err());
+    assert!(Config::from_toml("speed = 100000").is_err());
+    assert!(Config::from_toml("[keymap]\nu = 16").is_err());
+    assert!(Config::from_toml("[keymap]\nup = 1").is_err());
+    assert!(Config::from_toml("colour = 1").is_err());
+    assert_eq!(Config::from_toml(""), Ok(Config::default()));
+}
+
+#[test]
+fn test_keymap_is_cosmac_layout() {
+    // The COSMAC VIP keypad, row by row, under the left of a QWERTY keyboard
+    let layout = [
+        (Keycode::Num1, Chip8Key::K1), (Keycode::Num2, Chip8Key::K2),
+        (Keycode::Num3, Chip8Key::K3), (Keycode::Num4, Chip8Key::KC),
+        (Keycode::Q, Chip8Key::K4), (Keycode::W, Chip8Key::K5),
+        (Keycode::E, Chip8Key::K6), (Keycode::R, Chip8Key::KD),
+        (Keycode::A, Chip8Key::K7), (Keycode::S, Chip8Key::K8),
+        (Keycode::D, Chip8Key::K9), (Keycode::F, Chip8Key::KE),
+        (Keycode::Z, Chip8Key::KA), (Keycode::X, Chip8Key::K0),
+        (Keycode::C, Chip8Key::KB), (Keycode::V, Chip8Key::KF),
+    ];
+
+    let config = Config::default();
+    for (keycode, chip_key) in layout {
+        assert_eq!(key_to_chip8_key(keycode, &config), Some(chip_key), "{:?}", keycode);
+    }
+
+    // Every keypad key is reachable, once
+    let mut seen: Vec<usize> = layout.iter().map(|(_, key)| key.index()).collect();
+    seen.sort();
+    assert_eq!(seen, (0..16).collect::<Vec<_>>());
+}
+
+#[test]
+fn test_key_labels() {
+    // The keypad, by index, as the usual layout labels it
+    let labels = [
+        "X", "1", "2", "3", "Q", "W", "E", "A",
+        "S", "D", "Z", "C", "4", "R", "F", "V",
+    ];
+    assert_eq!(default_key_labels(), labels);
+    assert_eq!(Config::default().key_labels(), labels);
+    assert_eq!(chip8_key_to_display_name(Chip8Key::KC), "4");
+
+    for (i, label) in labels.iter().enumerate() {
+        let key = Chip8Key::from_index(i as u8).unwrap();
+        assert_eq!(char_to_chip8_key(chip8_key_to_char(key)), Some(key), "{}", label);
+    }
+
+    // The keymap adds keys, and takes the ones it moves elsewhere
+    let config = Config::from_toml("[keymap]\nu = 0xC\nq = 0x1\nw = 0x5").unwrap();
+    let labels = config.key_labels();
+    assert_eq!(labels[0xC], "4/U");
+    assert_eq!(labels[0x1], "1/Q");
+    assert_eq!(labels[0x4], "");
+    assert_eq!(labels[0x5], "W");
+}
+
+#[test]
+fn test_tone_generators() {
+    let mut square = Square;
+    assert_eq!(square.sample(0.0), 1.0);
+    assert_eq!(square.sample(0.25), 1.0);
+    assert_eq!(square.sample(0.5), -1.0);
+    assert_eq!(square.sample(0.75), -1.0);
+
+    let mut sine = Sine;
+    assert!(sine.sample(0.0).abs() < 1e-6);
+    assert!((sine.sample(0.25) - 1.0).abs() < 1e-6);
+    assert!(sine.sample(0.5).abs() < 1e-6);
+    assert!((sine.sample(0.75) + 1.0).abs() < 1e-6);
+
+    assert_eq!(Waveform::parse("sine"), Ok(Waveform::Sine));
+    assert_eq!(Waveform::parse("square"), Ok(Waveform::Square));
+    assert!(Waveform::parse("triangle").is_err());
+    assert_eq!(Waveform::Sine.generator().sample(0.25), sine.sample(0.25));
+}
+
+#[test]
+fn test_pattern_generator() {
+    // 8 bits high, 8 bits low, and so on, with the very last bit set
+    let mut bits = [0xFF, 0x00].repeat(8);
+    bits[15] = 0x01;
+    let mut pattern = Pattern(bits.try_into().unwrap());
+    assert_eq!(pattern.sample(0.0), 1.0);
+    assert_eq!(pattern.sample(7.0 / 128.0), 1.0);
+    assert_eq!(pattern.sample(8.0 / 128.0), -1.0);
+    assert_eq!(pattern.sample(16.0 / 128.0), 1.0);
+    assert_eq!(pattern.sample(126.0 / 128.0), -1.0);
+    // The last bit, and the very end of the period
+    assert_eq!(pattern.sample(127.0 / 128.0), 1.0);
+    assert_eq!(pattern.sample(0.999_999), 1.0);
+}
+
+#[test]
+fn test_dump_state() {
+    let dir = config_dir("chip8_test_dump_state");
+    let path = dir.join("final.state");
+
+    let mut processor = Chip8Processor::new();
+    processor.load_rom(&[0x60, 0x2A, 0xA2, 0x10, 0xD0, 0x05, 0x12, 0x06]).unwrap();
+    processor.cycle_n(4).unwrap();
+    dump_state(&processor, &path).unwrap();
+
+    let mut loaded = Chip8Processor::new();
+    loaded.load_state_binary(&std::fs::read(&path).unwrap()).unwrap();
+    assert_eq!(loaded.snapshot(), processor.snapshot());
+
+    // A directory that isn't there is an error, not a panic
+    assert!(dump_state(&processor, &dir.join("nope").join("final.state")).is_err());
+}
+
+#[test]
+fn test_encode_bmp() {
+    let mut pixels = [false; 64 * 32];
+    pixels[0] = true; // Top left
+    pixels[64 * 31 + 63] = true; // Bottom right
+    pixels[64 * 31 + 9] = true;
+
+    let bmp = encode_bmp(&pixels, 64, 32);
+    // Headers, palette, and 32 rows of 8 bytes
+    assert_eq!(bmp.len(), 14 + 40 + 8 + 32 * 8);
+    assert_eq!(&bmp[..2], b"BM");
+    assert_eq!(bmp[2..6], (bmp.len() as u32).to_le_bytes());
+    assert_eq!(bmp[10..14], 62u32.to_le_bytes());
+    assert_eq!(bmp[18..22], 64u32.to_le_bytes());
+    assert_eq!(bmp[22..26], 32u32.to_le_bytes());
+    assert_eq!(bmp[28..30], 1u16.to_le_bytes());
+
+    // The bottom row comes first
+    let rows: Vec<_> = bmp[62..].chunks(8).collect();
+    assert_eq!(rows[0], [0x00, 0x40, 0, 0, 0, 0, 0, 0x01]);
+    assert_eq!(rows[31], [0x80, 0, 0, 0, 0, 0, 0, 0]);
+    assert!(rows[1..31].iter().all(|row| row.iter().all(|byte| *byte == 0)));
+
+    // Rows are padded to 4 bytes
+    assert_eq!(encode_bmp(&[true; 12 * 2], 12, 2).len(), 62 + 2 * 4);
+}
+
+#[cfg(feature = "remote")]
+#[test]
+fn test_remote_updates() {
+    use chip8_interface::remote::*;
+
+    assert_eq!(parse_update(0x85), Some(KeyUpdate { key: 0x5, pressed: true }));
+    assert_eq!(parse_update(0x0F), Some(KeyUpdate { key: 0xF, pressed: false }));
+    assert_eq!(parse_update(b'a'), None);
+
+    let mut keys = [false; 16];
+    apply_updates(&mut keys, &[0x81, 0x8C, b'\n', 0x01]);
+    assert!(!keys[0x1]);
+    assert!(keys[0xC]);
+    assert_eq!(keys.iter().filter(|key| **key).count(), 1);
+}